@@ -0,0 +1,281 @@
+extern crate gtk;
+use gtk::IsA;
+
+use gtk::{Dialog, Label, Button, Grid, ComboBoxText, CheckButton, ProgressBar, TextView, ScrolledWindow, FileChooserButton, FileChooserDialog, FileChooserAction, ResponseType, PolicyType};
+use gtk::{DialogExt, GridExt, WidgetExt, GtkWindowExt, ContainerExt, ButtonExt, LabelExt, ComboBoxExt, ComboBoxTextExt, ToggleButtonExt, ProgressBarExt, TextViewExt, TextBufferExt, FileChooserExt, FileChooserButtonExt, ScrolledWindowExt, StyleContextExt};
+use glib::translate::ToGlib;
+use gio::Settings;
+use gio::SettingsExt;
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use srtnr::core::{ShortenRequest, Shortener};
+use srtnr::core::batch::{self, BatchProgress, BatchReport, Format, Table};
+
+use super::{provider_label_text, settings_cleaner};
+use super::notifier::Notifier;
+use super::worker::Worker;
+
+pub struct BatchDialogUi {
+    pub batch_dialog: Dialog,
+}
+
+impl BatchDialogUi {
+    pub fn new<P>(parent: &P, shortener: Arc<Shortener>, provider: Option<String>, notifier: Notifier) -> BatchDialogUi
+    where P: IsA<gtk::Window>, {
+    let batch_dialog = Dialog::new ();
+    GtkWindowExt::set_title (&batch_dialog, "Shorten a List of Links");
+    GtkWindowExt::set_modal (&batch_dialog, true);
+    GtkWindowExt::set_transient_for (&batch_dialog, parent);
+    GtkWindowExt::set_default_size (&batch_dialog, 550, 450);
+
+    let content_grid = Grid::new ();
+    GridExt::set_row_spacing (&content_grid, 12);
+    GridExt::set_column_spacing (&content_grid, 6);
+    GridExt::set_column_homogeneous (&content_grid, true);
+    WidgetExt::set_margin_top (&content_grid, 20);
+    WidgetExt::set_margin_start (&content_grid, 10);
+    WidgetExt::set_margin_end (&content_grid, 10);
+    WidgetExt::set_margin_bottom (&content_grid, 10);
+
+    let file_label = Label::new_with_mnemonic (Some ("File:"));
+    WidgetExt::set_halign (&file_label, gtk::Align::End);
+    let file_button = FileChooserButton::new ("Choose a CSV, TSV or text file", FileChooserAction::Open);
+    GridExt::attach (&content_grid, &file_label, 0, 0, 1, 1);
+    GridExt::attach (&content_grid, &file_button, 1, 0, 3, 1);
+
+    let column_label = Label::new_with_mnemonic (Some ("URL column:"));
+    WidgetExt::set_halign (&column_label, gtk::Align::End);
+    let column_combobox = ComboBoxText::new ();
+    GridExt::attach (&content_grid, &column_label, 0, 1, 1, 1);
+    GridExt::attach (&content_grid, &column_combobox, 1, 1, 3, 1);
+
+    let provider_label = Label::new_with_mnemonic (Some ("Provider:"));
+    WidgetExt::set_halign (&provider_label, gtk::Align::End);
+    let provider_combobox = ComboBoxText::new ();
+    for provider in shortener.providers ().iter () {
+        ComboBoxTextExt::append (&provider_combobox, Some (provider.id ()), &provider_label_text (&**provider));
+    }
+    match provider {
+        Some (ref provider) => { ComboBoxExt::set_active_id (&provider_combobox, Some (provider.as_str ())); },
+        None => ComboBoxExt::set_active (&provider_combobox, 0),
+    }
+    GridExt::attach (&content_grid, &provider_label, 0, 2, 1, 1);
+    GridExt::attach (&content_grid, &provider_combobox, 1, 2, 3, 1);
+
+    let fallback_check = CheckButton::new_with_label ("Fall back to other providers");
+    GridExt::attach (&content_grid, &fallback_check, 1, 3, 3, 1);
+
+    let progress_bar = ProgressBar::new ();
+    ProgressBarExt::set_show_text (&progress_bar, true);
+    ProgressBarExt::set_text (&progress_bar, "");
+    GridExt::attach (&content_grid, &progress_bar, 0, 4, 4, 1);
+
+    //rows which could not be shortened
+    let failures_view = TextView::new ();
+    TextViewExt::set_editable (&failures_view, false);
+    TextViewExt::set_cursor_visible (&failures_view, false);
+    let failures_window = ScrolledWindow::new (None, None);
+    ScrolledWindowExt::set_policy (&failures_window, PolicyType::Automatic, PolicyType::Automatic);
+    ScrolledWindowExt::set_min_content_height (&failures_window, 120);
+    WidgetExt::set_vexpand (&failures_window, true);
+    ContainerExt::add (&failures_window, &failures_view);
+    GridExt::attach (&content_grid, &failures_window, 0, 5, 4, 1);
+
+    let status_label = Label::new ("");
+    LabelExt::set_line_wrap (&status_label, true);
+    LabelExt::set_selectable (&status_label, true);
+    WidgetExt::set_halign (&status_label, gtk::Align::Start);
+    GridExt::attach (&content_grid, &status_label, 0, 6, 4, 1);
+
+    DialogExt::get_content_area (&batch_dialog).add (&content_grid);
+
+    let close_button = Button::new_with_label ("Close");
+    let cancel_button = Button::new_with_label ("Cancel");
+    let start_button = Button::new_with_label ("Shorten All");
+    WidgetExt::get_style_context (&start_button).map (|c| c.add_class("suggested-action"));
+    WidgetExt::set_sensitive (&start_button, false);
+    WidgetExt::set_sensitive (&cancel_button, false);
+    DialogExt::add_action_widget (&batch_dialog, &close_button, 0);
+    DialogExt::add_action_widget (&batch_dialog, &cancel_button, 1);
+    DialogExt::add_action_widget (&batch_dialog, &start_button, 2);
+    DialogExt::get_content_area (&batch_dialog).show_all ();
+
+    let input: Rc<RefCell<Option<(PathBuf, Table)>>> = Rc::new (RefCell::new (None));
+    let worker = Worker::new ();
+    {
+        let start_button = start_button.clone ();
+        let cancel_button = cancel_button.clone ();
+        let status_label = status_label.clone ();
+        worker.on_panic (move |message| {
+            WidgetExt::set_sensitive (&cancel_button, false);
+            WidgetExt::set_sensitive (&start_button, true);
+            LabelExt::set_text (&status_label, &format!("Something went wrong, nothing was written: {}", message));
+        });
+    }
+
+    //load the file and offer its columns
+    let input_clone = input.clone ();
+    let column_combobox_clone = column_combobox.clone ();
+    let start_button_clone = start_button.clone ();
+    let status_label_clone = status_label.clone ();
+    file_button.connect_file_set (move |file_button| {
+        ComboBoxTextExt::remove_all (&column_combobox_clone);
+        *input_clone.borrow_mut () = None;
+        WidgetExt::set_sensitive (&start_button_clone, false);
+
+        let path = match FileChooserExt::get_filename (file_button) {
+            Some (path) => path,
+            None => return,
+        };
+        let text = match fs::read_to_string (&path) {
+            Ok (text) => text,
+            Err (error) => {
+                LabelExt::set_text (&status_label_clone, &format!("Could not read {}: {}", path.display (), error));
+                return;
+            },
+        };
+
+        let format = Format::from_path (&path).unwrap_or_else (|| Format::sniff (&text));
+        let table = Table::parse (&text, format);
+        for (index, header) in table.headers.iter ().enumerate () {
+            ComboBoxTextExt::append (&column_combobox_clone, Some (index.to_string ().as_str ()), header);
+        }
+        ComboBoxExt::set_active (&column_combobox_clone, table.guess_url_column ().map (|column| column as i32).unwrap_or (0));
+        LabelExt::set_text (&status_label_clone, &format!("{} rows", table.rows.len ()));
+        WidgetExt::set_sensitive (&start_button_clone, !table.rows.is_empty ());
+        *input_clone.borrow_mut () = Some ((path, table));
+    });
+
+    //pick the output file and run
+    let batch_dialog_clone = batch_dialog.clone ();
+    let worker_clone = worker.clone ();
+    let cancel_button_clone = cancel_button.clone ();
+    let status_label_clone = status_label.clone ();
+    start_button.connect_clicked (move |start_button| {
+        let (input_path, table) = match *input.borrow () {
+            Some ((ref path, ref table)) => (path.clone (), table.clone ()),
+            None => return,
+        };
+        let column = match ComboBoxExt::get_active (&column_combobox) {
+            column if column >= 0 => column as usize,
+            _ => return,
+        };
+        let provider = match ComboBoxExt::get_active_id (&provider_combobox) {
+            Some (provider) => provider,
+            None => return,
+        };
+
+        let output_path = match choose_output (&batch_dialog_clone, &input_path, table.format) {
+            Some (path) => path,
+            None => return,
+        };
+
+        let mut template = ShortenRequest::new ("", provider.as_str ());
+        let settings = Settings::new ("com.github.arshubham.srtnr");
+        template.normalize.probe = SettingsExt::get_boolean (&settings, "probe-scheme");
+        template.cleaner = settings_cleaner (&settings, &[]);
+        if ToggleButtonExt::get_active (&fallback_check) {
+            template.fallbacks = shortener.providers ().iter ()
+                .filter (|other| other.id () != provider && other.is_configured ())
+                .map (|other| other.id ().to_owned ())
+                .collect ();
+        }
+
+        if let Some (buffer) = TextViewExt::get_buffer (&failures_view) {
+            TextBufferExt::set_text (&buffer, "");
+        }
+        ProgressBarExt::set_fraction (&progress_bar, 0.0);
+        LabelExt::set_text (&status_label_clone, "");
+        WidgetExt::set_sensitive (start_button, false);
+        WidgetExt::set_sensitive (&cancel_button_clone, true);
+
+        let shortener = shortener.clone ();
+        let progress_bar = progress_bar.clone ();
+        let failures_view = failures_view.clone ();
+        let status_label = status_label_clone.clone ();
+        let start_button = start_button.clone ();
+        let cancel_button = cancel_button_clone.clone ();
+        let progress_notifier = notifier.clone ();
+        let notifier = notifier.clone ();
+
+        worker_clone.spawn_with_progress (move |progress, cancel| {
+            template.cancel = cancel;
+            batch::shorten_table (&shortener, &table, column, &template, |step| progress.send (step.clone ()))
+        }, move |progress: BatchProgress| {
+            ProgressBarExt::set_fraction (&progress_bar, progress.done as f64 / progress.total.max (1) as f64);
+            ProgressBarExt::set_text (&progress_bar, format!("{} / {}", progress.done, progress.total).as_str ());
+            if let Some (error) = progress.error {
+                if !progress_notifier.group_batches () {
+                    progress_notifier.message (&format!("batch-row-{}", progress.done), &format!("Row {} was not shortened", progress.done), &error);
+                }
+                if let Some (buffer) = TextViewExt::get_buffer (&failures_view) {
+                    let mut end = TextBufferExt::get_end_iter (&buffer);
+                    TextBufferExt::insert (&buffer, &mut end, &format!("Row {}: {}\n", progress.done, error));
+                }
+            }
+        }, move |report: BatchReport| {
+            WidgetExt::set_sensitive (&start_button, true);
+            WidgetExt::set_sensitive (&cancel_button, false);
+            let status = match fs::write (&output_path, report.table.to_text ()) {
+                Ok (()) => format!("{} of {} links shortened, written to {}",
+                                   report.succeeded, report.table.rows.len (), output_path.display ()),
+                Err (error) => format!("Could not write {}: {}", output_path.display (), error),
+            };
+            LabelExt::set_text (&status_label, &status);
+            notifier.message ("batch", "Batch finished", &status);
+        });
+    });
+
+    let start_button_clone = start_button.clone ();
+    let status_label_clone = status_label.clone ();
+    let worker_clone = worker.clone ();
+    cancel_button.connect_clicked (move |cancel_button| {
+        worker_clone.cancel ();
+        WidgetExt::set_sensitive (cancel_button, false);
+        WidgetExt::set_sensitive (&start_button_clone, true);
+        LabelExt::set_text (&status_label_clone, "Cancelled, nothing was written");
+    });
+
+    let batch_dialog_clone = batch_dialog.clone ();
+    close_button.connect_clicked (move |_| {
+        worker.cancel ();
+        DialogExt::emit_close (&batch_dialog_clone);
+    });
+
+    BatchDialogUi {
+             batch_dialog,
+        }
+    }
+
+    pub fn run(&self) {
+        WidgetExt::show_now (&self.batch_dialog);
+    }
+
+}
+
+/// Asks where to save the result, suggesting `links.short.csv` for `links.csv`.
+fn choose_output (parent: &Dialog, input: &PathBuf, format: Format) -> Option<PathBuf> {
+    let dialog = FileChooserDialog::new (Some ("Save Shortened Links"), Some (parent), FileChooserAction::Save);
+    DialogExt::add_button (&dialog, "Cancel", ResponseType::Cancel.to_glib ());
+    DialogExt::add_button (&dialog, "Save", ResponseType::Accept.to_glib ());
+    FileChooserExt::set_do_overwrite_confirmation (&dialog, true);
+
+    let stem = input.file_stem ().and_then (|stem| stem.to_str ()).unwrap_or ("links");
+    let extension = if format == Format::Tsv { "tsv" } else { "csv" };
+    if let Some (dir) = input.parent () {
+        FileChooserExt::set_current_folder (&dialog, dir);
+    }
+    FileChooserExt::set_current_name (&dialog, format!("{}.short.{}", stem, extension));
+
+    let path = if DialogExt::run (&dialog) == ResponseType::Accept.to_glib () {
+        FileChooserExt::get_filename (&dialog)
+    } else {
+        None
+    };
+    WidgetExt::destroy (&dialog);
+    path
+}
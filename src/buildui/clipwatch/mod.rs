@@ -0,0 +1,215 @@
+extern crate gdk;
+extern crate glib;
+extern crate gtk;
+
+use gtk::prelude::*;
+use gtk::{Application, Clipboard, ClipboardExt, Continue};
+use gio::{Notification, NotificationExt, Settings, SettingsExt};
+use glib::{ObjectExt, SignalHandlerId, ToVariant};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use srtnr::core::{CopiedUrlFilter, Shortener};
+
+use super::notifier::{self, Notifier};
+use super::settings_request;
+use super::worker::Worker;
+
+/// Copying often comes as a burst of owner changes, the selection is read
+/// once it did not change for this long, in ms.
+const SETTLE_DELAY: u32 = 300;
+/// Application action behind the Shorten button of the notification.
+const SHORTEN_ACTION: &str = "shorten-copied";
+/// A newer offer replaces the one still on screen.
+const NOTIFICATION_ID: &str = "clipboard";
+
+thread_local! {
+    /// The selections being followed, by key, with whether it is PRIMARY.
+    /// Their owner-change handlers only get to pass the key around.
+    static FOLLOWED: RefCell<HashMap<u64, (Watch, Clipboard, bool)>> = RefCell::new (HashMap::new ());
+    static NEXT_KEY: Cell<u64> = Cell::new (0);
+}
+
+/// Watches CLIPBOARD, and PRIMARY when asked to, for copied long links.
+/// Depending on `clipboard-watch` they are offered in a notification or
+/// replaced by their short link right away. Nothing is watched while it
+/// is "off". One per application, whatever the number of windows.
+pub struct ClipboardWatcher {
+    watch: Watch,
+}
+
+impl ClipboardWatcher {
+    pub fn new (app: &Application, settings: &Settings, shortener: Arc<Shortener>, notifier: Notifier) -> ClipboardWatcher {
+        let clipboard = Clipboard::get (&gdk::Atom::intern ("CLIPBOARD"));
+        let min_length = SettingsExt::get_int (settings, "clipboard-min-length").max (0) as usize;
+        let watch = Watch {
+            notifier,
+            clipboard: clipboard.clone (),
+            settings: settings.clone (),
+            shortener,
+            filter: Rc::new (RefCell::new (CopiedUrlFilter::new (min_length))),
+            worker: Worker::new (),
+            handlers: Rc::new (RefCell::new (Vec::new ())),
+        };
+
+        // The text already there was not copied while watching.
+        if let Some (text) = ClipboardExt::wait_for_text (&clipboard) {
+            watch.filter.borrow_mut ().candidate (&text);
+        }
+
+        let watch_clone = watch.clone ();
+        notifier::add_string_action (app, SHORTEN_ACTION, move |url| watch_clone.shorten (url.to_owned ()));
+
+        watch.update ();
+        let watch_clone = watch.clone ();
+        SettingsExt::connect_changed (settings, move |_, key| {
+            if key == "clipboard-watch" || key == "clipboard-primary" {
+                watch_clone.update ();
+            }
+        });
+
+        ClipboardWatcher { watch }
+    }
+
+    /// A short link the window put on the clipboard, so it is not offered
+    /// for shortening again.
+    pub fn remember_produced (&self, url: &str) {
+        self.watch.filter.borrow_mut ().remember_produced (url);
+    }
+}
+
+#[derive(Clone)]
+struct Watch {
+    notifier: Notifier,
+    /// Where short links go, also when the long one was only selected.
+    clipboard: Clipboard,
+    settings: Settings,
+    shortener: Arc<Shortener>,
+    filter: Rc<RefCell<CopiedUrlFilter>>,
+    worker: Worker,
+    /// The owner-change handlers of the followed selections, by key.
+    handlers: Rc<RefCell<Vec<(u64, Clipboard, SignalHandlerId)>>>,
+}
+
+impl Watch {
+    /// Follows the selections the settings ask for, none while "off".
+    fn update (&self) {
+        for (key, selection, handler) in self.handlers.borrow_mut ().drain (..) {
+            ObjectExt::disconnect (&selection, handler);
+            FOLLOWED.with (|followed| followed.borrow_mut ().remove (&key));
+        }
+
+        if SettingsExt::get_string (&self.settings, "clipboard-watch").unwrap_or_default () == "off" {
+            // A link still being shortened is not copied anymore.
+            self.worker.cancel ();
+            return;
+        }
+        self.follow (&self.clipboard, false);
+        if SettingsExt::get_boolean (&self.settings, "clipboard-primary") {
+            self.follow (&Clipboard::get (&gdk::Atom::intern ("PRIMARY")), true);
+        }
+    }
+
+    /// Checks `selection` whenever it got new content and stopped changing.
+    fn follow (&self, selection: &Clipboard, primary: bool) {
+        let key = NEXT_KEY.with (|next| {
+            next.set (next.get () + 1);
+            next.get ()
+        });
+
+        // gtk does not bind "owner-change", and handlers connected by name
+        // must be Send. So each change only starts a timeout, and the one
+        // of the last change of a burst has the main loop check the key.
+        let changes = Arc::new (AtomicUsize::new (0));
+        let connected = ObjectExt::connect (selection, "owner-change", false, move |_| {
+            let count = changes.fetch_add (1, Ordering::SeqCst) + 1;
+            let changes = changes.clone ();
+            glib::timeout_add (SETTLE_DELAY, move || {
+                if changes.load (Ordering::SeqCst) == count {
+                    settled (key);
+                }
+                Continue (false)
+            });
+            None
+        });
+
+        if let Ok (handler) = connected {
+            FOLLOWED.with (|followed| followed.borrow_mut ().insert (key, (self.clone (), selection.clone (), primary)));
+            self.handlers.borrow_mut ().push ((key, selection.clone (), handler));
+        }
+    }
+
+    /// The mode is read on every change, so the preferences apply at once.
+    fn check (&self, selection: &Clipboard, primary: bool) {
+        let mode = SettingsExt::get_string (&self.settings, "clipboard-watch").unwrap_or_default ();
+        if mode == "off" || (primary && !SettingsExt::get_boolean (&self.settings, "clipboard-primary")) {
+            return;
+        }
+        let text = match ClipboardExt::wait_for_text (selection) {
+            Some (text) => text,
+            None => return,
+        };
+
+        let url = {
+            let mut filter = self.filter.borrow_mut ();
+            filter.min_length = SettingsExt::get_int (&self.settings, "clipboard-min-length").max (0) as usize;
+            match filter.candidate (&text) {
+                Some (url) => url,
+                None => return,
+            }
+        };
+
+        if mode == "auto" {
+            self.shorten (url);
+        } else {
+            let notification = Notification::new ("Shorten the copied link?");
+            notification.set_body (Some (url.as_str ()));
+            notification.add_button_with_target_value ("Shorten", &notifier::action_name (SHORTEN_ACTION), Some (&url.to_variant ()));
+            // Asked for with the "offer" mode, so sent whatever the notification settings.
+            self.notifier.send_always (NOTIFICATION_ID, &notification);
+        }
+    }
+
+    /// Shortens `url` like the window would and copies the short link,
+    /// unless a newer copy or turning the watch off cancelled it.
+    fn shorten (&self, url: String) {
+        let mut request = match settings_request (&self.shortener, &self.settings, &url, "") {
+            Some (request) => request,
+            None => {
+                self.notifier.message (NOTIFICATION_ID, "The copied link was not shortened", "None of the automatic providers can be used, check the preferences");
+                return;
+            },
+        };
+
+        let watch = self.clone ();
+        let shortener = self.shortener.clone ();
+        self.worker.spawn (move |cancel| {
+            request.cancel = cancel.clone ();
+            (shortener.shorten (&request), cancel)
+        }, move |(outcome, cancel)| {
+            if cancel.is_cancelled () {
+                return;
+            }
+            match outcome {
+                Ok (outcome) => {
+                    watch.filter.borrow_mut ().remember_produced (&outcome.short_url);
+                    ClipboardExt::set_text (&watch.clipboard, &outcome.short_url);
+                    watch.notifier.shortened (&outcome.short_url);
+                },
+                Err (error) => watch.notifier.message (NOTIFICATION_ID, "The copied link was not shortened", &error.to_string ()),
+            }
+        });
+    }
+}
+
+/// Runs on the main loop, where `FOLLOWED` lives. Does nothing once the
+/// selection is no longer followed.
+fn settled (key: u64) {
+    let followed = FOLLOWED.with (|followed| followed.borrow ().get (&key).cloned ());
+    if let Some ((watch, selection, primary)) = followed {
+        watch.check (&selection, primary);
+    }
+}
@@ -0,0 +1,155 @@
+extern crate gtk;
+
+use gtk::Application;
+use gio::Settings;
+use glib_sys::{self, GVariant};
+
+use srtnr::core::{self, ExpandOptions};
+
+use super::super::{settings_request, Shared};
+use super::super::notifier::Notifier;
+use super::{child, copy_lasting, new_string, run_held, string, string_dictionary, tuple, ArrayBuilder, CallError, Invocation, MethodHandler};
+
+const INTERFACE_XML: &str = include_str!("../../../../data/org.kde.krunner1.xml");
+const INTERFACE_NAME: &str = "org.kde.krunner1";
+/// As in data/com.github.arshubham.srtnr.krunner.desktop.
+const OBJECT_PATH: &str = "/com/github/arshubham/srtnr/KRunner";
+const ICON: &str = "com.github.arshubham.srtnr";
+/// `Plasma::QueryMatch::ExactMatch`, the keyword was typed out.
+const EXACT_MATCH: i32 = 100;
+/// Results of a run replace each other.
+const NOTIFICATION_ID: &str = "krunner";
+
+/// Exports the KRunner runner: "short <url>" shortens with the default
+/// provider and "expand <url>" follows the redirects of a short link, the
+/// result going to the clipboard either way.
+pub fn export (app: &Application, shared: &Shared) -> Result<(), String> {
+    let runner = Runner {
+        app: app.clone (),
+        settings: Settings::new ("com.github.arshubham.srtnr"),
+        shared: shared.clone (),
+    };
+    super::register (app, INTERFACE_XML, INTERFACE_NAME, OBJECT_PATH, Box::new (runner))
+}
+
+#[derive(Clone, Copy)]
+enum Command {
+    Short,
+    Expand,
+}
+
+impl Command {
+    fn keyword (self) -> &'static str {
+        match self {
+            Command::Short => "short",
+            Command::Expand => "expand",
+        }
+    }
+
+    /// The command and the link of a query or match id, `None` when it is
+    /// not one for this runner.
+    fn parse (text: &str) -> Option<(Command, &str)> {
+        let text = text.trim ();
+        let split = text.find (char::is_whitespace)?;
+        let url = text[split..].trim ();
+        let command = match &text[..split] {
+            "short" => Command::Short,
+            "expand" => Command::Expand,
+            _ => return None,
+        };
+        if url.is_empty () || url.contains (char::is_whitespace) {
+            return None;
+        }
+        Some ((command, url))
+    }
+}
+
+struct Runner {
+    app: Application,
+    settings: Settings,
+    shared: Shared,
+}
+
+impl MethodHandler for Runner {
+    unsafe fn call (&self, method: &str, parameters: *mut GVariant, invocation: Invocation) {
+        match method {
+            "Actions" => invocation.return_value (tuple (vec![ArrayBuilder::new ("a(sss)").end ()])),
+            "Match" => self.matches (invocation, &child (parameters, 0, |value| string (value))),
+            "Run" => {
+                let id = child (parameters, 0, |value| string (value));
+                self.run (invocation, &id);
+            },
+            "Teardown" => invocation.return_empty (),
+            method => invocation.return_error (CallError::unknown_method (method)),
+        }
+    }
+}
+
+impl Runner {
+    /// Only looks at the query, the network is left alone until a match
+    /// is chosen as KRunner asks again on every key.
+    fn matches (&self, invocation: Invocation, query: &str) {
+        let matches = unsafe { ArrayBuilder::new ("a(sssida{sv})") };
+        if let Some ((command, url)) = Command::parse (query) {
+            let (text, subtext) = match command {
+                Command::Short => (format!("Shorten {}", url), "Copy the short link".to_owned ()),
+                Command::Expand => (format!("Expand {}", url), "Copy where the redirects lead".to_owned ()),
+            };
+            // The id is the query again, so Run needs no state.
+            let id = format!("{} {}", command.keyword (), url);
+            unsafe {
+                matches.add_value (tuple (vec![
+                    new_string (&id),
+                    new_string (&text),
+                    new_string (ICON),
+                    glib_sys::g_variant_new_int32 (EXACT_MATCH),
+                    glib_sys::g_variant_new_double (1.0),
+                    string_dictionary (&[("subtext", subtext.as_str ())]),
+                ]));
+            }
+        }
+        unsafe {
+            invocation.return_value (tuple (vec![matches.end ()]));
+        }
+    }
+
+    /// Answered right away, KRunner closes and the outcome is notified.
+    fn run (&self, invocation: Invocation, id: &str) {
+        let (command, url) = match Command::parse (id) {
+            Some ((command, url)) => (command, url.to_owned ()),
+            None => return invocation.return_error (CallError::invalid_args (format!("Not a match of Srtnr: {}", id))),
+        };
+        invocation.return_empty ();
+
+        let notifier = Notifier::new (&self.app, &self.settings);
+        let shortener = self.shared.shortener.clone ();
+        match command {
+            Command::Short => {
+                let request = match settings_request (&shortener, &self.settings, &url, "") {
+                    Some (request) => request,
+                    None => return notifier.message (NOTIFICATION_ID, "The link was not shortened", "None of the automatic providers can be used, check the preferences"),
+                };
+                run_held (&self.app, move || shortener.shorten (&request), move |outcome| {
+                    match outcome {
+                        Ok (outcome) => {
+                            copy_lasting (&outcome.short_url);
+                            notifier.message (NOTIFICATION_ID, "Short Url Copied into clipboard.", &outcome.short_url);
+                        },
+                        Err (error) => notifier.message (NOTIFICATION_ID, "The link was not shortened", &error.to_string ()),
+                    }
+                });
+            },
+            Command::Expand => {
+                run_held (&self.app, move || core::expand_chain (&url, shortener.http (), &ExpandOptions::default ()), move |expansion| {
+                    match expansion {
+                        Ok (expansion) => {
+                            copy_lasting (expansion.destination ());
+                            notifier.message (NOTIFICATION_ID, "Expanded Url Copied into clipboard.", expansion.destination ());
+                        },
+                        Err (error) => notifier.message (NOTIFICATION_ID, "The link was not expanded", &error.to_string ()),
+                    }
+                });
+            },
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Interfaces exported on the session bus next to the ones GApplication
+//! exports itself. gio does not bind GDBus yet, so this goes through
+//! gio-sys.
+
+extern crate gdk;
+extern crate gtk;
+
+mod krunner;
+mod search;
+mod shortener;
+
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::c_char;
+use std::ptr;
+
+use gtk::{Application, Clipboard, ClipboardExt};
+use gio::ApplicationExt;
+use glib::Cast;
+use glib::translate::ToGlibPtr;
+use gio_sys::{self, GDBusConnection, GDBusInterfaceVTable, GDBusMethodInvocation};
+use glib_sys::{self, gpointer, GError, GVariant, GVariantType};
+use gtk_sys;
+
+use srtnr::core::ShortenError;
+
+use super::Shared;
+use super::worker::Worker;
+
+/// How long (in ms) a service started for a call stays after the last one.
+const INACTIVITY_TIMEOUT: u32 = 30000;
+
+/// Exports every interface. Calls are answered without showing the window.
+pub fn export (app: &Application, shared: &Shared) -> Result<(), String> {
+    shortener::export (app, shared)?;
+    search::export (app, shared)?;
+    krunner::export (app, shared)?;
+    ApplicationExt::set_inactivity_timeout (app, INACTIVITY_TIMEOUT);
+    Ok (())
+}
+
+/// Answers the calls of one exported interface.
+trait MethodHandler {
+    /// `parameters` were checked by GDBus against the introspection data.
+    unsafe fn call (&self, method: &str, parameters: *mut GVariant, invocation: Invocation);
+}
+
+/// Exports `interface_name`, described in `xml`, at `object_path`.
+fn register (app: &Application, xml: &str, interface_name: &str, object_path: &str, handler: Box<dyn MethodHandler>) -> Result<(), String> {
+    let xml = c_string (xml);
+    let name = interface_name;
+    let interface_name = c_string (interface_name);
+    let object_path = c_string (object_path);
+    let vtable = GDBusInterfaceVTable {
+        method_call: Some (method_call),
+        get_property: None,
+        set_property: None,
+        padding: [ptr::null_mut (); 8],
+    };
+
+    unsafe {
+        let application: *mut gio_sys::GApplication = app.upcast_ref::<gio::Application> ().to_glib_none ().0;
+        let connection = gio_sys::g_application_get_dbus_connection (application);
+        if connection.is_null () {
+            return Err ("Not connected to the session bus".to_owned ());
+        }
+
+        let mut error = ptr::null_mut ();
+        let node = gio_sys::g_dbus_node_info_new_for_xml (xml.as_ptr (), &mut error);
+        if node.is_null () {
+            return Err (take_error (error));
+        }
+        let interface = gio_sys::g_dbus_node_info_lookup_interface (node, interface_name.as_ptr ());
+        if interface.is_null () {
+            gio_sys::g_dbus_node_info_unref (node);
+            return Err (format!("No interface {} in the introspection data", name));
+        }
+
+        let handler = Box::into_raw (Box::new (handler));
+        // The vtable is copied and the interface info referenced by GDBus.
+        let id = gio_sys::g_dbus_connection_register_object (connection, object_path.as_ptr (), interface, &vtable,
+                                                              handler as gpointer, Some (free_handler), &mut error);
+        gio_sys::g_dbus_node_info_unref (node);
+        if id == 0 {
+            free_handler (handler as gpointer);
+            return Err (take_error (error));
+        }
+    }
+    Ok (())
+}
+
+unsafe extern "C" fn method_call (_connection: *mut GDBusConnection, _sender: *const c_char, _object_path: *const c_char,
+                                  _interface_name: *const c_char, method_name: *const c_char, parameters: *mut GVariant,
+                                  invocation: *mut GDBusMethodInvocation, user_data: gpointer) {
+    let handler = &*(user_data as *const Box<dyn MethodHandler>);
+    let method = CStr::from_ptr (method_name).to_string_lossy ().into_owned ();
+    handler.call (&method, parameters, Invocation (invocation));
+}
+
+unsafe extern "C" fn free_handler (user_data: gpointer) {
+    drop (Box::from_raw (user_data as *mut Box<dyn MethodHandler>));
+}
+
+/// Runs `job` off the main loop, each call on its own so they do not
+/// replace each other. `app` stays up until it is done, or until it
+/// panicked, which drops `on_done` and so fails its invocation.
+fn run_held<T, F, C> (app: &Application, job: F, on_done: C)
+where
+    T: Send + 'static,
+    F: FnOnce () -> T + Send + 'static,
+    C: FnOnce (T) + 'static,
+{
+    ApplicationExt::hold (app);
+    let worker = Worker::new ();
+    let panic_app = app.clone ();
+    worker.on_panic (move |_| ApplicationExt::release (&panic_app));
+    let app = app.clone ();
+    let on_done = Cell::new (Some (on_done));
+    worker.spawn (move |_| job (), move |result| {
+        if let Some (on_done) = on_done.take () {
+            on_done (result);
+        }
+        ApplicationExt::release (&app);
+    });
+}
+
+/// Puts `text` on the clipboard and hands it to the clipboard manager, as
+/// a service started for one call quits soon after.
+fn copy_lasting (text: &str) {
+    let clipboard = Clipboard::get (&gdk::Atom::intern ("CLIPBOARD"));
+    ClipboardExt::set_text (&clipboard, text);
+    // gtk does not bind it, all targets of the text are to be stored.
+    unsafe {
+        gtk_sys::gtk_clipboard_set_can_store (clipboard.to_glib_none ().0, ptr::null_mut (), 0);
+    }
+    ClipboardExt::store (&clipboard);
+}
+
+/// A D-Bus error, `name` being the last part of
+/// `com.github.arshubham.srtnr.Error.<name>` unless it has dots itself.
+struct CallError {
+    name: &'static str,
+    message: String,
+}
+
+impl CallError {
+    fn invalid_args (message: String) -> CallError {
+        CallError { name: "org.freedesktop.DBus.Error.InvalidArgs", message }
+    }
+
+    fn unknown_method (method: &str) -> CallError {
+        CallError { name: "org.freedesktop.DBus.Error.UnknownMethod", message: format!("No method {}", method) }
+    }
+
+    fn dbus_name (&self) -> String {
+        if self.name.contains ('.') {
+            self.name.to_owned ()
+        } else {
+            format!("com.github.arshubham.srtnr.Error.{}", self.name)
+        }
+    }
+}
+
+impl From<ShortenError> for CallError {
+    fn from (error: ShortenError) -> CallError {
+        let name = match error {
+            ShortenError::InvalidUrl (_) => "InvalidUrl",
+            ShortenError::UnknownProvider (_) => "UnknownProvider",
+            ShortenError::Unavailable (_) => "Unavailable",
+            ShortenError::Decode (_) => "Decode",
+            ShortenError::MissingCredentials (_) => "MissingCredentials",
+            ShortenError::Credentials (_) => "Credentials",
+            ShortenError::AllFailed (_) => "AllFailed",
+            ShortenError::Redirects (_) => "Redirects",
+            ShortenError::AliasTaken (_) => "AliasTaken",
+            ShortenError::InvalidAlias (_) => "InvalidAlias",
+            ShortenError::QuotaExceeded (_) => "QuotaExceeded",
+            ShortenError::Cancelled => "Cancelled",
+        };
+        CallError { name, message: error.to_string () }
+    }
+}
+
+/// A method call waiting for its answer. Answering consumes it, as GDBus
+/// drops its reference then. Dropped unanswered, it fails the call.
+struct Invocation (*mut GDBusMethodInvocation);
+
+impl Invocation {
+    /// The call to answer, once.
+    fn take (mut self) -> *mut GDBusMethodInvocation {
+        mem::replace (&mut self.0, ptr::null_mut ())
+    }
+
+    /// `value` is a floating tuple, sunk by GDBus.
+    unsafe fn return_value (self, value: *mut GVariant) {
+        gio_sys::g_dbus_method_invocation_return_value (self.take (), value);
+    }
+
+    fn return_empty (self) {
+        unsafe {
+            self.return_value (tuple (Vec::new ()));
+        }
+    }
+
+    fn return_strings (self, values: &[&str]) {
+        unsafe {
+            self.return_value (tuple (values.iter ().map (|value| new_string (value)).collect ()));
+        }
+    }
+
+    fn return_error (self, error: CallError) {
+        unsafe {
+            return_dbus_error (self.take (), &error);
+        }
+    }
+}
+
+impl Drop for Invocation {
+    fn drop (&mut self) {
+        if !self.0.is_null () {
+            let error = CallError { name: "org.freedesktop.DBus.Error.Failed", message: "The call failed inside Srtnr".to_owned () };
+            unsafe {
+                return_dbus_error (self.0, &error);
+            }
+        }
+    }
+}
+
+/// Answers `invocation` with `error`.
+unsafe fn return_dbus_error (invocation: *mut GDBusMethodInvocation, error: &CallError) {
+    let name = c_string (&error.dbus_name ());
+    let message = c_string (&error.message);
+    gio_sys::g_dbus_method_invocation_return_dbus_error (invocation, name.as_ptr (), message.as_ptr ());
+}
+
+/// Builds an array of type `type_string`, e.g. `a(us)`.
+struct ArrayBuilder (*mut glib_sys::GVariantBuilder);
+
+impl ArrayBuilder {
+    unsafe fn new (type_string: &str) -> ArrayBuilder {
+        let type_string = c_string (type_string);
+        let variant_type = glib_sys::g_variant_type_new (type_string.as_ptr ());
+        let builder = glib_sys::g_variant_builder_new (variant_type);
+        glib_sys::g_variant_type_free (variant_type);
+        ArrayBuilder (builder)
+    }
+
+    /// Adds `value`, which is sunk if floating.
+    unsafe fn add_value (&self, value: *mut GVariant) {
+        glib_sys::g_variant_builder_add_value (self.0, value);
+    }
+
+    /// The floating array.
+    unsafe fn end (self) -> *mut GVariant {
+        let array = glib_sys::g_variant_builder_end (self.0);
+        glib_sys::g_variant_builder_unref (self.0);
+        array
+    }
+}
+
+/// A floating tuple of the floating `items`.
+unsafe fn tuple (mut items: Vec<*mut GVariant>) -> *mut GVariant {
+    glib_sys::g_variant_new_tuple (items.as_mut_ptr (), items.len ())
+}
+
+/// A floating string.
+fn new_string (text: &str) -> *mut GVariant {
+    unsafe { glib_sys::g_variant_new_string (c_string (text).as_ptr ()) }
+}
+
+/// A floating `a{sv}` dictionary of string values.
+unsafe fn string_dictionary (entries: &[(&str, &str)]) -> *mut GVariant {
+    let dictionary = ArrayBuilder::new ("a{sv}");
+    for &(key, value) in entries {
+        dictionary.add_value (glib_sys::g_variant_new_dict_entry (new_string (key), glib_sys::g_variant_new_variant (new_string (value))));
+    }
+    dictionary.end ()
+}
+
+/// `read` applied to item `index` of `tuple`.
+unsafe fn child<T, F: FnOnce (*mut GVariant) -> T> (tuple: *mut GVariant, index: usize, read: F) -> T {
+    let value = glib_sys::g_variant_get_child_value (tuple, index);
+    let result = read (value);
+    glib_sys::g_variant_unref (value);
+    result
+}
+
+/// `read` applied to the `key` entry of an `a{sv}` dictionary, an error
+/// when it is not of type `type_string`.
+unsafe fn lookup<T, F: FnOnce (*mut GVariant) -> T> (dictionary: *mut GVariant, key: &str, type_string: &str, read: F) -> Result<Option<T>, CallError> {
+    let key_string = c_string (key);
+    let value = glib_sys::g_variant_lookup_value (dictionary, key_string.as_ptr (), ptr::null ());
+    if value.is_null () {
+        return Ok (None);
+    }
+    // A GVariantType is its type string.
+    let expected = c_string (type_string);
+    let result = if glib_sys::g_variant_is_of_type (value, expected.as_ptr () as *const GVariantType) != 0 {
+        Ok (Some (read (value)))
+    } else {
+        Err (CallError::invalid_args (format!("The option \"{}\" must be of type {}", key, type_string)))
+    };
+    glib_sys::g_variant_unref (value);
+    result
+}
+
+unsafe fn string (value: *mut GVariant) -> String {
+    CStr::from_ptr (glib_sys::g_variant_get_string (value, ptr::null_mut ())).to_string_lossy ().into_owned ()
+}
+
+/// The items of an `as` array.
+unsafe fn strings (value: *mut GVariant) -> Vec<String> {
+    (0..glib_sys::g_variant_n_children (value))
+        .map (|index| child (value, index, |item| string (item)))
+        .collect ()
+}
+
+/// D-Bus strings cannot hold NUL, anything after one is dropped.
+fn c_string (text: &str) -> CString {
+    CString::new (text.split ('\0').next ().unwrap_or ("")).unwrap ()
+}
+
+unsafe fn take_error (error: *mut GError) -> String {
+    let message = CStr::from_ptr ((*error).message).to_string_lossy ().into_owned ();
+    glib_sys::g_error_free (error);
+    message
+}
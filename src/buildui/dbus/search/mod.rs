@@ -0,0 +1,155 @@
+extern crate gtk;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use gtk::{Application, GtkApplicationExt};
+use gio::{ApplicationExt, Settings};
+use glib_sys::GVariant;
+
+use srtnr::core::history::{History, HistoryEntry};
+
+use super::super::Shared;
+use super::super::historydialog::HistoryDialogUi;
+use super::super::notifier::Notifier;
+use super::{child, copy_lasting, new_string, string, string_dictionary, strings, tuple, ArrayBuilder, CallError, Invocation, MethodHandler};
+
+const INTERFACE_XML: &str = include_str!("../../../../data/org.gnome.Shell.SearchProvider2.xml");
+const INTERFACE_NAME: &str = "org.gnome.Shell.SearchProvider2";
+/// As in data/com.github.arshubham.srtnr.search-provider.ini.
+const OBJECT_PATH: &str = "/com/github/arshubham/srtnr/SearchProvider";
+/// The overview shows a handful of results per provider anyway.
+const RESULT_LIMIT: usize = 20;
+const ICON: &str = "com.github.arshubham.srtnr";
+
+/// Exports the search provider of GNOME Shell, which finds the links
+/// shortened before by their long or short URL.
+pub fn export (app: &Application, shared: &Shared) -> Result<(), String> {
+    let provider = SearchProvider {
+        app: app.clone (),
+        settings: Settings::new ("com.github.arshubham.srtnr"),
+        history: shared.history.clone (),
+    };
+    super::register (app, INTERFACE_XML, INTERFACE_NAME, OBJECT_PATH, Box::new (provider))
+}
+
+struct SearchProvider {
+    app: Application,
+    settings: Settings,
+    history: Arc<History>,
+}
+
+impl MethodHandler for SearchProvider {
+    unsafe fn call (&self, method: &str, parameters: *mut GVariant, invocation: Invocation) {
+        match method {
+            "GetInitialResultSet" => {
+                let terms = child (parameters, 0, |value| strings (value));
+                self.initial_results (invocation, &terms.join (" "));
+            },
+            "GetSubsearchResultSet" => {
+                let previous = child (parameters, 0, |value| strings (value));
+                let terms = child (parameters, 1, |value| strings (value));
+                self.subsearch_results (invocation, &previous, &terms.join (" "));
+            },
+            "GetResultMetas" => self.result_metas (invocation, &child (parameters, 0, |value| strings (value))),
+            "ActivateResult" => self.activate_result (invocation, &child (parameters, 0, |value| string (value))),
+            "LaunchSearch" => {
+                let terms = child (parameters, 0, |value| strings (value));
+                self.launch_search (invocation, &terms.join (" "));
+            },
+            method => invocation.return_error (CallError::unknown_method (method)),
+        }
+    }
+}
+
+impl SearchProvider {
+    /// The entry behind a result identifier, which is its history id.
+    fn entry (&self, identifier: &str) -> Option<HistoryEntry> {
+        let id = identifier.parse ().ok ()?;
+        self.history.get (id).ok ().and_then (|entry| entry)
+    }
+
+    /// The entries behind `identifiers`, from a single read of the history.
+    fn entries (&self, identifiers: &[String]) -> HashMap<String, HistoryEntry> {
+        self.history.entries ().unwrap_or_default ()
+            .into_iter ()
+            .map (|entry| (entry.id.to_string (), entry))
+            .filter (|&(ref id, _)| identifiers.contains (id))
+            .collect ()
+    }
+
+    fn initial_results (&self, invocation: Invocation, query: &str) {
+        let entries = self.history.find_links (query, RESULT_LIMIT).unwrap_or_default ();
+        let ids: Vec<String> = entries.iter ().map (|entry| entry.id.to_string ()).collect ();
+        let ids: Vec<&str> = ids.iter ().map (|id| id.as_str ()).collect ();
+        unsafe {
+            invocation.return_value (tuple (vec![string_array (&ids)]));
+        }
+    }
+
+    /// Narrows `previous`, the terms only got longer.
+    fn subsearch_results (&self, invocation: Invocation, previous: &[String], query: &str) {
+        let entries = self.entries (previous);
+        let ids: Vec<&str> = previous.iter ()
+            .filter (|id| entries.get (*id).map (|entry| entry.matches (query)).unwrap_or (false))
+            .map (|id| id.as_str ())
+            .collect ();
+        unsafe {
+            invocation.return_value (tuple (vec![string_array (&ids)]));
+        }
+    }
+
+    fn result_metas (&self, invocation: Invocation, identifiers: &[String]) {
+        let entries = self.entries (identifiers);
+        unsafe {
+            let metas = ArrayBuilder::new ("aa{sv}");
+            for identifier in identifiers {
+                if let Some (entry) = entries.get (identifier) {
+                    metas.add_value (string_dictionary (&[
+                        ("id", identifier.as_str ()),
+                        ("name", entry.short_url.as_str ()),
+                        ("description", entry.long_url.as_str ()),
+                        ("gicon", ICON),
+                    ]));
+                }
+            }
+            invocation.return_value (tuple (vec![metas.end ()]));
+        }
+    }
+
+    /// Copies the short link, the overview closes right away so a
+    /// notification tells it happened.
+    fn activate_result (&self, invocation: Invocation, identifier: &str) {
+        let entry = match self.entry (identifier) {
+            Some (entry) => entry,
+            None => return invocation.return_error (CallError {
+                name: "UnknownResult",
+                message: format!("No shortened link {}", identifier),
+            }),
+        };
+
+        copy_lasting (&entry.short_url);
+        Notifier::new (&self.app, &self.settings).message ("search", "Short Url Copied into clipboard.", &entry.short_url);
+        invocation.return_empty ();
+    }
+
+    /// Opens the history with the terms of the overview.
+    fn launch_search (&self, invocation: Invocation, query: &str) {
+        ApplicationExt::activate (&self.app);
+        if let Some (window) = GtkApplicationExt::get_active_window (&self.app) {
+            let history_dialog = HistoryDialogUi::new (&window, self.history.clone ());
+            history_dialog.search (query);
+            HistoryDialogUi::run (&history_dialog);
+        }
+        invocation.return_empty ();
+    }
+}
+
+/// A floating `as` array.
+unsafe fn string_array (values: &[&str]) -> *mut GVariant {
+    let array = ArrayBuilder::new ("as");
+    for value in values {
+        array.add_value (new_string (value));
+    }
+    array.end ()
+}
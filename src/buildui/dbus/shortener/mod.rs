@@ -0,0 +1,151 @@
+extern crate gtk;
+
+use gtk::Application;
+use gio::Settings;
+use glib_sys::{self, GVariant};
+
+use srtnr::core::{self, ExpandOptions, LinkOptions};
+
+use super::super::{settings_request, Shared};
+use super::{child, lookup, new_string, run_held, string, strings, tuple, ArrayBuilder, CallError, Invocation, MethodHandler};
+
+const INTERFACE_XML: &str = include_str!("../../../../data/com.github.arshubham.srtnr.Shortener.xml");
+const INTERFACE_NAME: &str = "com.github.arshubham.srtnr.Shortener";
+const OBJECT_PATH: &str = "/com/github/arshubham/srtnr";
+
+/// Exports `com.github.arshubham.srtnr.Shortener`, so other programs can
+/// use the providers and tokens set up here.
+pub fn export (app: &Application, shared: &Shared) -> Result<(), String> {
+    let service = Service {
+        app: app.clone (),
+        settings: Settings::new ("com.github.arshubham.srtnr"),
+        shared: shared.clone (),
+    };
+    super::register (app, INTERFACE_XML, INTERFACE_NAME, OBJECT_PATH, Box::new (service))
+}
+
+struct Service {
+    app: Application,
+    settings: Settings,
+    shared: Shared,
+}
+
+impl MethodHandler for Service {
+    unsafe fn call (&self, method: &str, parameters: *mut GVariant, invocation: Invocation) {
+        match method {
+            "Shorten" => {
+                let url = child (parameters, 0, |value| string (value));
+                let provider = child (parameters, 1, |value| string (value));
+                match child (parameters, 2, |value| ShortenOptions::from_variant (value)) {
+                    Ok (options) => self.shorten (invocation, url, provider, options),
+                    Err (error) => invocation.return_error (error),
+                }
+            },
+            "Expand" => self.expand (invocation, child (parameters, 0, |value| string (value))),
+            "ListProviders" => self.list_providers (invocation),
+            method => invocation.return_error (CallError::unknown_method (method)),
+        }
+    }
+}
+
+impl Service {
+    fn shorten (&self, invocation: Invocation, url: String, provider: String, options: ShortenOptions) {
+        let mut request = match settings_request (&self.shared.shortener, &self.settings, &url, &provider) {
+            Some (request) => request,
+            None => return invocation.return_error (CallError {
+                name: "NoProvider",
+                message: "None of the automatic providers can be used, check the preferences of Srtnr".to_owned (),
+            }),
+        };
+        request.alias = options.alias;
+        request.options = options.link;
+        if let Some (probe) = options.probe {
+            request.normalize.probe = probe;
+        }
+        if options.clean == Some (false) {
+            request.cleaner = None;
+        }
+
+        let shortener = self.shared.shortener.clone ();
+        run_held (&self.app, move || shortener.shorten (&request), move |outcome| {
+            match outcome {
+                Ok (outcome) => invocation.return_strings (&[&outcome.short_url, &outcome.provider, &outcome.long_url]),
+                Err (error) => invocation.return_error (error.into ()),
+            }
+        });
+    }
+
+    fn expand (&self, invocation: Invocation, url: String) {
+        let shortener = self.shared.shortener.clone ();
+        run_held (&self.app, move || core::expand_chain (&url, shortener.http (), &ExpandOptions::default ()), move |expansion| {
+            match expansion {
+                Ok (expansion) => unsafe {
+                    let hops = ArrayBuilder::new ("a(us)");
+                    for hop in &expansion.hops {
+                        hops.add_value (tuple (vec![glib_sys::g_variant_new_uint32 (hop.status as u32), new_string (&hop.url)]));
+                    }
+                    invocation.return_value (tuple (vec![new_string (expansion.destination ()), hops.end ()]));
+                },
+                Err (error) => invocation.return_error (error.into ()),
+            }
+        });
+    }
+
+    fn list_providers (&self, invocation: Invocation) {
+        unsafe {
+            let providers = ArrayBuilder::new ("a(ssbb)");
+            for provider in self.shared.shortener.providers () {
+                providers.add_value (tuple (vec![
+                    new_string (provider.id ()),
+                    new_string (provider.name ()),
+                    glib_sys::g_variant_new_boolean (provider.is_configured () as i32),
+                    glib_sys::g_variant_new_boolean (provider.supports_alias () as i32),
+                ]));
+            }
+            invocation.return_value (tuple (vec![providers.end ()]));
+        }
+    }
+}
+
+/// The `options` of Shorten, `None` for what the preferences decide.
+struct ShortenOptions {
+    alias: Option<String>,
+    link: LinkOptions,
+    clean: Option<bool>,
+    probe: Option<bool>,
+}
+
+impl ShortenOptions {
+    unsafe fn from_variant (dictionary: *mut GVariant) -> Result<ShortenOptions, CallError> {
+        Ok (ShortenOptions {
+            alias: lookup (dictionary, "alias", "s", |value| string (value))?
+                .filter (|alias| !alias.trim ().is_empty ()),
+            link: LinkOptions {
+                title: text_option (dictionary, "title")?,
+                tags: lookup (dictionary, "tags", "as", |value| strings (value))?
+                    .unwrap_or_default ()
+                    .into_iter ()
+                    .map (|tag| tag.trim ().to_owned ())
+                    .filter (|tag| !tag.is_empty ())
+                    .collect (),
+                domain: text_option (dictionary, "domain")?,
+                valid_until: text_option (dictionary, "valid_until")?,
+                max_visits: lookup (dictionary, "max_visits", "u", |value| glib_sys::g_variant_get_uint32 (value))?,
+                find_if_exists: lookup (dictionary, "find_if_exists", "b", |value| glib_sys::g_variant_get_boolean (value) != 0)?
+                    .unwrap_or (false),
+                password: lookup (dictionary, "password", "s", |value| string (value))?
+                    .filter (|password| !password.is_empty ()),
+                expire_in: text_option (dictionary, "expire_in")?,
+                description: text_option (dictionary, "description")?,
+            },
+            clean: lookup (dictionary, "clean", "b", |value| glib_sys::g_variant_get_boolean (value) != 0)?,
+            probe: lookup (dictionary, "probe", "b", |value| glib_sys::g_variant_get_boolean (value) != 0)?,
+        })
+    }
+}
+
+/// A string of the options, `None` when missing or blank.
+unsafe fn text_option (dictionary: *mut GVariant, key: &str) -> Result<Option<String>, CallError> {
+    Ok (lookup (dictionary, key, "s", |value| string (value))?
+        .filter (|text| !text.trim ().is_empty ()))
+}
@@ -6,6 +6,8 @@ use gtk::{WidgetExt, StyleContextExt, HeaderBarExt};
 pub struct HeaderUi {
     pub headerbar: HeaderBar,
     pub preferences_button: Button,
+    pub batch_button: Button,
+    pub history_button: Button,
 }
 
 impl HeaderUi {
@@ -16,13 +18,23 @@ impl HeaderUi {
 
         let preferences_button = Button::new_from_icon_name ("open-menu", IconSize::LargeToolbar.into ());
 
+        let batch_button = Button::new_from_icon_name ("document-open", IconSize::LargeToolbar.into ());
+        WidgetExt::set_tooltip_text (&batch_button, "Shorten a list of links");
+
         HeaderBarExt::pack_end (&headerbar, &preferences_button);
+        let history_button = Button::new_from_icon_name ("document-open-recent", IconSize::LargeToolbar.into ());
+        WidgetExt::set_tooltip_text (&history_button, "History");
+
+        HeaderBarExt::pack_start (&headerbar, &batch_button);
+        HeaderBarExt::pack_start (&headerbar, &history_button);
 
         WidgetExt::get_style_context (&headerbar).map (|c| c.add_class("flat"));
 
         HeaderUi {
             headerbar,
             preferences_button,
+            batch_button,
+            history_button,
         }
     }
 }
\ No newline at end of file
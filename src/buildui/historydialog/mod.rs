@@ -0,0 +1,242 @@
+extern crate gtk;
+use gtk::IsA;
+
+use gtk::{Dialog, Label, Button, Grid, Box, Orientation, ComboBoxText, SearchEntry, ListBox, ListBoxRow, ScrolledWindow, PolicyType, IconSize, SelectionMode, Clipboard};
+use gtk::{DialogExt, GridExt, WidgetExt, BoxExt, GtkWindowExt, ContainerExt, ButtonExt, LabelExt, ComboBoxExt, ComboBoxTextExt, EntryExt, SearchEntryExt, ListBoxExt, ScrolledWindowExt, ClipboardExt, StyleContextExt};
+use glib::DateTime;
+
+use std::rc::Rc;
+use std::sync::Arc;
+use srtnr::core::history::{History, HistoryEntry, SortOrder};
+
+pub struct HistoryDialogUi {
+    pub history_dialog: Dialog,
+    search_entry: SearchEntry,
+}
+
+impl HistoryDialogUi {
+    pub fn new<P>(parent: &P, history: Arc<History>) -> HistoryDialogUi
+    where P: IsA<gtk::Window>, {
+    let history_dialog = Dialog::new ();
+    GtkWindowExt::set_title (&history_dialog, "History");
+    GtkWindowExt::set_modal (&history_dialog, true);
+    GtkWindowExt::set_transient_for (&history_dialog, parent);
+    GtkWindowExt::set_default_size (&history_dialog, 650, 450);
+
+    let content_grid = Grid::new ();
+    GridExt::set_row_spacing (&content_grid, 12);
+    GridExt::set_column_spacing (&content_grid, 6);
+    WidgetExt::set_margin_top (&content_grid, 10);
+    WidgetExt::set_margin_start (&content_grid, 10);
+    WidgetExt::set_margin_end (&content_grid, 10);
+    WidgetExt::set_margin_bottom (&content_grid, 10);
+
+    //search and sort
+    let search_entry = SearchEntry::new ();
+    EntryExt::set_placeholder_text (&search_entry, "Search links, providers and errors");
+    WidgetExt::set_hexpand (&search_entry, true);
+    let sort_combobox = ComboBoxText::new ();
+    ComboBoxTextExt::append (&sort_combobox, Some ("newest"), "Newest first");
+    ComboBoxTextExt::append (&sort_combobox, Some ("oldest"), "Oldest first");
+    ComboBoxTextExt::append (&sort_combobox, Some ("long-url"), "By long URL");
+    ComboBoxTextExt::append (&sort_combobox, Some ("short-url"), "By short URL");
+    ComboBoxTextExt::append (&sort_combobox, Some ("provider"), "By provider");
+    ComboBoxExt::set_active (&sort_combobox, 0);
+    GridExt::attach (&content_grid, &search_entry, 0, 0, 3, 1);
+    GridExt::attach (&content_grid, &sort_combobox, 3, 0, 1, 1);
+
+    //entries
+    let list_box = ListBox::new ();
+    ListBoxExt::set_selection_mode (&list_box, SelectionMode::None);
+    let list_window = ScrolledWindow::new (None, None);
+    ScrolledWindowExt::set_policy (&list_window, PolicyType::Automatic, PolicyType::Automatic);
+    WidgetExt::set_vexpand (&list_window, true);
+    ContainerExt::add (&list_window, &list_box);
+    GridExt::attach (&content_grid, &list_window, 0, 1, 4, 1);
+
+    let status_label = Label::new ("");
+    WidgetExt::set_halign (&status_label, gtk::Align::Start);
+    WidgetExt::set_hexpand (&status_label, true);
+    WidgetExt::get_style_context (&status_label).map (|c| c.add_class("dim-label"));
+    GridExt::attach (&content_grid, &status_label, 0, 2, 4, 1);
+
+    DialogExt::get_content_area (&history_dialog).add (&content_grid);
+
+    let clear_button = Button::new_with_label ("Clear History");
+    WidgetExt::get_style_context (&clear_button).map (|c| c.add_class("destructive-action"));
+    let close_button = Button::new_with_label ("Close");
+    DialogExt::add_action_widget (&history_dialog, &clear_button, 1);
+    DialogExt::add_action_widget (&history_dialog, &close_button, 0);
+    DialogExt::get_content_area (&history_dialog).show_all ();
+
+    let refresh = {
+        let history = history.clone ();
+        let list_box = list_box.clone ();
+        let search_entry = search_entry.clone ();
+        let sort_combobox = sort_combobox.clone ();
+        let status_label = status_label.clone ();
+        move || {
+            let query = EntryExt::get_text (&search_entry).unwrap_or_default ();
+            let order = sort_order (ComboBoxExt::get_active_id (&sort_combobox));
+            populate (&list_box, &history, &query, order, &status_label);
+        }
+    };
+    refresh ();
+    let refresh = Rc::new (refresh);
+
+    let refresh_clone = refresh.clone ();
+    search_entry.connect_search_changed (move |_| refresh_clone ());
+
+    let refresh_clone = refresh.clone ();
+    sort_combobox.connect_changed (move |_| refresh_clone ());
+
+    let refresh_clone = refresh.clone ();
+    let status_label_clone = status_label.clone ();
+    clear_button.connect_clicked (move |_| {
+        match history.clear () {
+            Ok (()) => refresh_clone (),
+            Err (error) => LabelExt::set_text (&status_label_clone, &format!("Could not clear the history: {}", error)),
+        }
+    });
+
+    let history_dialog_clone = history_dialog.clone ();
+    close_button.connect_clicked (move |_| {
+        DialogExt::emit_close (&history_dialog_clone);
+    });
+
+    HistoryDialogUi {
+             history_dialog,
+             search_entry,
+        }
+    }
+
+    pub fn run(&self) {
+        WidgetExt::show_now (&self.history_dialog);
+    }
+
+    /// Shows only the entries matching `query`, as if it had been typed.
+    pub fn search(&self, query: &str) {
+        EntryExt::set_text (&self.search_entry, query);
+    }
+
+}
+
+fn sort_order (id: Option<String>) -> SortOrder {
+    match id.as_ref ().map (|id| id.as_str ()) {
+        Some ("oldest") => SortOrder::OldestFirst,
+        Some ("long-url") => SortOrder::LongUrl,
+        Some ("short-url") => SortOrder::ShortUrl,
+        Some ("provider") => SortOrder::Provider,
+        _ => SortOrder::NewestFirst,
+    }
+}
+
+/// Replaces the rows of `list_box` with the entries matching `query`.
+fn populate (list_box: &ListBox, history: &Arc<History>, query: &str, order: SortOrder, status_label: &Label) {
+    for child in ContainerExt::get_children (list_box) {
+        ContainerExt::remove (list_box, &child);
+    }
+
+    let entries = match history.search (query, order) {
+        Ok (entries) => entries,
+        Err (error) => {
+            LabelExt::set_text (status_label, &format!("Could not read the history: {}", error));
+            return;
+        },
+    };
+
+    let status = match entries.len () {
+        0 if query.trim ().is_empty () => "Nothing shortened yet".to_owned (),
+        0 => "No matching links".to_owned (),
+        1 => "1 link".to_owned (),
+        count => format!("{} links", count),
+    };
+    LabelExt::set_text (status_label, &status);
+
+    for entry in entries {
+        let row = entry_row (list_box, history, &entry, status_label);
+        ListBoxExt::insert (list_box, &row, -1);
+    }
+    WidgetExt::show_all (list_box);
+}
+
+fn entry_row (list_box: &ListBox, history: &Arc<History>, entry: &HistoryEntry, status_label: &Label) -> ListBoxRow {
+    let row = ListBoxRow::new ();
+    let row_grid = Grid::new ();
+    GridExt::set_column_spacing (&row_grid, 6);
+    WidgetExt::set_margin_top (&row_grid, 6);
+    WidgetExt::set_margin_bottom (&row_grid, 6);
+    WidgetExt::set_margin_start (&row_grid, 6);
+    WidgetExt::set_margin_end (&row_grid, 6);
+
+    let result = match entry.error {
+        Some (ref error) => format!("Failed: {}", error),
+        None => entry.short_url.clone (),
+    };
+    let result_label = Label::new (Some (result.as_str ()));
+    LabelExt::set_selectable (&result_label, true);
+    WidgetExt::set_halign (&result_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&result_label).map (|c| c.add_class(if entry.succeeded () { "h4" } else { "error" }));
+
+    let long_url_label = Label::new (Some (entry.long_url.as_str ()));
+    LabelExt::set_selectable (&long_url_label, true);
+    WidgetExt::set_halign (&long_url_label, gtk::Align::Start);
+    WidgetExt::set_hexpand (&long_url_label, true);
+
+    let when = DateTime::new_from_unix_local (entry.timestamp)
+        .format ("%x %X")
+        .unwrap_or_default ();
+    let details_label = Label::new (Some (format!("{} · {}", when, entry.provider).as_str ()));
+    WidgetExt::set_halign (&details_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&details_label).map (|c| c.add_class("dim-label"));
+
+    let buttons_box = Box::new (Orientation::Horizontal, 0);
+    WidgetExt::get_style_context (&buttons_box).map (|c| c.add_class("linked"));
+    WidgetExt::set_valign (&buttons_box, gtk::Align::Center);
+    let copy_button = Button::new_from_icon_name ("edit-copy-symbolic", IconSize::Button.into ());
+    WidgetExt::set_tooltip_text (&copy_button, "Copy the short link");
+    let open_button = Button::new_from_icon_name ("web-browser-symbolic", IconSize::Button.into ());
+    WidgetExt::set_tooltip_text (&open_button, "Open the short link");
+    let delete_button = Button::new_from_icon_name ("edit-delete-symbolic", IconSize::Button.into ());
+    WidgetExt::set_tooltip_text (&delete_button, "Remove from history");
+    WidgetExt::set_sensitive (&copy_button, entry.succeeded ());
+    WidgetExt::set_sensitive (&open_button, entry.succeeded ());
+    BoxExt::pack_start (&buttons_box, &copy_button, false, false, 0);
+    BoxExt::pack_start (&buttons_box, &open_button, false, false, 0);
+    BoxExt::pack_start (&buttons_box, &delete_button, false, false, 0);
+
+    GridExt::attach (&row_grid, &result_label, 0, 0, 1, 1);
+    GridExt::attach (&row_grid, &long_url_label, 0, 1, 1, 1);
+    GridExt::attach (&row_grid, &details_label, 0, 2, 1, 1);
+    GridExt::attach (&row_grid, &buttons_box, 1, 0, 1, 3);
+    ContainerExt::add (&row, &row_grid);
+
+    let short_url = entry.short_url.clone ();
+    copy_button.connect_clicked (move |button| {
+        if let Some (gclipboard) = WidgetExt::get_display (button).and_then (|display| Clipboard::get_default (&display)) {
+            gclipboard.set_text (&short_url);
+        }
+    });
+
+    let short_url = entry.short_url.clone ();
+    let status_label_clone = status_label.clone ();
+    open_button.connect_clicked (move |_| {
+        if let Err (error) = gtk::show_uri (None, &short_url, 0) {
+            LabelExt::set_text (&status_label_clone, &format!("Could not open {}: {}", short_url, error));
+        }
+    });
+
+    let id = entry.id;
+    let history = history.clone ();
+    let list_box = list_box.clone ();
+    let row_clone = row.clone ();
+    let status_label = status_label.clone ();
+    delete_button.connect_clicked (move |_| {
+        match history.delete (id) {
+            Ok (()) => ContainerExt::remove (&list_box, &row_clone),
+            Err (error) => LabelExt::set_text (&status_label, &format!("Could not remove the link: {}", error)),
+        }
+    });
+
+    row
+}
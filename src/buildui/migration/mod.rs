@@ -0,0 +1,84 @@
+extern crate gio;
+
+use gio::Settings;
+use gio::SettingsExt;
+use srtnr::core;
+
+/// Version written by the last step in `STEPS`.
+const SETTINGS_VERSION: i32 = 1;
+
+/// Each step upgrades the settings from the previous version and may return
+/// a notice for the user.
+const STEPS: &[fn (&Settings) -> Option<String>] = &[
+    provider_index_to_id,
+];
+
+/// Brings settings written by an older release up to date. Returns the
+/// notices to show once, empty when nothing changed for the user.
+pub fn migrate (settings: &Settings) -> Vec<String> {
+    let version = SettingsExt::get_int (settings, "settings-version");
+    let mut notices = Vec::new ();
+
+    for (step_version, step) in (1..).zip (STEPS) {
+        if step_version > version {
+            notices.extend (step (settings));
+        }
+    }
+    if version < SETTINGS_VERSION {
+        SettingsExt::set_int (settings, "settings-version", SETTINGS_VERSION);
+    }
+    notices
+}
+
+/// Version 1: `default-provider` held the combobox index, which broke
+/// whenever the provider list changed. `default-provider-id` holds the id.
+fn provider_index_to_id (settings: &Settings) -> Option<String> {
+    // Never shortened anything, the new default applies.
+    if SettingsExt::get_user_value (settings, "default-provider").is_none () {
+        return None;
+    }
+
+    let index = SettingsExt::get_int (settings, "default-provider");
+    SettingsExt::reset (settings, "default-provider");
+
+    let (id, notice) = provider_for_index (index);
+    SettingsExt::set_string (settings, "default-provider-id", id);
+    notice
+}
+
+/// The provider which replaces the old combobox `index`, with why when it
+/// is not the one picked back then.
+fn provider_for_index (index: i32) -> (&'static str, Option<String>) {
+    let id = core::legacy_provider_id (index).unwrap_or (core::DEFAULT_PROVIDER);
+    match core::retired_reason (id) {
+        Some (reason) => (core::DEFAULT_PROVIDER, Some (format!("{}, your default provider is now {}.", reason, core::DEFAULT_PROVIDER))),
+        None => (id, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_indexes_map_to_their_provider () {
+        assert_eq!(provider_for_index (1), ("bitly.com", None));
+        assert_eq!(provider_for_index (2), ("is.gd", None));
+        assert_eq!(provider_for_index (5), ("hmm.rs", None));
+    }
+
+    #[test]
+    fn goo_gl_moves_to_the_default_with_a_notice () {
+        let (id, notice) = provider_for_index (0);
+        assert_eq!(id, core::DEFAULT_PROVIDER);
+        let notice = notice.unwrap ();
+        assert!(notice.starts_with (core::retired_reason ("goo.gl").unwrap ()));
+        assert!(notice.ends_with ("your default provider is now is.gd."));
+    }
+
+    #[test]
+    fn unknown_indexes_fall_back_to_the_default () {
+        assert_eq!(provider_for_index (-1), (core::DEFAULT_PROVIDER, None));
+        assert_eq!(provider_for_index (42), (core::DEFAULT_PROVIDER, None));
+    }
+}
@@ -0,0 +1,101 @@
+extern crate gtk;
+
+use gtk::prelude::*;
+use gtk::{Application, GtkApplicationExt, GtkWindowExt};
+use gio::{ActionMapExt, ApplicationExt, Notification, NotificationExt, Settings, SettingsExt, SimpleAction, SimpleActionExt};
+use glib::{ToVariant, VariantTy};
+
+/// Opens the short link in the browser.
+pub const OPEN_ACTION: &str = "open-link";
+/// Puts the short link on the clipboard again.
+pub const COPY_ACTION: &str = "copy-link";
+/// Brings the window up with the QR code of the short link.
+pub const QR_ACTION: &str = "show-qr";
+/// Puts the long link back on the clipboard and drops it from the history.
+pub const UNDO_ACTION: &str = "undo-link";
+
+/// Sends desktop notifications through the `Application`, so they carry
+/// buttons wired to its actions and survive the window being closed.
+#[derive(Clone)]
+pub struct Notifier {
+    app: Application,
+    settings: Settings,
+}
+
+impl Notifier {
+    pub fn new (app: &Application, settings: &Settings) -> Notifier {
+        Notifier {
+            app: app.clone (),
+            settings: settings.clone (),
+        }
+    }
+
+    /// Whether notifications are switched on, and wanted while the window
+    /// has the focus if it has.
+    pub fn enabled (&self) -> bool {
+        if !SettingsExt::get_boolean (&self.settings, "notifications") {
+            return false;
+        }
+        let focused = GtkApplicationExt::get_active_window (&self.app)
+            .map (|window| GtkWindowExt::is_active (&window))
+            .unwrap_or (false);
+        !focused || SettingsExt::get_boolean (&self.settings, "notifications-when-focused")
+    }
+
+    /// Batch runs send one summary rather than a notification per row.
+    pub fn group_batches (&self) -> bool {
+        SettingsExt::get_boolean (&self.settings, "notifications-group-batch")
+    }
+
+    /// "Short Url Copied into clipboard." with buttons to open, copy, show
+    /// the QR code of or undo `short_url`.
+    pub fn shortened (&self, short_url: &str) {
+        let notification = Notification::new ("Short Url Copied into clipboard.");
+        notification.set_body (Some (short_url));
+        let target = short_url.to_variant ();
+        notification.set_default_action_and_target_value (&action_name (QR_ACTION), Some (&target));
+        notification.add_button_with_target_value ("Open", &action_name (OPEN_ACTION), Some (&target));
+        notification.add_button_with_target_value ("Copy again", &action_name (COPY_ACTION), Some (&target));
+        notification.add_button_with_target_value ("Show QR", &action_name (QR_ACTION), Some (&target));
+        notification.add_button_with_target_value ("Undo", &action_name (UNDO_ACTION), Some (&target));
+        self.send ("shortened", &notification);
+    }
+
+    /// A notification with just a title and a body.
+    pub fn message (&self, id: &str, title: &str, body: &str) {
+        let notification = Notification::new (title);
+        notification.set_body (Some (body));
+        self.send (id, &notification);
+    }
+
+    /// Sends `notification` if `enabled`. A later notification with the
+    /// same `id` replaces it.
+    pub fn send (&self, id: &str, notification: &Notification) {
+        if self.enabled () {
+            ApplicationExt::send_notification (&self.app, Some (id), notification);
+        }
+    }
+
+    /// Sends `notification` whatever the settings, for notifications the
+    /// user asked for elsewhere.
+    pub fn send_always (&self, id: &str, notification: &Notification) {
+        ApplicationExt::send_notification (&self.app, Some (id), notification);
+    }
+}
+
+/// `app.<name>`, as notification buttons name actions.
+pub fn action_name (name: &str) -> String {
+    format!("app.{}", name)
+}
+
+/// Adds the application action `name`, which takes a string parameter.
+pub fn add_string_action<F> (app: &Application, name: &str, activate: F)
+where F: Fn (&str) + 'static, {
+    let action = SimpleAction::new (name, Some (VariantTy::new ("s").unwrap ()));
+    action.connect_activate (move |_, parameter| {
+        if let Some (value) = parameter.as_ref ().and_then (|parameter| parameter.get_str ()) {
+            activate (value);
+        }
+    });
+    ActionMapExt::add_action (app, &action);
+}
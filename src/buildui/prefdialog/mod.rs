@@ -1,20 +1,34 @@
 extern crate gtk;
 use gtk::IsA;
 
-use gtk::{Dialog, Label, Button, Grid, Switch, Box, Orientation};
-use gtk::{DialogExt, GridExt, WidgetExt, BoxExt, GtkWindowExt, ContainerExt, ButtonExt, SwitchExt};
+use gtk::{Dialog, Label, Button, CheckButton, Grid, Switch, Box, Orientation, Entry, TextView, ComboBoxText, SpinButton, ListStore, TreeView, TreeViewColumn, CellRendererToggle, CellRendererText, ScrolledWindow, PolicyType, Type, ToValue, FileChooserDialog, FileChooserAction, ResponseType};
+use gtk::{DialogExt, GridExt, WidgetExt, BoxExt, GtkWindowExt, ContainerExt, ButtonExt, ToggleButtonExt, SwitchExt, EntryExt, TextViewExt, TextBufferExt, LabelExt, StyleContextExt, TreeViewExt, TreeModelExt, CellLayoutExt, CellRendererToggleExt, ScrolledWindowExt, ListStoreExtManual, ComboBoxExt, ComboBoxTextExt, SpinButtonExt, SpinButtonSignals, FileChooserExt};
+use glib::translate::ToGlib;
 
 use gio::Settings;
 use gio::SettingsExt;
 use gio;
 
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use srtnr::core::{self, BitlyAccount, BitlyProvider, Shortener};
+use srtnr::core::clean::{self, Rule};
+use srtnr::core::config::ProviderConfig;
+use srtnr::core::credentials::CredentialStore;
+use srtnr::core::custom::DefinitionFile;
+
+use super::{provider_label_text, reload_providers};
+use super::worker::Worker;
+
 
 pub struct PrefDialogUi {
     pub pref_dialog: Dialog,
 }
 
 impl PrefDialogUi {
-    pub fn new<P>(parent: &P) -> PrefDialogUi 
+    pub fn new<P>(parent: &P, shortener: &Arc<Shortener>, store: Arc<dyn CredentialStore>, config: Arc<ProviderConfig>) -> PrefDialogUi 
     where P: IsA<gtk::Window>, {
     let pref_dialog = Dialog::new ();
     let content_grid = Grid::new ();
@@ -24,6 +38,7 @@ impl PrefDialogUi {
     WidgetExt::set_margin_top (&content_grid, 20);
     WidgetExt::set_margin_start (&content_grid, 10);
     WidgetExt::set_margin_end (&content_grid, 30);
+    WidgetExt::set_margin_bottom (&content_grid, 20);
     GtkWindowExt::set_modal (&pref_dialog, true);
 
     let dark_setting_box = Box::new (Orientation::Horizontal, 5);
@@ -45,25 +60,454 @@ impl PrefDialogUi {
         gio::signal::Inhibit(false)
     });
 
-    GtkWindowExt::set_default_size (&pref_dialog, 500, 300);
+    // The rows outgrow most screens, they scroll in a resizable dialog.
+    GtkWindowExt::set_default_size (&pref_dialog, 560, 600);
     pref_dialog.set_size_request (500, 300);
-    GtkWindowExt::set_resizable (&pref_dialog, false);
     
     BoxExt::pack_start (&dark_setting_box, &dark_setting_label, false, false, 0);
     BoxExt::pack_end (&dark_setting_box, &dark_setting_switch, false, false, 0);
 
     GridExt::attach (&content_grid, &dark_setting_box, 0, 0, 1, 1);
-    DialogExt::get_content_area (&pref_dialog).add (&content_grid);
+
+    //https is assumed for links typed without a scheme
+    let probe_setting_box = Box::new (Orientation::Horizontal, 5);
+    let probe_setting_label = Label::new_with_mnemonic (Some ("Fall back to http:// for sites without HTTPS"));
+    WidgetExt::set_halign (&probe_setting_label, gtk::Align::Start);
+    WidgetExt::set_tooltip_text (&probe_setting_label, "Checks the site before shortening a link typed without https:// or http://");
+    let probe_setting_switch = Switch::new ();
+    let probe_settings = Settings::new ("com.github.arshubham.srtnr");
+    SwitchExt::set_active (&probe_setting_switch, SettingsExt::get_boolean (&probe_settings, "probe-scheme"));
+    probe_setting_switch.connect_state_set (move |_, state| {
+        SettingsExt::set_boolean (&probe_settings, "probe-scheme", state);
+        gio::signal::Inhibit(false)
+    });
+    BoxExt::pack_start (&probe_setting_box, &probe_setting_label, false, false, 0);
+    BoxExt::pack_end (&probe_setting_box, &probe_setting_switch, false, false, 0);
+    GridExt::attach (&content_grid, &probe_setting_box, 0, 1, 1, 1);
+
+    //API tokens of the providers which need one
+    let credentials_label = Label::new_with_mnemonic (Some ("API Tokens"));
+    WidgetExt::set_halign (&credentials_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&credentials_label).map (|c| c.add_class("h4"));
+    GridExt::attach (&content_grid, &credentials_label, 0, 2, 1, 1);
+
+    let credentials_status_label = Label::new (Some (store.description ().as_str ()));
+    WidgetExt::set_halign (&credentials_status_label, gtk::Align::Start);
+    LabelExt::set_line_wrap (&credentials_status_label, true);
+    WidgetExt::get_style_context (&credentials_status_label).map (|c| c.add_class("dim-label"));
+
+    let mut credential_entries = Vec::new ();
+    let mut row = 3;
+    for provider in shortener.providers ().iter ().filter (|provider| provider.uses_secret ()) {
+        let credential_box = Box::new (Orientation::Horizontal, 5);
+        let credential_label = Label::new (Some (provider.name ()));
+        let credential_entry = Entry::new ();
+        EntryExt::set_visibility (&credential_entry, false);
+        EntryExt::set_placeholder_text (&credential_entry, "Not set");
+        WidgetExt::set_size_request (&credential_entry, 300, -1);
+
+        match store.get (provider.id ()) {
+            Ok (Some (secret)) => EntryExt::set_text (&credential_entry, &secret),
+            Ok (None) => {},
+            Err (error) => LabelExt::set_text (&credentials_status_label, &error.to_string ()),
+        }
+
+        BoxExt::pack_start (&credential_box, &credential_label, false, false, 0);
+        BoxExt::pack_end (&credential_box, &credential_entry, false, false, 0);
+        GridExt::attach (&content_grid, &credential_box, 0, row, 1, 1);
+        credential_entries.push ((provider.id ().to_owned (), credential_entry));
+        row += 1;
+    }
+    GridExt::attach (&content_grid, &credentials_status_label, 0, row, 1, 1);
+
+    //addresses and sign in of the self-hosted providers
+    let mut config_inputs = Vec::new ();
+    for provider in shortener.providers ().iter ().filter (|provider| !provider.config_fields ().is_empty ()) {
+        row += 1;
+        let config_label = Label::new (Some (provider.name ()));
+        WidgetExt::set_halign (&config_label, gtk::Align::Start);
+        WidgetExt::get_style_context (&config_label).map (|c| c.add_class("h4"));
+        GridExt::attach (&content_grid, &config_label, 0, row, 1, 1);
+
+        for field in provider.config_fields () {
+            let value = match config.get (provider.id (), field.key) {
+                Ok (value) => value,
+                Err (error) => {
+                    LabelExt::set_text (&credentials_status_label, &error.to_string ());
+                    None
+                },
+            };
+
+            let field_box = Box::new (Orientation::Horizontal, 5);
+            let field_label = Label::new (Some (field.label));
+            BoxExt::pack_start (&field_box, &field_label, false, false, 0);
+            let input = if field.choices.is_empty () {
+                let field_entry = Entry::new ();
+                EntryExt::set_placeholder_text (&field_entry, field.placeholder);
+                EntryExt::set_text (&field_entry, &value.unwrap_or_default ());
+                WidgetExt::set_size_request (&field_entry, 300, -1);
+                BoxExt::pack_end (&field_box, &field_entry, false, false, 0);
+                FieldInput::Text (field_entry)
+            } else {
+                let field_combo = ComboBoxText::new ();
+                for &(id, text) in field.choices {
+                    ComboBoxTextExt::append (&field_combo, Some (id), text);
+                }
+                let active = value.unwrap_or_else (|| field.choices[0].0.to_owned ());
+                ComboBoxExt::set_active_id (&field_combo, Some (active.as_str ()));
+                BoxExt::pack_end (&field_box, &field_combo, false, false, 0);
+                FieldInput::Choice (field_combo)
+            };
+            row += 1;
+            GridExt::attach (&content_grid, &field_box, 0, row, 1, 1);
+            config_inputs.push ((provider.id ().to_owned (), field.key, input));
+        }
+
+        //groups and branded domains come from the bit.ly account
+        if provider.id () == core::BITLY {
+            let account_box = Box::new (Orientation::Horizontal, 5);
+            let account_label = Label::new (Some ("Groups and branded domains of the account"));
+            LabelExt::set_line_wrap (&account_label, true);
+            WidgetExt::get_style_context (&account_label).map (|c| c.add_class("dim-label"));
+            let account_button = Button::new_with_label ("Load");
+            BoxExt::pack_start (&account_box, &account_label, false, false, 0);
+            BoxExt::pack_end (&account_box, &account_button, false, false, 0);
+            row += 1;
+            GridExt::attach (&content_grid, &account_box, 0, row, 1, 1);
+
+            let group_entry = config_inputs.iter ()
+                .filter_map (|&(ref id, key, ref input)| match *input {
+                    FieldInput::Text (ref entry) if id == core::BITLY && key == "group" => Some (entry.clone ()),
+                    _ => None,
+                })
+                .next ();
+            let bitly = Arc::new (BitlyProvider::new (store.clone (), config.clone ()));
+            let account_shortener = shortener.clone ();
+            let account_worker = Worker::new ();
+            account_button.connect_clicked (move |account_button| {
+                WidgetExt::set_sensitive (account_button, false);
+                LabelExt::set_text (&account_label, "Loading…");
+
+                let account_button = account_button.clone ();
+                let account_label = account_label.clone ();
+                let group_entry = group_entry.clone ();
+                let bitly = bitly.clone ();
+                let job_bitly = bitly.clone ();
+                let shortener = account_shortener.clone ();
+                account_worker.spawn (move |_| job_bitly.account (shortener.http ()), move |account| {
+                    WidgetExt::set_sensitive (&account_button, true);
+                    match account {
+                        Ok (account) => {
+                            if let (Some (entry), Some (group)) = (group_entry.as_ref (), account.default_group.as_ref ()) {
+                                if EntryExt::get_text (entry).unwrap_or_default ().trim ().is_empty () {
+                                    EntryExt::set_text (entry, group);
+                                }
+                            }
+                            let text = match bitly.remember_domains (&account.domains ()) {
+                                Ok (()) => account_text (&account),
+                                Err (error) => error.to_string (),
+                            };
+                            LabelExt::set_text (&account_label, &text);
+                        },
+                        Err (error) => LabelExt::set_text (&account_label, &error.to_string ()),
+                    }
+                });
+            });
+        }
+    }
+
+    //providers defined in custom-providers.json, shared as files
+    row += 1;
+    let custom_label = Label::new_with_mnemonic (Some ("Custom Providers"));
+    WidgetExt::set_halign (&custom_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&custom_label).map (|c| c.add_class("h4"));
+    GridExt::attach (&content_grid, &custom_label, 0, row, 1, 1);
+
+    let definitions = Rc::new (DefinitionFile::new ());
+    let custom_status_label = Label::new (Some (definitions_text (&definitions).as_str ()));
+    WidgetExt::set_halign (&custom_status_label, gtk::Align::Start);
+    LabelExt::set_line_wrap (&custom_status_label, true);
+    LabelExt::set_selectable (&custom_status_label, true);
+    WidgetExt::get_style_context (&custom_status_label).map (|c| c.add_class("dim-label"));
+    row += 1;
+    GridExt::attach (&content_grid, &custom_status_label, 0, row, 1, 1);
+
+    let custom_box = Box::new (Orientation::Horizontal, 5);
+    let import_button = Button::new_with_label ("Import…");
+    let export_button = Button::new_with_label ("Export…");
+    BoxExt::pack_end (&custom_box, &export_button, false, false, 0);
+    BoxExt::pack_end (&custom_box, &import_button, false, false, 0);
+    row += 1;
+    GridExt::attach (&content_grid, &custom_box, 0, row, 1, 1);
+
+    let pref_dialog_clone = pref_dialog.clone ();
+    let definitions_clone = definitions.clone ();
+    let custom_status_label_clone = custom_status_label.clone ();
+    let import_shortener = shortener.clone ();
+    let import_store = store.clone ();
+    let import_config = config.clone ();
+    import_button.connect_clicked (move |_| {
+        if let Some (file) = choose_definitions (&pref_dialog_clone, FileChooserAction::Open) {
+            let text = match definitions_clone.import (&file) {
+                // The provider picker is filled again once the dialog closes.
+                Ok (ids) => reload_providers (&import_shortener, import_store.clone (), import_config.clone ())
+                    .unwrap_or_else (|| format!("Imported {}.", ids.join (", "))),
+                Err (error) => error.to_string (),
+            };
+            LabelExt::set_text (&custom_status_label_clone, &text);
+        }
+    });
+    let pref_dialog_clone = pref_dialog.clone ();
+    export_button.connect_clicked (move |_| {
+        if let Some (file) = choose_definitions (&pref_dialog_clone, FileChooserAction::Save) {
+            let text = match definitions.export (&file) {
+                Ok (count) => format!("Exported {} providers to {}", count, file.display ()),
+                Err (error) => error.to_string (),
+            };
+            LabelExt::set_text (&custom_status_label, &text);
+        }
+    });
+
+    //order of the providers tried by "Automatic"
+    let priority_label = Label::new_with_mnemonic (Some ("Automatic Provider Order"));
+    WidgetExt::set_halign (&priority_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&priority_label).map (|c| c.add_class("h4"));
+    GridExt::attach (&content_grid, &priority_label, 0, row + 1, 1, 1);
+
+    let priority_settings = Settings::new ("com.github.arshubham.srtnr");
+    let priority_store = priority_store (shortener, &SettingsExt::get_strv (&priority_settings, "provider-priority"));
+    let priority_view = TreeView::new_with_model (&priority_store);
+    TreeViewExt::set_reorderable (&priority_view, true);
+    TreeViewExt::set_headers_visible (&priority_view, false);
+
+    let enabled_renderer = CellRendererToggle::new ();
+    let enabled_column = TreeViewColumn::new ();
+    CellLayoutExt::pack_start (&enabled_column, &enabled_renderer, false);
+    CellLayoutExt::add_attribute (&enabled_column, &enabled_renderer, "active", PRIORITY_ENABLED_COLUMN as i32);
+    TreeViewExt::append_column (&priority_view, &enabled_column);
+
+    let name_renderer = CellRendererText::new ();
+    let name_column = TreeViewColumn::new ();
+    CellLayoutExt::pack_start (&name_column, &name_renderer, true);
+    CellLayoutExt::add_attribute (&name_column, &name_renderer, "text", PRIORITY_NAME_COLUMN as i32);
+    TreeViewExt::append_column (&priority_view, &name_column);
+
+    let priority_window = ScrolledWindow::new (None, None);
+    ScrolledWindowExt::set_policy (&priority_window, PolicyType::Never, PolicyType::Automatic);
+    ScrolledWindowExt::set_min_content_height (&priority_window, 150);
+    ContainerExt::add (&priority_window, &priority_view);
+    GridExt::attach (&content_grid, &priority_window, 0, row + 2, 1, 1);
+
+    let priority_hint_label = Label::new (Some ("Drag providers to change the order, untick the ones to skip."));
+    WidgetExt::set_halign (&priority_hint_label, gtk::Align::Start);
+    LabelExt::set_line_wrap (&priority_hint_label, true);
+    WidgetExt::get_style_context (&priority_hint_label).map (|c| c.add_class("dim-label"));
+    GridExt::attach (&content_grid, &priority_hint_label, 0, row + 3, 1, 1);
+
+    let priority_store_clone = priority_store.clone ();
+    let priority_settings_clone = priority_settings.clone ();
+    enabled_renderer.connect_toggled (move |_, path| {
+        if let Some (iter) = TreeModelExt::get_iter (&priority_store_clone, &path) {
+            let enabled = TreeModelExt::get_value (&priority_store_clone, &iter, PRIORITY_ENABLED_COLUMN as i32)
+                .get::<bool> ()
+                .unwrap_or (false);
+            ListStoreExtManual::set_value (&priority_store_clone, &iter, PRIORITY_ENABLED_COLUMN, &(!enabled).to_value ());
+        }
+        save_priority (&priority_settings_clone, &priority_store_clone);
+    });
+
+    // A drag and drop reorder ends with the old row being deleted.
+    priority_store.connect_row_deleted (move |priority_store, _| {
+        save_priority (&priority_settings, priority_store);
+    });
+
+    //tracking parameters stripped before shortening
+    let clean_label = Label::new_with_mnemonic (Some ("Link Cleaning"));
+    WidgetExt::set_halign (&clean_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&clean_label).map (|c| c.add_class("h4"));
+    GridExt::attach (&content_grid, &clean_label, 0, row + 4, 1, 1);
+
+    let clean_settings = Settings::new ("com.github.arshubham.srtnr");
+    let clean_setting_box = Box::new (Orientation::Horizontal, 5);
+    let clean_setting_label = Label::new_with_mnemonic (Some ("Remove tracking parameters"));
+    let clean_setting_switch = Switch::new ();
+    SwitchExt::set_active (&clean_setting_switch, SettingsExt::get_boolean (&clean_settings, "clean-urls"));
+    BoxExt::pack_start (&clean_setting_box, &clean_setting_label, false, false, 0);
+    BoxExt::pack_end (&clean_setting_box, &clean_setting_switch, false, false, 0);
+    GridExt::attach (&content_grid, &clean_setting_box, 0, row + 5, 1, 1);
+
+    let rules_grid = Grid::new ();
+    GridExt::set_column_spacing (&rules_grid, 12);
+    GridExt::set_column_homogeneous (&rules_grid, true);
+    let disabled_rules = SettingsExt::get_strv (&clean_settings, "clean-disabled-rules");
+    for (index, rule) in clean::builtin_rules ().into_iter ().enumerate () {
+        let rule_check = CheckButton::new_with_label (&rule.name);
+        ToggleButtonExt::set_active (&rule_check, !disabled_rules.contains (&rule.id));
+        GridExt::attach (&rules_grid, &rule_check, (index % 2) as i32, (index / 2) as i32, 1, 1);
+
+        let clean_settings = clean_settings.clone ();
+        rule_check.connect_toggled (move |rule_check| {
+            let mut disabled = SettingsExt::get_strv (&clean_settings, "clean-disabled-rules");
+            disabled.retain (|id| *id != rule.id);
+            if !ToggleButtonExt::get_active (rule_check) {
+                disabled.push (rule.id.clone ());
+            }
+            let disabled: Vec<&str> = disabled.iter ().map (|id| id.as_str ()).collect ();
+            SettingsExt::set_strv (&clean_settings, "clean-disabled-rules", &disabled);
+        });
+    }
+    GridExt::attach (&content_grid, &rules_grid, 0, row + 6, 1, 1);
+
+    let rules_grid_clone = rules_grid.clone ();
+    let clean_settings_clone = clean_settings.clone ();
+    WidgetExt::set_sensitive (&rules_grid, SwitchExt::get_active (&clean_setting_switch));
+    clean_setting_switch.connect_state_set (move |_, state| {
+        SettingsExt::set_boolean (&clean_settings_clone, "clean-urls", state);
+        WidgetExt::set_sensitive (&rules_grid_clone, state);
+        gio::signal::Inhibit(false)
+    });
+
+    let custom_rules_view = TextView::new ();
+    if let Some (buffer) = TextViewExt::get_buffer (&custom_rules_view) {
+        TextBufferExt::set_text (&buffer, &SettingsExt::get_strv (&clean_settings, "clean-custom-rules").join ("\n"));
+    }
+    let custom_rules_window = ScrolledWindow::new (None, None);
+    ScrolledWindowExt::set_policy (&custom_rules_window, PolicyType::Automatic, PolicyType::Automatic);
+    ScrolledWindowExt::set_min_content_height (&custom_rules_window, 60);
+    ContainerExt::add (&custom_rules_window, &custom_rules_view);
+    GridExt::attach (&content_grid, &custom_rules_window, 0, row + 7, 1, 1);
+
+    let clean_status_label = Label::new (Some ("Custom rules, one per line: param:name,name* drops query parameters, regex:expression removes matches, regex:expression => replacement replaces them."));
+    WidgetExt::set_halign (&clean_status_label, gtk::Align::Start);
+    LabelExt::set_line_wrap (&clean_status_label, true);
+    WidgetExt::get_style_context (&clean_status_label).map (|c| c.add_class("dim-label"));
+    GridExt::attach (&content_grid, &clean_status_label, 0, row + 8, 1, 1);
+
+    //long links copied in other applications
+    let clipboard_label = Label::new_with_mnemonic (Some ("Clipboard"));
+    WidgetExt::set_halign (&clipboard_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&clipboard_label).map (|c| c.add_class("h4"));
+    GridExt::attach (&content_grid, &clipboard_label, 0, row + 9, 1, 1);
+
+    let clipboard_settings = Settings::new ("com.github.arshubham.srtnr");
+    let clipboard_mode_box = Box::new (Orientation::Horizontal, 5);
+    let clipboard_mode_label = Label::new_with_mnemonic (Some ("Copied long links"));
+    let clipboard_mode_combo = ComboBoxText::new ();
+    ComboBoxTextExt::append (&clipboard_mode_combo, Some ("off"), "Ignore");
+    ComboBoxTextExt::append (&clipboard_mode_combo, Some ("offer"), "Offer to shorten");
+    ComboBoxTextExt::append (&clipboard_mode_combo, Some ("auto"), "Replace with short link");
+    let clipboard_mode = SettingsExt::get_string (&clipboard_settings, "clipboard-watch").unwrap_or_default ();
+    ComboBoxExt::set_active_id (&clipboard_mode_combo, Some (clipboard_mode.as_str ()));
+    BoxExt::pack_start (&clipboard_mode_box, &clipboard_mode_label, false, false, 0);
+    BoxExt::pack_end (&clipboard_mode_box, &clipboard_mode_combo, false, false, 0);
+    GridExt::attach (&content_grid, &clipboard_mode_box, 0, row + 10, 1, 1);
+
+    let clipboard_primary_box = Box::new (Orientation::Horizontal, 5);
+    let clipboard_primary_label = Label::new_with_mnemonic (Some ("Also watch selected text"));
+    let clipboard_primary_switch = Switch::new ();
+    SwitchExt::set_active (&clipboard_primary_switch, SettingsExt::get_boolean (&clipboard_settings, "clipboard-primary"));
+    BoxExt::pack_start (&clipboard_primary_box, &clipboard_primary_label, false, false, 0);
+    BoxExt::pack_end (&clipboard_primary_box, &clipboard_primary_switch, false, false, 0);
+    GridExt::attach (&content_grid, &clipboard_primary_box, 0, row + 11, 1, 1);
+
+    let clipboard_length_box = Box::new (Orientation::Horizontal, 5);
+    let clipboard_length_label = Label::new_with_mnemonic (Some ("Only links with at least this many characters"));
+    let clipboard_length_spin = SpinButton::new_with_range (0.0, 2048.0, 1.0);
+    SpinButtonExt::set_value (&clipboard_length_spin, SettingsExt::get_int (&clipboard_settings, "clipboard-min-length") as f64);
+    BoxExt::pack_start (&clipboard_length_box, &clipboard_length_label, false, false, 0);
+    BoxExt::pack_end (&clipboard_length_box, &clipboard_length_spin, false, false, 0);
+    GridExt::attach (&content_grid, &clipboard_length_box, 0, row + 12, 1, 1);
+
+    let watching = clipboard_mode != "off";
+    WidgetExt::set_sensitive (&clipboard_primary_box, watching);
+    WidgetExt::set_sensitive (&clipboard_length_box, watching);
+
+    let clipboard_settings_clone = clipboard_settings.clone ();
+    clipboard_mode_combo.connect_changed (move |clipboard_mode_combo| {
+        let mode = ComboBoxExt::get_active_id (clipboard_mode_combo).unwrap_or_else (|| "off".to_owned ());
+        SettingsExt::set_string (&clipboard_settings_clone, "clipboard-watch", &mode);
+        WidgetExt::set_sensitive (&clipboard_primary_box, mode != "off");
+        WidgetExt::set_sensitive (&clipboard_length_box, mode != "off");
+    });
+    let clipboard_settings_clone = clipboard_settings.clone ();
+    clipboard_primary_switch.connect_state_set (move |_, state| {
+        SettingsExt::set_boolean (&clipboard_settings_clone, "clipboard-primary", state);
+        gio::signal::Inhibit(false)
+    });
+    clipboard_length_spin.connect_value_changed (move |clipboard_length_spin| {
+        SettingsExt::set_int (&clipboard_settings, "clipboard-min-length", SpinButtonExt::get_value_as_int (clipboard_length_spin));
+    });
+
+    //desktop notifications
+    let notifications_label = Label::new_with_mnemonic (Some ("Notifications"));
+    WidgetExt::set_halign (&notifications_label, gtk::Align::Start);
+    WidgetExt::get_style_context (&notifications_label).map (|c| c.add_class("h4"));
+    GridExt::attach (&content_grid, &notifications_label, 0, row + 13, 1, 1);
+
+    let notification_settings = Settings::new ("com.github.arshubham.srtnr");
+    let notification_switches = [
+        ("notifications", "Show notifications"),
+        ("notifications-when-focused", "Also while the window is focused"),
+        ("notifications-group-batch", "One notification per batch run"),
+    ];
+    let mut notification_boxes = Vec::new ();
+    for (index, &(key, text)) in notification_switches.iter ().enumerate () {
+        let notification_box = Box::new (Orientation::Horizontal, 5);
+        let notification_label = Label::new_with_mnemonic (Some (text));
+        let notification_switch = Switch::new ();
+        SwitchExt::set_active (&notification_switch, SettingsExt::get_boolean (&notification_settings, key));
+        BoxExt::pack_start (&notification_box, &notification_label, false, false, 0);
+        BoxExt::pack_end (&notification_box, &notification_switch, false, false, 0);
+        GridExt::attach (&content_grid, &notification_box, 0, row + 14 + index as i32, 1, 1);
+
+        let notification_settings = notification_settings.clone ();
+        notification_switch.connect_state_set (move |_, state| {
+            SettingsExt::set_boolean (&notification_settings, key, state);
+            gio::signal::Inhibit(false)
+        });
+        notification_boxes.push (notification_box);
+    }
+
+    // The other switches only matter when notifications are shown.
+    for notification_box in &notification_boxes[1..] {
+        WidgetExt::set_sensitive (notification_box, SettingsExt::get_boolean (&notification_settings, "notifications"));
+    }
+    notification_settings.connect_changed (move |notification_settings, key| {
+        if key == "notifications" {
+            for notification_box in &notification_boxes[1..] {
+                WidgetExt::set_sensitive (notification_box, SettingsExt::get_boolean (notification_settings, "notifications"));
+            }
+        }
+    });
+    let content_window = ScrolledWindow::new (None, None);
+    ScrolledWindowExt::set_policy (&content_window, PolicyType::Never, PolicyType::Automatic);
+    WidgetExt::set_vexpand (&content_window, true);
+    ContainerExt::add (&content_window, &content_grid);
+    DialogExt::get_content_area (&pref_dialog).add (&content_window);
     GtkWindowExt::set_transient_for (&pref_dialog, parent);
     let close_button = Button::new_with_label("Close");
-    DialogExt::add_action_widget (&pref_dialog, &close_button, 0);
+    DialogExt::add_action_widget (&pref_dialog, &close_button, CLOSE_RESPONSE);
     DialogExt::get_content_area (&pref_dialog).show_all ();
 
-    
-    let pref_dialog_clone = pref_dialog.clone ();
-    close_button.connect_clicked (move |_| {
-        DialogExt::emit_close (&pref_dialog_clone);
+    // Esc and the close button of the title bar answer with DELETE_EVENT
+    // before the window goes, the Close button with CLOSE_RESPONSE. Both
+    // save, and the dialog stays open when something could not be saved.
+    let saved = Rc::new (Cell::new (true));
+    let saved_clone = saved.clone ();
+    pref_dialog.connect_response (move |pref_dialog, response| {
+        let result = save_credentials (&*store, &credential_entries)
+            .and_then (|()| save_provider_config (&config, &config_inputs))
+            .map_err (|error| LabelExt::set_text (&credentials_status_label, &error))
+            .and_then (|()| {
+                save_custom_rules (&clean_settings, &custom_rules_view)
+                    .map_err (|error| LabelExt::set_text (&clean_status_label, &error))
+            });
+        saved_clone.set (result.is_ok ());
+        if result.is_ok () && response == CLOSE_RESPONSE {
+            WidgetExt::destroy (pref_dialog);
+        }
     });
+    pref_dialog.connect_delete_event (move |_, _| gio::signal::Inhibit (!saved.get ()));
 
     PrefDialogUi {
              pref_dialog,
@@ -74,4 +518,153 @@ impl PrefDialogUi {
         WidgetExt::show_now (&self.pref_dialog);
     }
 
-}
\ No newline at end of file
+}
+
+const CLOSE_RESPONSE: i32 = 0;
+
+const PRIORITY_ID_COLUMN: u32 = 0;
+const PRIORITY_ENABLED_COLUMN: u32 = 1;
+const PRIORITY_NAME_COLUMN: u32 = 2;
+
+/// The providers of `priority` ticked and in that order, then every other
+/// provider still in service, unticked.
+fn priority_store (shortener: &Shortener, priority: &[String]) -> ListStore {
+    let store = ListStore::new (&[Type::String, Type::Bool, Type::String]);
+    let columns = [PRIORITY_ID_COLUMN, PRIORITY_ENABLED_COLUMN, PRIORITY_NAME_COLUMN];
+
+    for id in priority {
+        if let Some (provider) = shortener.provider (id) {
+            store.insert_with_values (None, &columns, &[&provider.id (), &true, &provider_label_text (&*provider)]);
+        }
+    }
+    for provider in shortener.providers ().iter () {
+        let listed = priority.iter ().any (|id| id == provider.id ());
+        if !listed && core::retired_reason (provider.id ()).is_none () {
+            store.insert_with_values (None, &columns, &[&provider.id (), &false, &provider_label_text (&**provider)]);
+        }
+    }
+    store
+}
+
+/// Writes the ticked providers, in their current order, to the settings.
+fn save_priority (settings: &Settings, store: &ListStore) {
+    let mut priority = Vec::new ();
+    if let Some (iter) = TreeModelExt::get_iter_first (store) {
+        loop {
+            let enabled = TreeModelExt::get_value (store, &iter, PRIORITY_ENABLED_COLUMN as i32).get::<bool> ();
+            let id = TreeModelExt::get_value (store, &iter, PRIORITY_ID_COLUMN as i32).get::<String> ();
+            if let (Some (true), Some (id)) = (enabled, id) {
+                priority.push (id);
+            }
+            if !TreeModelExt::iter_next (store, &iter) {
+                break;
+            }
+        }
+    }
+
+    let priority: Vec<&str> = priority.iter ().map (|id| id.as_str ()).collect ();
+    SettingsExt::set_strv (settings, "provider-priority", &priority);
+}
+
+/// Stores the custom cleaning rules, one per line, unless one of them is
+/// invalid.
+fn save_custom_rules (settings: &Settings, view: &TextView) -> Result<(), String> {
+    let text = TextViewExt::get_buffer (view)
+        .and_then (|buffer| {
+            let (start, end) = TextBufferExt::get_bounds (&buffer);
+            TextBufferExt::get_text (&buffer, &start, &end, false)
+        })
+        .unwrap_or_default ();
+
+    let rules: Vec<&str> = text.lines ()
+        .map (|line| line.trim ())
+        .filter (|line| !line.is_empty ())
+        .collect ();
+    for rule in &rules {
+        Rule::parse (rule).map_err (|error| error.to_string ())?;
+    }
+    SettingsExt::set_strv (settings, "clean-custom-rules", &rules);
+    Ok (())
+}
+
+/// Stores the tokens typed in the dialog, an emptied entry removes the token.
+fn save_credentials (store: &dyn CredentialStore, entries: &[(String, Entry)]) -> Result<(), String> {
+    for &(ref provider, ref entry) in entries {
+        let secret = EntryExt::get_text (entry).unwrap_or_default ();
+        let result = if secret.trim ().is_empty () {
+            store.delete (provider)
+        } else {
+            store.set (provider, secret.trim ())
+        };
+        result.map_err (|error| format!("Could not save the token for {}: {}", provider, error))?;
+    }
+    Ok (())
+}
+
+/// The names of the custom providers, or where to define them.
+fn definitions_text (definitions: &DefinitionFile) -> String {
+    match definitions.load () {
+        Ok (ref all) if all.is_empty () => format!("None yet, define them in {} or import a file.", definitions.path ().display ()),
+        Ok (all) => all.iter ().map (|definition| definition.name.as_str ()).collect::<Vec<_>> ().join (", "),
+        Err (error) => error.to_string (),
+    }
+}
+
+/// Asks for a definitions file to import, or where to export them.
+fn choose_definitions (parent: &Dialog, action: FileChooserAction) -> Option<PathBuf> {
+    let (title, accept) = if action == FileChooserAction::Save { ("Export Providers", "Save") } else { ("Import Providers", "Open") };
+    let dialog = FileChooserDialog::new (Some (title), Some (parent), action);
+    DialogExt::add_button (&dialog, "Cancel", ResponseType::Cancel.to_glib ());
+    DialogExt::add_button (&dialog, accept, ResponseType::Accept.to_glib ());
+    if action == FileChooserAction::Save {
+        FileChooserExt::set_do_overwrite_confirmation (&dialog, true);
+        FileChooserExt::set_current_name (&dialog, "custom-providers.json");
+    }
+
+    let path = if DialogExt::run (&dialog) == ResponseType::Accept.to_glib () {
+        FileChooserExt::get_filename (&dialog)
+    } else {
+        None
+    };
+    WidgetExt::destroy (&dialog);
+    path
+}
+
+/// One "name: guid" line per group of the bit.ly account, with its branded
+/// domains.
+fn account_text (account: &BitlyAccount) -> String {
+    let lines: Vec<String> = account.groups.iter ()
+        .map (|group| {
+            if group.domains.is_empty () {
+                format!("{}: {}", group.name, group.guid)
+            } else {
+                format!("{}: {} ({})", group.name, group.guid, group.domains.join (", "))
+            }
+        })
+        .collect ();
+    if lines.is_empty () {
+        "The account has no groups".to_owned ()
+    } else {
+        lines.join ("\n")
+    }
+}
+
+/// Where the value of a provider setting is typed or picked.
+enum FieldInput {
+    Text (Entry),
+    Choice (ComboBoxText),
+}
+
+/// Stores the provider settings of the dialog, an emptied entry removes the
+/// setting.
+fn save_provider_config (config: &ProviderConfig, inputs: &[(String, &'static str, FieldInput)]) -> Result<(), String> {
+    for &(ref provider, key, ref input) in inputs {
+        let value = match *input {
+            FieldInput::Text (ref entry) => EntryExt::get_text (entry).unwrap_or_default (),
+            FieldInput::Choice (ref combo) => ComboBoxExt::get_active_id (combo).unwrap_or_default (),
+        };
+        config.set (provider, key, &value)
+            .map_err (|error| format!("Could not save the settings of {}: {}", provider, error))?;
+    }
+    Ok (())
+}
@@ -0,0 +1,359 @@
+extern crate gdk;
+extern crate gdk_pixbuf;
+extern crate gtk;
+use gtk::IsA;
+
+use gtk::prelude::*;
+use gtk::{Grid, Box, Orientation, Image, Label, Button, MenuButton, Popover, ComboBoxText, SpinButton, ColorButton, Clipboard, EventBox, Window, WindowType, FileChooserDialog, FileChooserAction, ResponseType, IconSize, Inhibit};
+use gtk::{GridExt, WidgetExt, BoxExt, GtkWindowExt, ContainerExt, ButtonExt, LabelExt, ImageExt, MenuButtonExt, ComboBoxExt, ComboBoxTextExt, SpinButtonExt, ColorChooserExt, ColorButtonExt, ClipboardExt, DialogExt, FileChooserExt, StyleContextExt};
+use gdk_pixbuf::{Colorspace, Pixbuf};
+use gio::Settings;
+use gio::SettingsExt;
+use glib::translate::ToGlib;
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use srtnr::core::qr::{ErrorCorrection, QrCode, QrStyle, Rgb};
+
+/// Side of the code shown in the main window, in pixels.
+const PREVIEW_SIZE: usize = 160;
+/// Pixels per module of saved and copied PNGs, large enough for print.
+const EXPORT_SCALE: usize = 12;
+/// Used when the screen size is unknown.
+const PRESENT_SIZE: usize = 800;
+
+/// The QR code of the last short link, with its options and exports.
+pub struct QrPanelUi {
+    pub qr_panel: Grid,
+    link: Rc<RefCell<Option<String>>>,
+    image: Image,
+    status_label: Label,
+    settings: Settings,
+}
+
+impl QrPanelUi {
+    pub fn new<P>(parent: &P, settings: &Settings) -> QrPanelUi
+    where P: IsA<gtk::Window> + Clone + 'static, {
+    let qr_panel = Grid::new ();
+    GridExt::set_row_spacing (&qr_panel, 6);
+    WidgetExt::set_halign (&qr_panel, gtk::Align::Center);
+    WidgetExt::set_margin_top (&qr_panel, 20);
+    // Stays hidden until there is a link to show.
+    WidgetExt::set_no_show_all (&qr_panel, true);
+
+    let image = Image::new ();
+    GridExt::attach (&qr_panel, &image, 0, 0, 1, 1);
+
+    //copy, save, present and options
+    let buttons_box = Box::new (Orientation::Horizontal, 0);
+    WidgetExt::get_style_context (&buttons_box).map (|c| c.add_class("linked"));
+    WidgetExt::set_halign (&buttons_box, gtk::Align::Center);
+
+    let copy_button = Button::new_from_icon_name ("edit-copy-symbolic", IconSize::Button.into ());
+    WidgetExt::set_tooltip_text (&copy_button, "Copy the QR code image");
+
+    let save_button = MenuButton::new ();
+    ButtonExt::set_image (&save_button, &Image::new_from_icon_name ("document-save-symbolic", IconSize::Button.into ()));
+    WidgetExt::set_tooltip_text (&save_button, "Save the QR code");
+    let save_popover = Popover::new (Some (&save_button));
+    let save_box = Box::new (Orientation::Vertical, 0);
+    WidgetExt::set_margin_top (&save_box, 6);
+    WidgetExt::set_margin_bottom (&save_box, 6);
+    let save_png_button = Button::new_with_label ("Save as PNG…");
+    let save_svg_button = Button::new_with_label ("Save as SVG…");
+    for button in &[&save_png_button, &save_svg_button] {
+        ButtonExt::set_relief (*button, gtk::ReliefStyle::None);
+        BoxExt::pack_start (&save_box, *button, false, false, 0);
+    }
+    ContainerExt::add (&save_popover, &save_box);
+    WidgetExt::show_all (&save_box);
+    MenuButtonExt::set_popover (&save_button, Some (&save_popover));
+
+    let present_button = Button::new_from_icon_name ("view-fullscreen-symbolic", IconSize::Button.into ());
+    WidgetExt::set_tooltip_text (&present_button, "Show the QR code full screen");
+
+    let options_button = MenuButton::new ();
+    ButtonExt::set_image (&options_button, &Image::new_from_icon_name ("emblem-system-symbolic", IconSize::Button.into ()));
+    WidgetExt::set_tooltip_text (&options_button, "QR code options");
+    let options_popover = Popover::new (Some (&options_button));
+    MenuButtonExt::set_popover (&options_button, Some (&options_popover));
+
+    BoxExt::pack_start (&buttons_box, &copy_button, false, false, 0);
+    BoxExt::pack_start (&buttons_box, &save_button, false, false, 0);
+    BoxExt::pack_start (&buttons_box, &present_button, false, false, 0);
+    BoxExt::pack_start (&buttons_box, &options_button, false, false, 0);
+    GridExt::attach (&qr_panel, &buttons_box, 0, 1, 1, 1);
+
+    let status_label = Label::new ("");
+    LabelExt::set_line_wrap (&status_label, true);
+    LabelExt::set_max_width_chars (&status_label, 24);
+    WidgetExt::get_style_context (&status_label).map (|c| c.add_class("dim-label"));
+    GridExt::attach (&qr_panel, &status_label, 0, 2, 1, 1);
+
+    //options, stored right away so the next link uses them too
+    let options_grid = Grid::new ();
+    GridExt::set_row_spacing (&options_grid, 6);
+    GridExt::set_column_spacing (&options_grid, 12);
+    WidgetExt::set_margin_top (&options_grid, 10);
+    WidgetExt::set_margin_start (&options_grid, 10);
+    WidgetExt::set_margin_end (&options_grid, 10);
+    WidgetExt::set_margin_bottom (&options_grid, 10);
+
+    let ecc_combobox = ComboBoxText::new ();
+    ComboBoxTextExt::append (&ecc_combobox, Some ("L"), "Low (7%)");
+    ComboBoxTextExt::append (&ecc_combobox, Some ("M"), "Medium (15%)");
+    ComboBoxTextExt::append (&ecc_combobox, Some ("Q"), "Quartile (25%)");
+    ComboBoxTextExt::append (&ecc_combobox, Some ("H"), "High (30%)");
+    ComboBoxExt::set_active_id (&ecc_combobox, Some (error_correction (settings).letter ()));
+
+    let current_style = style (settings);
+    let margin_spin = SpinButton::new_with_range (0.0, 16.0, 1.0);
+    SpinButtonExt::set_value (&margin_spin, current_style.margin as f64);
+    let foreground_button = ColorButton::new_with_rgba (&to_rgba (current_style.foreground));
+    let background_button = ColorButton::new_with_rgba (&to_rgba (current_style.background));
+
+    let option_rows: [(&str, &gtk::Widget); 4] = [
+        ("Error correction", ecc_combobox.upcast_ref ()),
+        ("Margin", margin_spin.upcast_ref ()),
+        ("Foreground", foreground_button.upcast_ref ()),
+        ("Background", background_button.upcast_ref ()),
+    ];
+    for (row, &(text, widget)) in option_rows.iter ().enumerate () {
+        let option_label = Label::new (Some (text));
+        WidgetExt::set_halign (&option_label, gtk::Align::Start);
+        GridExt::attach (&options_grid, &option_label, 0, row as i32, 1, 1);
+        GridExt::attach (&options_grid, widget, 1, row as i32, 1, 1);
+    }
+    ContainerExt::add (&options_popover, &options_grid);
+    WidgetExt::show_all (&options_grid);
+
+    let panel = QrPanelUi {
+        qr_panel,
+        link: Rc::new (RefCell::new (None)),
+        image,
+        status_label,
+        settings: settings.clone (),
+    };
+
+    let redraw = panel.redraw_fn ();
+    let settings_clone = settings.clone ();
+    ecc_combobox.connect_changed (move |ecc_combobox| {
+        if let Some (letter) = ComboBoxExt::get_active_id (ecc_combobox) {
+            SettingsExt::set_string (&settings_clone, "qr-error-correction", &letter);
+            redraw ();
+        }
+    });
+
+    let redraw = panel.redraw_fn ();
+    let settings_clone = settings.clone ();
+    margin_spin.connect_value_changed (move |margin_spin| {
+        SettingsExt::set_int (&settings_clone, "qr-margin", SpinButtonExt::get_value_as_int (margin_spin));
+        redraw ();
+    });
+
+    for &(button, key) in &[(&foreground_button, "qr-foreground"), (&background_button, "qr-background")] {
+        let redraw = panel.redraw_fn ();
+        let settings_clone = settings.clone ();
+        button.connect_color_set (move |button| {
+            SettingsExt::set_string (&settings_clone, key, &from_rgba (&ColorChooserExt::get_rgba (button)).to_hex ());
+            redraw ();
+        });
+    }
+
+    let link = panel.link.clone ();
+    let settings_clone = settings.clone ();
+    let status_label = panel.status_label.clone ();
+    copy_button.connect_clicked (move |button| {
+        let pixbuf = match current_code (&link, &settings_clone, &status_label) {
+            Some ((code, style)) => to_pixbuf (&code, &style, EXPORT_SCALE),
+            None => return,
+        };
+        if let Some (gclipboard) = WidgetExt::get_display (button).and_then (|display| Clipboard::get_default (&display)) {
+            ClipboardExt::set_image (&gclipboard, &pixbuf);
+            LabelExt::set_text (&status_label, "QR code copied");
+        }
+    });
+
+    let export_formats = [(&save_png_button, "png"), (&save_svg_button, "svg")];
+    for &(button, extension) in &export_formats {
+        let parent = parent.clone ();
+        let link = panel.link.clone ();
+        let settings_clone = settings.clone ();
+        let status_label = panel.status_label.clone ();
+        let save_popover = save_popover.clone ();
+        button.connect_clicked (move |_| {
+            WidgetExt::hide (&save_popover);
+            let (code, style) = match current_code (&link, &settings_clone, &status_label) {
+                Some (code) => code,
+                None => return,
+            };
+            let path = match choose_file (&parent, link.borrow ().as_ref ().map (|link| link.as_str ()).unwrap_or ("qr"), extension) {
+                Some (path) => path,
+                None => return,
+            };
+            let contents = if extension == "svg" {
+                code.to_svg (&style).into_bytes ()
+            } else {
+                code.to_png (&style, EXPORT_SCALE)
+            };
+            match fs::write (&path, contents) {
+                Ok (()) => LabelExt::set_text (&status_label, &format!("Saved {}", path.display ())),
+                Err (error) => LabelExt::set_text (&status_label, &format!("Could not save {}: {}", path.display (), error)),
+            }
+        });
+    }
+
+    let parent = parent.clone ();
+    let link = panel.link.clone ();
+    let settings_clone = settings.clone ();
+    let status_label = panel.status_label.clone ();
+    present_button.connect_clicked (move |_| {
+        if let Some ((code, style)) = current_code (&link, &settings_clone, &status_label) {
+            present (&parent, &code, &style);
+        }
+    });
+
+    panel
+    }
+
+    /// Shows the code of `link`, or hides the panel for `None`.
+    pub fn set_link (&self, link: Option<&str>) {
+        *self.link.borrow_mut () = link.map (|link| link.to_owned ());
+        LabelExt::set_text (&self.status_label, "");
+        self.redraw_fn () ();
+    }
+
+    fn redraw_fn (&self) -> impl Fn () {
+        let qr_panel = self.qr_panel.clone ();
+        let link = self.link.clone ();
+        let image = self.image.clone ();
+        let status_label = self.status_label.clone ();
+        let settings = self.settings.clone ();
+        move || {
+            if link.borrow ().is_none () {
+                ImageExt::clear (&image);
+                WidgetExt::hide (&qr_panel);
+                return;
+            }
+            if let Some ((code, style)) = current_code (&link, &settings, &status_label) {
+                let scale = (PREVIEW_SIZE / code.width (&style)).max (1);
+                ImageExt::set_from_pixbuf (&image, Some (&to_pixbuf (&code, &style, scale)));
+            }
+            WidgetExt::set_no_show_all (&qr_panel, false);
+            WidgetExt::show_all (&qr_panel);
+        }
+    }
+}
+
+fn error_correction (settings: &Settings) -> ErrorCorrection {
+    SettingsExt::get_string (settings, "qr-error-correction")
+        .and_then (|letter| ErrorCorrection::from_letter (&letter))
+        .unwrap_or (ErrorCorrection::Medium)
+}
+
+fn style (settings: &Settings) -> QrStyle {
+    let default = QrStyle::default ();
+    let colour = |key: &str, default: Rgb| {
+        SettingsExt::get_string (settings, key)
+            .and_then (|hex| Rgb::parse (&hex))
+            .unwrap_or (default)
+    };
+    QrStyle {
+        margin: SettingsExt::get_int (settings, "qr-margin").max (0) as usize,
+        foreground: colour ("qr-foreground", default.foreground),
+        background: colour ("qr-background", default.background),
+    }
+}
+
+/// Encodes the current link with the stored options, reporting failures in
+/// `status_label`.
+fn current_code (link: &Rc<RefCell<Option<String>>>, settings: &Settings, status_label: &Label) -> Option<(QrCode, QrStyle)> {
+    let link = link.borrow ();
+    let link = link.as_ref ()?;
+    match QrCode::encode (link, error_correction (settings)) {
+        Ok (code) => Some ((code, style (settings))),
+        Err (error) => {
+            LabelExt::set_text (status_label, &error.to_string ());
+            None
+        },
+    }
+}
+
+fn to_pixbuf (code: &QrCode, style: &QrStyle, scale: usize) -> Pixbuf {
+    let (side, pixels) = code.to_rgb (style, scale);
+    Pixbuf::new_from_vec (pixels, Colorspace::Rgb, false, 8, side as i32, side as i32, side as i32 * 3)
+}
+
+fn to_rgba (colour: Rgb) -> gdk::RGBA {
+    gdk::RGBA {
+        red: f64::from (colour.0) / 255.0,
+        green: f64::from (colour.1) / 255.0,
+        blue: f64::from (colour.2) / 255.0,
+        alpha: 1.0,
+    }
+}
+
+fn from_rgba (rgba: &gdk::RGBA) -> Rgb {
+    let channel = |value: f64| (value.max (0.0).min (1.0) * 255.0).round () as u8;
+    Rgb (channel (rgba.red), channel (rgba.green), channel (rgba.blue))
+}
+
+/// Asks where to save, suggesting `is.gd-abc.png` for `https://is.gd/abc`.
+fn choose_file<P: IsA<gtk::Window>> (parent: &P, link: &str, extension: &str) -> Option<PathBuf> {
+    let title = format!("Save QR Code as {}", extension.to_uppercase ());
+    let dialog = FileChooserDialog::new (Some (title.as_str ()), Some (parent), FileChooserAction::Save);
+    DialogExt::add_button (&dialog, "Cancel", ResponseType::Cancel.to_glib ());
+    DialogExt::add_button (&dialog, "Save", ResponseType::Accept.to_glib ());
+    FileChooserExt::set_do_overwrite_confirmation (&dialog, true);
+
+    let stem: String = link.splitn (2, "://").last ().unwrap_or (link)
+        .trim_end_matches ('/')
+        .chars ()
+        .map (|c| if c.is_ascii_alphanumeric () || c == '.' { c } else { '-' })
+        .collect ();
+    FileChooserExt::set_current_name (&dialog, format!("{}.{}", stem, extension));
+
+    let path = if DialogExt::run (&dialog) == ResponseType::Accept.to_glib () {
+        FileChooserExt::get_filename (&dialog)
+    } else {
+        None
+    };
+    WidgetExt::destroy (&dialog);
+    path
+}
+
+/// A fullscreen window with the code as large as the screen allows, closed
+/// by a click or Escape.
+fn present<P: IsA<gtk::Window>> (parent: &P, code: &QrCode, style: &QrStyle) {
+    let present_window = Window::new (WindowType::Toplevel);
+    GtkWindowExt::set_transient_for (&present_window, parent);
+    GtkWindowExt::set_modal (&present_window, true);
+
+    let screen_size = WidgetExt::get_screen (&present_window)
+        .map (|screen| screen.get_width ().min (screen.get_height ()) as usize)
+        .unwrap_or (PRESENT_SIZE);
+    let scale = (screen_size * 9 / 10 / code.width (style)).max (1);
+    let image = Image::new_from_pixbuf (Some (&to_pixbuf (code, style, scale)));
+
+    let event_box = EventBox::new ();
+    ContainerExt::add (&event_box, &image);
+    ContainerExt::add (&present_window, &event_box);
+
+    let present_window_clone = present_window.clone ();
+    event_box.connect_button_press_event (move |_, _| {
+        WidgetExt::destroy (&present_window_clone);
+        Inhibit (true)
+    });
+    present_window.connect_key_press_event (|present_window, event| {
+        if event.get_keyval () == gdk::enums::key::Escape {
+            WidgetExt::destroy (present_window);
+            return Inhibit (true);
+        }
+        Inhibit (false)
+    });
+
+    GtkWindowExt::fullscreen (&present_window);
+    WidgetExt::show_all (&present_window);
+}
@@ -0,0 +1,182 @@
+extern crate glib;
+extern crate gtk;
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use gtk::Continue;
+
+use srtnr::core::CancelFlag;
+
+thread_local! {
+    /// Jobs waiting for the main loop to read what their thread sent, by
+    /// key. Dropping one drops its receivers.
+    static PENDING: RefCell<HashMap<u64, Box<dyn FnMut () -> bool>>> = RefCell::new (HashMap::new ());
+    static NEXT_KEY: Cell<u64> = Cell::new (0);
+}
+
+/// Runs blocking jobs (network requests) off the GTK main thread and hands
+/// their results back to a callback on the main loop.
+///
+/// Only one job is "current" at a time: spawning a new job or calling
+/// `cancel` replaces the running one and sets its `CancelFlag`. The job
+/// stops at its next check of the flag, a request it already sent runs
+/// until its answer or its timeout. Either way its result is dropped
+/// instead of being delivered.
+///
+/// A job that panics has the handler set with `on_panic` called instead
+/// of its `on_done`, so the caller can leave its busy state.
+#[derive(Clone)]
+pub struct Worker {
+    current: Rc<RefCell<Option<(u64, CancelFlag)>>>,
+    on_panic: Rc<RefCell<Option<Rc<dyn Fn (String)>>>>,
+}
+
+/// Hands messages of a job to the main loop.
+pub struct Progress<P> {
+    key: u64,
+    sender: Sender<P>,
+}
+
+impl<P: Send + 'static> Progress<P> {
+    /// False once the job got cancelled or replaced, which is the job's
+    /// cue to stop early.
+    pub fn send (&self, message: P) -> bool {
+        let sent = self.sender.send (message).is_ok ();
+        if sent {
+            wake (self.key);
+        }
+        sent
+    }
+}
+
+impl Worker {
+    pub fn new () -> Worker {
+        Worker {
+            current: Rc::new (RefCell::new (None)),
+            on_panic: Rc::new (RefCell::new (None)),
+        }
+    }
+
+    /// Has `handler` called on the main loop with the message of a job
+    /// that panicked. Shared by the clones of this worker.
+    pub fn on_panic<H> (&self, handler: H)
+    where
+        H: Fn (String) + 'static,
+    {
+        *self.on_panic.borrow_mut () = Some (Rc::new (handler));
+    }
+
+    /// Starts `job` on a new thread with the flag `cancel` sets. `on_done`
+    /// is called on the main loop with the result, unless the job got
+    /// cancelled or replaced before.
+    pub fn spawn<T, F, C> (&self, job: F, on_done: C)
+    where
+        T: Send + 'static,
+        F: FnOnce (CancelFlag) -> T + Send + 'static,
+        C: Fn (T) + 'static,
+    {
+        self.spawn_with_progress (move |_: Progress<()>, cancel| job (cancel), |_| {}, on_done);
+    }
+
+    /// Like `spawn`, but `job` also gets a `Progress` whose messages are
+    /// handed to `on_progress` on the main loop.
+    pub fn spawn_with_progress<T, P, F, G, C> (&self, job: F, on_progress: G, on_done: C)
+    where
+        T: Send + 'static,
+        P: Send + 'static,
+        F: FnOnce (Progress<P>, CancelFlag) -> T + Send + 'static,
+        G: Fn (P) + 'static,
+        C: Fn (T) + 'static,
+    {
+        self.cancel ();
+
+        let key = NEXT_KEY.with (|next| {
+            next.set (next.get () + 1);
+            next.get ()
+        });
+        let cancel = CancelFlag::new ();
+        *self.current.borrow_mut () = Some ((key, cancel.clone ()));
+
+        let (sender, receiver): (Sender<thread::Result<T>>, Receiver<thread::Result<T>>) = channel ();
+        let (progress_sender, progress_receiver) = channel ();
+        let current = self.current.clone ();
+        let on_panic = self.on_panic.clone ();
+        PENDING.with (|pending| {
+            pending.borrow_mut ().insert (key, Box::new (move || {
+                // Replaced by a callback of its own.
+                if current.borrow ().as_ref ().map (|&(current, _)| current) != Some (key) {
+                    return true;
+                }
+                while let Ok (progress) = progress_receiver.try_recv () {
+                    on_progress (progress);
+                }
+                match receiver.try_recv () {
+                    Ok (Ok (result)) => {
+                        *current.borrow_mut () = None;
+                        on_done (result);
+                        true
+                    },
+                    Ok (Err (panic)) => {
+                        *current.borrow_mut () = None;
+                        let on_panic = on_panic.borrow ().clone ();
+                        if let Some (on_panic) = on_panic {
+                            on_panic (panic_message (&*panic));
+                        }
+                        true
+                    },
+                    Err (TryRecvError::Empty) => false,
+                    Err (TryRecvError::Disconnected) => true,
+                }
+            }));
+        });
+
+        let progress = Progress { key, sender: progress_sender };
+        thread::spawn (move || {
+            // Caught so that the main loop still hears of a job that panicked.
+            let result = panic::catch_unwind (AssertUnwindSafe (move || job (progress, cancel)));
+            // The receiver is gone when the job was replaced, nothing to do then.
+            let _ = sender.send (result);
+            wake (key);
+        });
+    }
+
+    /// Stops the current job, its result will never be delivered.
+    pub fn cancel (&self) {
+        if let Some ((key, cancel)) = self.current.borrow_mut ().take () {
+            cancel.cancel ();
+            PENDING.with (|pending| pending.borrow_mut ().remove (&key));
+        }
+    }
+}
+
+/// Has the main loop read what the thread of job `key` sent.
+fn wake (key: u64) {
+    glib::idle_add (move || {
+        deliver (key);
+        Continue (false)
+    });
+}
+
+/// Runs on the main loop, where `PENDING` lives.
+fn deliver (key: u64) {
+    // Taken out while it runs, as callbacks may spawn or cancel jobs.
+    let job = PENDING.with (|pending| pending.borrow_mut ().remove (&key));
+    if let Some (mut job) = job {
+        if !job () {
+            PENDING.with (|pending| pending.borrow_mut ().insert (key, job));
+        }
+    }
+}
+
+/// The text a job panicked with, if it is text.
+fn panic_message (panic: &(dyn Any + Send)) -> String {
+    panic.downcast_ref::<&str> ().map (|message| message.to_string ())
+        .or_else (|| panic.downcast_ref::<String> ().cloned ())
+        .unwrap_or_else (|| "unknown error".to_owned ())
+}
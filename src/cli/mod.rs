@@ -0,0 +1,830 @@
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use srtnr::core::{self, BitlyProvider, Clipboard, CommandClipboard, ExpandOptions, LinkOptions, NoClipboard, ReqwestClient, ShlinkProvider, ShortenError, ShortenRequest, Shortener, YourlsLink, YourlsProvider};
+use srtnr::core::batch::{self, Format, Table};
+use srtnr::core::clean::{self, Cleaner};
+use srtnr::core::config::ProviderConfig;
+use srtnr::core::credentials::{self, CredentialStore};
+use srtnr::core::custom::DefinitionFile;
+use srtnr::core::history::History;
+use gio::{Settings, SettingsExt};
+use gio_sys;
+use glib_sys;
+use serde_json;
+
+const USAGE: &str = "Usage:
+  com.github.arshubham.srtnr shorten <url> [--provider <id>] [--fallback <id>,...] [--alias <slug>] [--title <text>] [--tags <tag>,...] [--domain <host>] [--valid-until <date>] [--max-visits <n>] [--find-if-exists] [--password <text>] [--expire-in <duration>] [--description <text>] [--unwrap] [--probe] [--keep <rule>,...] [--no-clean] [--timeout <seconds>] [--json] [--no-clipboard]
+  com.github.arshubham.srtnr batch <file> [--column <name|number>] [--provider <id>] [--fallback <id>,...] [--output <file>] [--probe] [--keep <rule>,...] [--no-clean] [--json]
+  com.github.arshubham.srtnr expand <url> [--max-redirects <n>] [--no-meta-refresh] [--timeout <seconds>] [--json]
+  com.github.arshubham.srtnr providers [--json]
+  com.github.arshubham.srtnr yourls expand|url-stats <url> [--timeout <seconds>] [--json]
+  com.github.arshubham.srtnr yourls db-stats [--timeout <seconds>] [--json]
+  com.github.arshubham.srtnr shlink domains [--timeout <seconds>] [--json]
+  com.github.arshubham.srtnr bitly groups [--timeout <seconds>] [--json]
+  com.github.arshubham.srtnr custom list [--json]
+  com.github.arshubham.srtnr custom import|export <file>
+
+Without a command the window is opened.
+
+Without --provider, shorten and batch use the provider last picked in the
+window. --provider automatic tries the usable providers in the order set in
+the preferences, then the ones given to --fallback.
+
+batch reads a CSV or TSV file with a header row, or a list with one URL per
+line, and writes it back with short_url, provider and error columns.
+
+URLs typed without a scheme get https://, --probe falls back to http:// for
+sites which do not answer over HTTPS.
+
+Links wrapped by a redirector (Google results, Outlook Safe Links, Facebook,
+Slack, ...) are only replaced by their destination with --unwrap.
+
+Tracking parameters (utm_*, fbclid, ...) are removed before shortening.
+--keep leaves the parameters of the given rules in place, --no-clean all of
+them. Rules: utm, facebook, google, mailchimp, microsoft, hubspot, marketo,
+social.
+
+--alias asks for a custom ending of the short URL, see providers for the
+services which take one. --title is kept by the providers which store one
+(YOURLS). --tags, --domain, --valid-until (ISO 8601, e.g.
+2030-01-31T00:00:00Z), --max-visits and --find-if-exists, which answers with
+an existing link of the URL instead of making a new one, apply to Shlink.
+--password, --expire-in (e.g. \"2 hours\" or \"7 days\"), --description,
+--domain and --find-if-exists apply to Kutt. --domain also picks a branded
+domain of bit.ly.
+
+yourls asks the YOURLS instance set up in the preferences where one of its
+links leads (expand), how often it was clicked (url-stats) or how many links
+and clicks it has (db-stats). shlink domains lists the domains the Shlink
+instance serves short links on. bitly groups lists the groups of the bit.ly
+account with their branded domains, which are then offered in the provider
+list of the window.
+
+custom manages the providers defined in
+~/.config/com.github.arshubham.srtnr/custom-providers.json: list shows them,
+import adds the definitions of a file (replacing the ones with the same id)
+and export writes them all to a file to share them.
+
+expand prints every hop as \"<status> <url>\", the destination last.
+
+Exit codes:
+  0  success
+  1  unexpected error
+  2  invalid arguments
+  3  invalid URL
+  4  unknown provider, or no usable provider for automatic
+  5  missing or unreadable credentials
+  6  service unavailable
+  7  unexpected answer from the service
+  8  some rows of a batch failed
+  9  redirect loop or too many redirects
+  10 custom alias taken or not accepted
+  11 limits of the account reached";
+
+const DEFAULT_TIMEOUT: u64 = 3;
+
+const SCHEMA_ID: &str = "com.github.arshubham.srtnr";
+
+const EXIT_OK: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_PARTIAL: i32 = 8;
+
+/// Whether `args` ask for the command line instead of the window.
+pub fn handles (args: &[String]) -> bool {
+    match args.get (1).map (|arg| arg.as_str ()) {
+        Some ("shorten") | Some ("batch") | Some ("expand") | Some ("providers") | Some ("yourls") | Some ("shlink") | Some ("bitly") | Some ("custom") | Some ("help") => true,
+        _ => false,
+    }
+}
+
+/// Runs the command in `args` and returns the process exit code. Never
+/// touches GTK, so it works without a display.
+pub fn run (args: &[String]) -> i32 {
+    let options = match Options::parse (&args[1..]) {
+        Ok (options) => options,
+        Err (message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        },
+    };
+
+    match options.command.as_str () {
+        "shorten" => shorten (&options),
+        "batch" => batch (&options),
+        "expand" => expand (&options),
+        "providers" => providers (&options),
+        "yourls" => yourls (&options),
+        "shlink" => shlink (&options),
+        "bitly" => bitly (&options),
+        "custom" => custom (&options),
+        _ => {
+            println!("{}", USAGE);
+            EXIT_OK
+        },
+    }
+}
+
+struct Options {
+    command: String,
+    /// What `yourls`, `shlink` or `bitly` asks the service, or what
+    /// `custom` does with the definitions.
+    action: Option<String>,
+    url: Option<String>,
+    /// `None` without `--provider`, see `request`.
+    provider: Option<String>,
+    fallbacks: Vec<String>,
+    alias: Option<String>,
+    link: LinkOptions,
+    probe: bool,
+    clean: bool,
+    unwrap: bool,
+    keep: Vec<String>,
+    column: Option<String>,
+    output: Option<String>,
+    expand: ExpandOptions,
+    timeout: u64,
+    json: bool,
+    clipboard: bool,
+}
+
+impl Options {
+    fn parse (args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            command: args[0].clone (),
+            action: None,
+            url: None,
+            provider: None,
+            fallbacks: Vec::new (),
+            alias: None,
+            link: LinkOptions::default (),
+            probe: false,
+            clean: true,
+            unwrap: false,
+            keep: Vec::new (),
+            column: None,
+            output: None,
+            expand: ExpandOptions::default (),
+            timeout: DEFAULT_TIMEOUT,
+            json: false,
+            clipboard: true,
+        };
+
+        let mut args = args[1..].iter ();
+        while let Some (arg) = args.next () {
+            match arg.as_str () {
+                "--provider" => {
+                    options.provider = Some (args.next ().ok_or ("--provider needs a provider id")?.clone ());
+                },
+                "--fallback" => {
+                    let fallbacks = args.next ().ok_or ("--fallback needs provider ids")?;
+                    options.fallbacks = fallbacks.split (',')
+                        .map (|id| id.trim ().to_owned ())
+                        .filter (|id| !id.is_empty ())
+                        .collect ();
+                },
+                "--alias" => {
+                    let alias = args.next ().ok_or ("--alias needs a custom ending")?.trim ();
+                    if !alias.is_empty () {
+                        options.alias = Some (alias.to_owned ());
+                    }
+                },
+                "--title" => {
+                    let title = args.next ().ok_or ("--title needs a text")?.trim ();
+                    if !title.is_empty () {
+                        options.link.title = Some (title.to_owned ());
+                    }
+                },
+                "--tags" => {
+                    let tags = args.next ().ok_or ("--tags needs tags")?;
+                    options.link.tags = tags.split (',')
+                        .map (|tag| tag.trim ().to_owned ())
+                        .filter (|tag| !tag.is_empty ())
+                        .collect ();
+                },
+                "--domain" => {
+                    let domain = args.next ().ok_or ("--domain needs a domain")?.trim ();
+                    if !domain.is_empty () {
+                        options.link.domain = Some (domain.to_owned ());
+                    }
+                },
+                "--valid-until" => {
+                    let date = args.next ().ok_or ("--valid-until needs a date")?.trim ();
+                    if !date.is_empty () {
+                        options.link.valid_until = Some (date.to_owned ());
+                    }
+                },
+                "--max-visits" => {
+                    let visits = args.next ().ok_or ("--max-visits needs a number")?;
+                    options.link.max_visits = Some (visits.parse ()
+                        .map_err (|_| format!("Invalid number of visits \"{}\"", visits))?);
+                },
+                "--find-if-exists" => options.link.find_if_exists = true,
+                "--password" => {
+                    let password = args.next ().ok_or ("--password needs a password")?;
+                    if !password.is_empty () {
+                        options.link.password = Some (password.clone ());
+                    }
+                },
+                "--expire-in" => {
+                    let expire_in = args.next ().ok_or ("--expire-in needs a duration")?.trim ();
+                    if !expire_in.is_empty () {
+                        options.link.expire_in = Some (expire_in.to_owned ());
+                    }
+                },
+                "--description" => {
+                    let description = args.next ().ok_or ("--description needs a text")?.trim ();
+                    if !description.is_empty () {
+                        options.link.description = Some (description.to_owned ());
+                    }
+                },
+                "--column" => {
+                    options.column = Some (args.next ().ok_or ("--column needs a column name or number")?.clone ());
+                },
+                "--output" => {
+                    options.output = Some (args.next ().ok_or ("--output needs a file name")?.clone ());
+                },
+                "--max-redirects" => {
+                    let max = args.next ().ok_or ("--max-redirects needs a number")?;
+                    options.expand.max_redirects = max.parse ()
+                        .map_err (|_| format!("Invalid number of redirects \"{}\"", max))?;
+                },
+                "--no-meta-refresh" => options.expand.meta_refresh = false,
+                "--probe" => options.probe = true,
+                "--no-clean" => options.clean = false,
+                "--unwrap" => options.unwrap = true,
+                "--keep" => {
+                    let keep = args.next ().ok_or ("--keep needs rule names")?;
+                    options.keep = keep.split (',')
+                        .map (|rule| rule.trim ().to_owned ())
+                        .filter (|rule| !rule.is_empty ())
+                        .collect ();
+                },
+                "--timeout" => {
+                    let timeout = args.next ().ok_or ("--timeout needs a number of seconds")?;
+                    options.timeout = timeout.parse ()
+                        .map_err (|_| format!("Invalid timeout \"{}\"", timeout))?;
+                },
+                "--json" => options.json = true,
+                "--no-clipboard" => options.clipboard = false,
+                arg if arg.starts_with ("--") => return Err (format!("Unknown option {}", arg)),
+                arg if (options.command == "yourls" || options.command == "shlink" || options.command == "bitly" || options.command == "custom") && options.action.is_none () => options.action = Some (arg.to_owned ()),
+                arg if options.url.is_none () => options.url = Some (arg.to_owned ()),
+                arg => return Err (format!("Unexpected argument \"{}\"", arg)),
+            }
+        }
+
+        let needs_url = options.command == "shorten" || options.command == "expand";
+        if needs_url && options.url.is_none () {
+            return Err (format!("{} needs a URL", options.command));
+        }
+        if options.command == "batch" && options.url.is_none () {
+            return Err ("batch needs a file".to_owned ());
+        }
+        if options.unwrap && options.command != "shorten" {
+            return Err ("--unwrap only works with shorten".to_owned ());
+        }
+        if options.alias.is_some () && options.command != "shorten" {
+            return Err ("--alias only works with shorten".to_owned ());
+        }
+        if options.link != LinkOptions::default () && options.command != "shorten" {
+            return Err ("The options of the link (--title, --tags, --password, ...) only work with shorten".to_owned ());
+        }
+        if options.command == "yourls" {
+            match options.action.as_ref ().map (|action| action.as_str ()) {
+                Some ("expand") | Some ("url-stats") if options.url.is_none () => return Err ("yourls expand and url-stats need a URL".to_owned ()),
+                Some ("expand") | Some ("url-stats") | Some ("db-stats") => {},
+                Some (action) => return Err (format!("Unknown yourls action \"{}\"", action)),
+                None => return Err ("yourls needs expand, url-stats or db-stats".to_owned ()),
+            }
+        }
+        if options.command == "shlink" {
+            match options.action.as_ref ().map (|action| action.as_str ()) {
+                Some ("domains") if options.url.is_some () => return Err ("shlink domains takes no URL".to_owned ()),
+                Some ("domains") => {},
+                Some (action) => return Err (format!("Unknown shlink action \"{}\"", action)),
+                None => return Err ("shlink needs domains".to_owned ()),
+            }
+        }
+        if options.command == "bitly" {
+            match options.action.as_ref ().map (|action| action.as_str ()) {
+                Some ("groups") if options.url.is_some () => return Err ("bitly groups takes no URL".to_owned ()),
+                Some ("groups") => {},
+                Some (action) => return Err (format!("Unknown bitly action \"{}\"", action)),
+                None => return Err ("bitly needs groups".to_owned ()),
+            }
+        }
+        if options.command == "custom" {
+            match options.action.as_ref ().map (|action| action.as_str ()) {
+                Some ("import") | Some ("export") if options.url.is_none () => return Err ("custom import and export need a file".to_owned ()),
+                Some ("list") if options.url.is_some () => return Err ("custom list takes no file".to_owned ()),
+                Some ("import") | Some ("export") | Some ("list") => {},
+                Some (action) => return Err (format!("Unknown custom action \"{}\"", action)),
+                None => return Err ("custom needs list, import or export".to_owned ()),
+            }
+        }
+        let rules = clean::builtin_rules ();
+        if let Some (unknown) = options.keep.iter ().find (|id| !rules.iter ().any (|rule| rule.id == **id)) {
+            return Err (format!("Unknown cleaning rule \"{}\"", unknown));
+        }
+        Ok (options)
+    }
+
+    fn shortener (&self) -> Result<Shortener, String> {
+        let http = ReqwestClient::new (Duration::from_secs (self.timeout))
+            .map_err (|error| error.to_string ())?;
+        let clipboard: Box<dyn Clipboard> = if self.clipboard {
+            Box::new (CommandClipboard)
+        } else {
+            Box::new (NoClipboard)
+        };
+
+        let mut shortener = Shortener::new (Box::new (http), clipboard);
+        shortener.set_history (Arc::new (History::new ()));
+        let store = credential_store ();
+        let mut providers = core::builtin_providers (store.clone (), Arc::new (ProviderConfig::new ()));
+        match DefinitionFile::new ().load () {
+            Ok (definitions) => {
+                let custom = core::custom_providers (store, definitions, &providers);
+                providers.extend (custom);
+            },
+            Err (error) => eprintln!("Could not read the custom providers: {}", error),
+        }
+        for provider in providers {
+            shortener.add_provider (provider);
+        }
+        Ok (shortener)
+    }
+
+    /// `--provider`, else the provider last picked in the window, the same
+    /// way the window picks it. Without the settings installed that is
+    /// the default provider. `None` when `automatic` has no usable
+    /// provider.
+    fn request<S: Into<String>> (&self, shortener: &Shortener, url: S) -> Option<ShortenRequest> {
+        let (last_used, priority) = match settings () {
+            Some (settings) => (SettingsExt::get_string (&settings, "default-provider-id"), SettingsExt::get_strv (&settings, "provider-priority")),
+            None => (Some (core::DEFAULT_PROVIDER.to_owned ()), vec![core::DEFAULT_PROVIDER.to_owned ()]),
+        };
+        let picked = self.provider.as_ref ().map (|provider| provider.as_str ());
+        let mut request = shortener.request_for (url, picked, last_used.as_ref ().map (|id| id.as_str ()), &priority)?;
+        request.fallbacks.extend (self.fallbacks.iter ().cloned ());
+        request.alias = self.alias.clone ();
+        let domain = request.options.domain.take ();
+        request.options = self.link.clone ();
+        if request.options.domain.is_none () {
+            request.options.domain = domain;
+        }
+        request.normalize.probe = self.probe;
+        if self.clean {
+            let mut cleaner = Cleaner::new (clean::builtin_rules ());
+            for rule in &self.keep {
+                cleaner.disable (rule);
+            }
+            request.cleaner = Some (cleaner);
+        }
+        request.copy_to_clipboard = self.clipboard;
+        Some (request)
+    }
+}
+
+/// The settings of the window, `None` when its schema is not installed
+/// (running from the build tree), where `Settings::new` would abort.
+pub fn settings () -> Option<Settings> {
+    let id = CString::new (SCHEMA_ID).unwrap ();
+    let installed = unsafe {
+        let source = gio_sys::g_settings_schema_source_get_default ();
+        let schema = if source.is_null () {
+            ptr::null_mut ()
+        } else {
+            gio_sys::g_settings_schema_source_lookup (source, id.as_ptr (), glib_sys::GTRUE)
+        };
+        if !schema.is_null () {
+            gio_sys::g_settings_schema_unref (schema);
+        }
+        !schema.is_null ()
+    };
+    if installed {
+        Some (Settings::new (SCHEMA_ID))
+    } else {
+        None
+    }
+}
+
+fn exit_code (error: &ShortenError) -> i32 {
+    match *error {
+        ShortenError::InvalidUrl (_) => 3,
+        ShortenError::UnknownProvider (_) => 4,
+        ShortenError::MissingCredentials (_) | ShortenError::Credentials (_) => 5,
+        ShortenError::Unavailable (_) | ShortenError::AllFailed (_) => 6,
+        ShortenError::Decode (_) => 7,
+        ShortenError::Redirects (_) => 9,
+        ShortenError::AliasTaken (_) | ShortenError::InvalidAlias (_) => 10,
+        ShortenError::QuotaExceeded (_) => 11,
+        ShortenError::Cancelled => EXIT_FAILURE,
+    }
+}
+
+fn error_name (error: &ShortenError) -> &'static str {
+    match *error {
+        ShortenError::InvalidUrl (_) => "invalid-url",
+        ShortenError::UnknownProvider (_) => "unknown-provider",
+        ShortenError::MissingCredentials (_) => "missing-credentials",
+        ShortenError::Credentials (_) => "credentials",
+        ShortenError::Unavailable (_) => "unavailable",
+        ShortenError::Decode (_) => "decode",
+        ShortenError::AllFailed (_) => "all-failed",
+        ShortenError::Redirects (_) => "redirects",
+        ShortenError::AliasTaken (_) => "alias-taken",
+        ShortenError::InvalidAlias (_) => "invalid-alias",
+        ShortenError::QuotaExceeded (_) => "quota-exceeded",
+        ShortenError::Cancelled => "cancelled",
+    }
+}
+
+fn fail (options: &Options, error: &ShortenError) -> i32 {
+    if options.json {
+        println!("{}", json!({ "error": error_name (error), "message": error.to_string () }));
+    } else {
+        eprintln!("{}", error);
+    }
+    exit_code (error)
+}
+
+/// `--provider automatic` with none of the providers of the preferences
+/// usable.
+fn no_provider (options: &Options) -> i32 {
+    let message = "None of the automatic providers can be used, check the preferences of Srtnr";
+    if options.json {
+        println!("{}", json!({ "error": "no-provider", "message": message }));
+    } else {
+        eprintln!("{}", message);
+    }
+    exit_code (&ShortenError::UnknownProvider (core::AUTOMATIC.to_owned ()))
+}
+
+fn startup_failure (message: &str) -> i32 {
+    eprintln!("{}", message);
+    EXIT_FAILURE
+}
+
+/// The store of the tokens, telling on stderr why it is not the keyring.
+fn credential_store () -> Arc<dyn CredentialStore> {
+    let (store, warning) = credentials::default_store ();
+    if let Some (warning) = warning {
+        eprintln!("{}", warning);
+    }
+    store
+}
+
+fn shorten (options: &Options) -> i32 {
+    let shortener = match options.shortener () {
+        Ok (shortener) => shortener,
+        Err (message) => return startup_failure (&message),
+    };
+
+    let mut url = options.url.clone ().unwrap ();
+    if let Some (unwrapped) = core::unwrap_redirector (&url) {
+        if options.unwrap {
+            url = unwrapped.url;
+        } else if !options.json {
+            eprintln!("This is a {} link to {}, pass --unwrap to shorten that instead", unwrapped.description (), unwrapped.url);
+        }
+    }
+    let request = match options.request (&shortener, url) {
+        Some (request) => request,
+        None => return no_provider (options),
+    };
+
+    match shortener.shorten (&request) {
+        Ok (outcome) => {
+            if options.json {
+                let removed: Vec<_> = outcome.removed.iter ()
+                    .map (|change| json!({ "rule": change.rule, "removed": change.removed }))
+                    .collect ();
+                println!("{}", json!({
+                    "long_url": outcome.long_url,
+                    "short_url": outcome.short_url,
+                    "provider": outcome.provider,
+                    "removed": removed,
+                    "copied": outcome.copied,
+                }));
+            } else {
+                println!("{}", outcome.short_url);
+                for change in &outcome.removed {
+                    eprintln!("Removed {} ({})", change.removed.join (" "), change.rule);
+                }
+                if options.clipboard && !outcome.copied {
+                    eprintln!("Could not copy the short URL to the clipboard");
+                }
+            }
+            EXIT_OK
+        },
+        Err (error) => fail (options, &error),
+    }
+}
+
+fn expand (options: &Options) -> i32 {
+    let shortener = match options.shortener () {
+        Ok (shortener) => shortener,
+        Err (message) => return startup_failure (&message),
+    };
+
+    let url = options.url.clone ().unwrap ();
+    match core::expand_chain (&url, shortener.http (), &options.expand) {
+        Ok (expansion) => {
+            if options.json {
+                let hops: Vec<_> = expansion.hops.iter ()
+                    .map (|hop| json!({ "url": hop.url, "status": hop.status, "meta_refresh": hop.meta_refresh }))
+                    .collect ();
+                println!("{}", json!({ "url": url, "destination": expansion.destination (), "hops": hops }));
+            } else {
+                for hop in &expansion.hops {
+                    let note = if hop.meta_refresh { "  (meta refresh)" } else { "" };
+                    println!("{} {}{}", hop.status, hop.url, note);
+                }
+            }
+            EXIT_OK
+        },
+        Err (error) => fail (options, &error),
+    }
+}
+
+fn providers (options: &Options) -> i32 {
+    let shortener = match options.shortener () {
+        Ok (shortener) => shortener,
+        Err (message) => return startup_failure (&message),
+    };
+
+    let stdout = io::stdout ();
+    let mut out = stdout.lock ();
+    if options.json {
+        let providers: Vec<_> = shortener.providers ().iter ()
+            .map (|provider| json!({
+                "id": provider.id (),
+                "name": provider.name (),
+                "requires_credentials": provider.requires_credentials (),
+                "configured": provider.is_configured (),
+                "alias": provider.supports_alias (),
+            }))
+            .collect ();
+        let _ = writeln!(out, "{}", serde_json::Value::Array (providers));
+    } else {
+        for provider in shortener.providers () {
+            let mut notes = Vec::new ();
+            if core::retired_reason (provider.id ()).is_some () {
+                notes.push ("retired");
+            } else if !provider.is_configured () {
+                notes.push ("needs an API token");
+            }
+            if provider.supports_alias () {
+                notes.push ("custom aliases");
+            }
+            let note = if notes.is_empty () { String::new () } else { format!("  ({})", notes.join (", ")) };
+            let _ = writeln!(out, "{}{}", provider.id (), note);
+        }
+    }
+    EXIT_OK
+}
+
+fn yourls_link_json (link: &YourlsLink) -> serde_json::Value {
+    json!({
+        "short_url": link.short_url,
+        "long_url": link.long_url,
+        "title": link.title,
+        "clicks": link.clicks,
+        "created": link.created,
+    })
+}
+
+fn yourls (options: &Options) -> i32 {
+    let http = match ReqwestClient::new (Duration::from_secs (options.timeout)) {
+        Ok (http) => http,
+        Err (error) => return startup_failure (&error.to_string ()),
+    };
+    let yourls = YourlsProvider::new (credential_store (), Arc::new (ProviderConfig::new ()));
+    let url = options.url.clone ().unwrap_or_default ();
+
+    match options.action.as_ref ().map (|action| action.as_str ()) {
+        Some ("db-stats") => match yourls.db_stats (&http) {
+            Ok (stats) => {
+                if options.json {
+                    println!("{}", json!({ "links": stats.links, "clicks": stats.clicks }));
+                } else {
+                    println!("{} links, {} clicks", stats.links, stats.clicks);
+                }
+                EXIT_OK
+            },
+            Err (error) => fail (options, &error),
+        },
+        Some ("url-stats") => match yourls.url_stats (&url, &http) {
+            Ok (link) => {
+                if options.json {
+                    println!("{}", yourls_link_json (&link));
+                } else {
+                    println!("{}", link.long_url);
+                    if !link.title.is_empty () {
+                        println!("Title: {}", link.title);
+                    }
+                    println!("Clicks: {}", link.clicks.unwrap_or (0));
+                    if let Some (ref created) = link.created {
+                        println!("Created: {}", created);
+                    }
+                }
+                EXIT_OK
+            },
+            Err (error) => fail (options, &error),
+        },
+        _ => match yourls.expand (&url, &http) {
+            Ok (link) => {
+                if options.json {
+                    println!("{}", yourls_link_json (&link));
+                } else {
+                    println!("{}", link.long_url);
+                }
+                EXIT_OK
+            },
+            Err (error) => fail (options, &error),
+        },
+    }
+}
+
+fn shlink (options: &Options) -> i32 {
+    let http = match ReqwestClient::new (Duration::from_secs (options.timeout)) {
+        Ok (http) => http,
+        Err (error) => return startup_failure (&error.to_string ()),
+    };
+    let shlink = ShlinkProvider::new (credential_store (), Arc::new (ProviderConfig::new ()));
+
+    match shlink.domains (&http) {
+        Ok (domains) => {
+            if options.json {
+                let domains = domains.iter ()
+                    .map (|domain| json!({ "domain": domain.authority, "default": domain.is_default }))
+                    .collect ();
+                println!("{}", serde_json::Value::Array (domains));
+            } else {
+                for domain in domains {
+                    if domain.is_default {
+                        println!("{}  (default)", domain.authority);
+                    } else {
+                        println!("{}", domain.authority);
+                    }
+                }
+            }
+            EXIT_OK
+        },
+        Err (error) => fail (options, &error),
+    }
+}
+
+fn bitly (options: &Options) -> i32 {
+    let http = match ReqwestClient::new (Duration::from_secs (options.timeout)) {
+        Ok (http) => http,
+        Err (error) => return startup_failure (&error.to_string ()),
+    };
+    let bitly = BitlyProvider::new (credential_store (), Arc::new (ProviderConfig::new ()));
+
+    let account = match bitly.account (&http) {
+        Ok (account) => account,
+        Err (error) => return fail (options, &error),
+    };
+    if let Err (error) = bitly.remember_domains (&account.domains ()) {
+        return fail (options, &error);
+    }
+    if options.json {
+        let groups = account.groups.iter ()
+            .map (|group| json!({
+                "guid": group.guid,
+                "name": group.name,
+                "domains": group.domains,
+                "default": account.default_group.as_ref () == Some (&group.guid),
+            }))
+            .collect ();
+        println!("{}", serde_json::Value::Array (groups));
+    } else {
+        for group in &account.groups {
+            let default = if account.default_group.as_ref () == Some (&group.guid) { "  (default)" } else { "" };
+            println!("{}  {}{}", group.guid, group.name, default);
+            for domain in &group.domains {
+                println!("    {}", domain);
+            }
+        }
+    }
+    EXIT_OK
+}
+
+fn custom (options: &Options) -> i32 {
+    let definitions = DefinitionFile::new ();
+    let file = PathBuf::from (options.url.clone ().unwrap_or_default ());
+
+    match options.action.as_ref ().map (|action| action.as_str ()) {
+        Some ("import") => match definitions.import (&file) {
+            Ok (ids) => {
+                println!("Imported {}", ids.join (", "));
+                EXIT_OK
+            },
+            Err (error) => startup_failure (&error.to_string ()),
+        },
+        Some ("export") => match definitions.export (&file) {
+            Ok (count) => {
+                println!("Exported {} providers to {}", count, file.display ());
+                EXIT_OK
+            },
+            Err (error) => startup_failure (&error.to_string ()),
+        },
+        _ => match definitions.load () {
+            Ok (all) => {
+                if options.json {
+                    let all = all.iter ().map (|definition| definition.to_json ()).collect ();
+                    println!("{}", serde_json::Value::Array (all));
+                } else {
+                    for definition in all {
+                        println!("{}  {}", definition.id, definition.name);
+                    }
+                }
+                EXIT_OK
+            },
+            Err (error) => startup_failure (&error.to_string ()),
+        },
+    }
+}
+
+/// `links.csv` becomes `links.short.csv`, lists are written as CSV.
+fn batch_output_path (input: &Path, format: Format) -> PathBuf {
+    let stem = input.file_stem ().and_then (|stem| stem.to_str ()).unwrap_or ("links");
+    let extension = match format {
+        Format::Tsv => "tsv",
+        Format::Csv | Format::List => "csv",
+    };
+    input.with_file_name (format!("{}.short.{}", stem, extension))
+}
+
+fn batch (options: &Options) -> i32 {
+    let input = PathBuf::from (options.url.clone ().unwrap ());
+    let text = match fs::read_to_string (&input) {
+        Ok (text) => text,
+        Err (error) => return startup_failure (&format!("Could not read {}: {}", input.display (), error)),
+    };
+
+    let format = Format::from_path (&input).unwrap_or_else (|| Format::sniff (&text));
+    let table = Table::parse (&text, format);
+    let column = match options.column {
+        Some (ref spec) => table.find_column (spec),
+        None => table.guess_url_column (),
+    };
+    let column = match column {
+        Some (column) => column,
+        None => {
+            eprintln!("No URL column found, pick one with --column. Columns: {}", table.headers.join (", "));
+            return EXIT_USAGE;
+        },
+    };
+
+    let shortener = match options.shortener () {
+        Ok (shortener) => shortener,
+        Err (message) => return startup_failure (&message),
+    };
+
+    let template = match options.request (&shortener, "") {
+        Some (template) => template,
+        None => return no_provider (options),
+    };
+    let report = batch::shorten_table (&shortener, &table, column, &template, |progress| {
+        if !options.json {
+            if let Some (ref error) = progress.error {
+                eprintln!("\rRow {}: {}", progress.done, error);
+            }
+            eprint!("\rShortened {}/{}", progress.done, progress.total);
+        }
+        true
+    });
+
+    let output = options.output.clone ()
+        .map (PathBuf::from)
+        .unwrap_or_else (|| batch_output_path (&input, format));
+    if let Err (error) = fs::write (&output, report.table.to_text ()) {
+        return startup_failure (&format!("Could not write {}: {}", output.display (), error));
+    }
+
+    if options.json {
+        let failures: Vec<_> = report.failures.iter ()
+            .map (|&(row, ref error)| json!({ "row": row, "error": error }))
+            .collect ();
+        println!("{}", json!({
+            "output": output.to_string_lossy (),
+            "succeeded": report.succeeded,
+            "failed": failures,
+        }));
+    } else {
+        eprintln!();
+        println!("{} of {} links shortened, written to {}", report.succeeded, table.rows.len (), output.display ());
+    }
+
+    if report.failures.is_empty () { EXIT_OK } else { EXIT_PARTIAL }
+}
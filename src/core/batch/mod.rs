@@ -0,0 +1,385 @@
+//! Shortening every URL of a CSV/TSV file or of a plain list of URLs.
+
+use std::path::Path;
+
+use super::{is_valid_url, ShortenError, ShortenRequest, Shortener};
+
+/// Column names looked for when guessing which column holds the URLs.
+const URL_HEADERS: &[&str] = &["url", "long_url", "link", "href", "address"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    /// One URL per line, no header.
+    List,
+}
+
+impl Format {
+    pub fn from_path (path: &Path) -> Option<Format> {
+        let extension = path.extension ()?.to_str ()?.to_lowercase ();
+        match extension.as_str () {
+            "csv" => Some (Format::Csv),
+            "tsv" | "tab" => Some (Format::Tsv),
+            "txt" | "list" => Some (Format::List),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the first line of `text`.
+    pub fn sniff (text: &str) -> Format {
+        let first_line = text.lines ().next ().unwrap_or ("").trim ();
+        if first_line.contains ('\t') {
+            Format::Tsv
+        } else if is_valid_url (first_line) {
+            Format::List
+        } else if first_line.contains (',') {
+            Format::Csv
+        } else {
+            Format::List
+        }
+    }
+
+    fn delimiter (&self) -> char {
+        match *self {
+            Format::Tsv => '\t',
+            Format::Csv | Format::List => ',',
+        }
+    }
+}
+
+/// A header row and the data rows below it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    pub format: Format,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn parse (text: &str, format: Format) -> Table {
+        if format == Format::List {
+            return Table {
+                format,
+                headers: vec!["url".to_owned ()],
+                rows: text.lines ()
+                    .map (|line| line.trim ())
+                    .filter (|line| !line.is_empty ())
+                    .map (|line| vec![line.to_owned ()])
+                    .collect (),
+            };
+        }
+
+        let mut records = parse_delimited (text, format.delimiter ()).into_iter ();
+        let headers = records.next ().unwrap_or_default ();
+        Table {
+            format,
+            headers,
+            rows: records.filter (|row| row.iter ().any (|cell| !cell.trim ().is_empty ())).collect (),
+        }
+    }
+
+    /// Finds a column by header name (case-insensitive) or 1-based number.
+    pub fn find_column (&self, spec: &str) -> Option<usize> {
+        if let Some (index) = self.headers.iter ().position (|header| header.trim ().eq_ignore_ascii_case (spec.trim ())) {
+            return Some (index);
+        }
+        match spec.trim ().parse::<usize> () {
+            Ok (number) if number >= 1 && number <= self.headers.len () => Some (number - 1),
+            _ => None,
+        }
+    }
+
+    /// A column with a URL-ish header, else the first column whose first
+    /// value is a URL.
+    pub fn guess_url_column (&self) -> Option<usize> {
+        for name in URL_HEADERS {
+            if let Some (index) = self.find_column (name) {
+                return Some (index);
+            }
+        }
+        let first_row = self.rows.first ()?;
+        first_row.iter ().position (|cell| is_valid_url (cell.trim ()))
+    }
+
+    /// Serializes the table, plain lists come out as CSV.
+    pub fn to_text (&self) -> String {
+        let delimiter = self.format.delimiter ();
+        let mut out = String::new ();
+        for record in Some (&self.headers).into_iter ().chain (&self.rows) {
+            let fields: Vec<String> = record.iter ().map (|field| quote (field, delimiter)).collect ();
+            out.push_str (&fields.join (&delimiter.to_string ()));
+            out.push ('\n');
+        }
+        out
+    }
+}
+
+fn quote (field: &str, delimiter: char) -> String {
+    if field.contains (delimiter) || field.contains ('"') || field.contains ('\n') || field.contains ('\r') {
+        format!("\"{}\"", field.replace ('"', "\"\""))
+    } else {
+        field.to_owned ()
+    }
+}
+
+/// RFC 4180 parsing: quoted fields may contain delimiters, newlines and
+/// doubled quotes.
+fn parse_delimited (text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new ();
+    let mut record = Vec::new ();
+    let mut field = String::new ();
+    let mut in_quotes = false;
+    let mut chars = text.chars ().peekable ();
+
+    while let Some (c) = chars.next () {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek () == Some (&'"') {
+                    field.push ('"');
+                    chars.next ();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push (c);
+            }
+        } else if c == '"' && field.is_empty () {
+            in_quotes = true;
+        } else if c == delimiter {
+            record.push (field.clone ());
+            field.clear ();
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek () == Some (&'\n') {
+                chars.next ();
+            }
+            record.push (field.clone ());
+            field.clear ();
+            records.push (record);
+            record = Vec::new ();
+        } else {
+            field.push (c);
+        }
+    }
+    if !field.is_empty () || !record.is_empty () {
+        record.push (field);
+        records.push (record);
+    }
+    records
+}
+
+/// Sent after every row.
+#[derive(Clone, Debug)]
+pub struct BatchProgress {
+    /// Rows handled so far, also the 1-based number of the last one.
+    pub done: usize,
+    pub total: usize,
+    /// Why the last row failed.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchReport {
+    /// The input with `short_url`, `provider` and `error` columns added.
+    pub table: Table,
+    pub succeeded: usize,
+    /// 1-based data row numbers with their error.
+    pub failures: Vec<(usize, String)>,
+    /// Stopped before the last row because `progress` returned false. The
+    /// rows left are in `table` with empty result columns.
+    pub cancelled: bool,
+}
+
+/// Shortens the URL in `column` of every row, with the provider and
+/// fallbacks of `template`. `progress` is called after each row and stops
+/// the batch by returning false. Rows are cut or padded to the headers so
+/// the results always land under their own.
+pub fn shorten_table<F> (shortener: &Shortener, table: &Table, column: usize, template: &ShortenRequest, mut progress: F) -> BatchReport
+where
+    F: FnMut (&BatchProgress) -> bool,
+{
+    let mut output = table.clone ();
+    output.headers.extend (vec!["short_url".to_owned (), "provider".to_owned (), "error".to_owned ()]);
+
+    let total = table.rows.len ();
+    let mut succeeded = 0;
+    let mut failures = Vec::new ();
+    let mut cancelled = false;
+
+    for (index, row) in output.rows.iter_mut ().enumerate () {
+        row.resize (table.headers.len (), String::new ());
+        if cancelled {
+            row.extend (vec![String::new (); 3]);
+            continue;
+        }
+
+        let url = row.get (column).cloned ().unwrap_or_default ();
+        let mut request = template.clone ();
+        request.url = url;
+        request.copy_to_clipboard = false;
+
+        let error = match shortener.shorten (&request) {
+            Ok (outcome) => {
+                row.extend (vec![outcome.short_url, outcome.provider, String::new ()]);
+                succeeded += 1;
+                None
+            },
+            Err (error) => {
+                let message = one_line (&error);
+                row.extend (vec![String::new (), String::new (), message.clone ()]);
+                failures.push ((index + 1, message.clone ()));
+                Some (message)
+            },
+        };
+
+        let keep_going = progress (&BatchProgress {
+            done: index + 1,
+            total,
+            error,
+        });
+        if !keep_going {
+            cancelled = index + 1 < total;
+        }
+    }
+
+    BatchReport {
+        table: output,
+        succeeded,
+        failures,
+        cancelled,
+    }
+}
+
+fn one_line (error: &ShortenError) -> String {
+    error.to_string ().replace ('\n', "; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use super::super::{builtin_providers, NoClipboard};
+    use super::super::testing::{provider_config, FakeHttp, MemoryStore};
+
+    fn shortener (http: FakeHttp) -> Shortener {
+        let mut shortener = Shortener::new (Box::new (Arc::new (http)), Box::new (NoClipboard));
+        for provider in builtin_providers (MemoryStore::with (&[]), provider_config (&[])) {
+            shortener.add_provider (provider);
+        }
+        shortener
+    }
+
+    #[test]
+    fn csv_fields_keep_delimiters_quotes_and_newlines () {
+        let text = "name,url\n\"Doe, J\",https://a.test/\n\"say \"\"hi\"\"\",https://b.test/\r\n\"two\nlines\",https://c.test/\n";
+        let table = Table::parse (text, Format::Csv);
+
+        assert_eq!(table.headers, vec!["name", "url"]);
+        assert_eq!(table.rows, vec![
+            vec!["Doe, J".to_owned (), "https://a.test/".to_owned ()],
+            vec!["say \"hi\"".to_owned (), "https://b.test/".to_owned ()],
+            vec!["two\nlines".to_owned (), "https://c.test/".to_owned ()],
+        ]);
+        assert_eq!(table.to_text (), text.replace ("\r\n", "\n"));
+    }
+
+    #[test]
+    fn csv_skips_blank_rows_and_reads_a_last_line_without_newline () {
+        let table = Table::parse ("url\n\nhttps://a.test/\n,\nhttps://b.test/", Format::Csv);
+        assert_eq!(table.rows, vec![vec!["https://a.test/".to_owned ()], vec!["https://b.test/".to_owned ()]]);
+    }
+
+    #[test]
+    fn tsv_only_quotes_tabs () {
+        let table = Table::parse ("url\tnote\nhttps://a.test/\tx, y\nhttps://b.test/\t\"tab\there\"\n", Format::Tsv);
+
+        assert_eq!(table.rows[0][1], "x, y");
+        assert_eq!(table.rows[1][1], "tab\there");
+        assert_eq!(table.to_text (), "url\tnote\nhttps://a.test/\tx, y\nhttps://b.test/\t\"tab\there\"\n");
+    }
+
+    #[test]
+    fn lists_come_out_as_csv () {
+        let table = Table::parse ("https://a.test/?a=1,2\n\n  https://b.test/  \n", Format::List);
+
+        assert_eq!(table.rows.len (), 2);
+        assert_eq!(table.to_text (), "url\n\"https://a.test/?a=1,2\"\nhttps://b.test/\n");
+    }
+
+    #[test]
+    fn sniffs_the_format_of_the_first_line () {
+        assert_eq!(Format::sniff ("url\tname\n"), Format::Tsv);
+        assert_eq!(Format::sniff ("https://a.test/?a=1,2\n"), Format::List);
+        assert_eq!(Format::sniff ("name,url\n"), Format::Csv);
+        assert_eq!(Format::from_path (Path::new ("links.TSV")), Some (Format::Tsv));
+    }
+
+    #[test]
+    fn finds_the_url_column () {
+        let table = Table::parse ("name,Link\nA,https://a.test/\n", Format::Csv);
+        assert_eq!(table.find_column ("link"), Some (1));
+        assert_eq!(table.find_column ("1"), Some (0));
+        assert_eq!(table.find_column ("3"), None);
+        assert_eq!(table.guess_url_column (), Some (1));
+
+        let table = Table::parse ("a,b\nx,https://a.test/\n", Format::Csv);
+        assert_eq!(table.guess_url_column (), Some (1));
+    }
+
+    #[test]
+    fn shortens_every_row_and_reports_failures () {
+        let http = FakeHttp::new ()
+            .answer ("https://is.gd/create.php?format=simple&url=https%3A%2F%2Fa.test%2F", 200, "https://is.gd/a\n")
+            .answer ("https://is.gd/create.php*", 502, "");
+        let shortener = shortener (http);
+        let table = Table::parse ("name,url\nA,https://a.test/\nB,https://b.test/\nC,not a url\n", Format::Csv);
+
+        let mut progress = Vec::new ();
+        let report = shorten_table (&shortener, &table, 1, &ShortenRequest::new ("", "is.gd"), |step| {
+            progress.push ((step.done, step.total, step.error.is_some ()));
+            true
+        });
+
+        assert_eq!(progress, vec![(1, 3, false), (2, 3, true), (3, 3, true)]);
+        assert_eq!(report.succeeded, 1);
+        assert_eq!(report.failures.iter ().map (|&(row, _)| row).collect::<Vec<_>> (), vec![2, 3]);
+        assert!(!report.cancelled);
+        assert_eq!(report.table.headers, vec!["name", "url", "short_url", "provider", "error"]);
+        assert_eq!(report.table.rows[0], vec!["A", "https://a.test/", "https://is.gd/a", "is.gd", ""]);
+        assert_eq!(report.table.rows[2][4], "\"not a url\" is not a valid URL");
+    }
+
+    #[test]
+    fn stops_when_progress_says_so () {
+        let shortener = shortener (FakeHttp::new ().answer ("https://is.gd/create.php*", 200, "https://is.gd/x"));
+        let table = Table::parse ("https://a.test/\nhttps://b.test/\nhttps://c.test/\n", Format::List);
+
+        let report = shorten_table (&shortener, &table, 0, &ShortenRequest::new ("", "is.gd"), |step| step.done < 2);
+
+        assert!(report.cancelled);
+        assert_eq!(report.succeeded, 2);
+        assert_eq!(report.table.rows, vec![
+            vec!["https://a.test/", "https://is.gd/x", "is.gd", ""],
+            vec!["https://b.test/", "https://is.gd/x", "is.gd", ""],
+            vec!["https://c.test/", "", "", ""],
+        ]);
+    }
+
+    #[test]
+    fn ragged_rows_get_their_results_under_the_right_headers () {
+        let shortener = shortener (FakeHttp::new ().answer ("https://is.gd/create.php*", 200, "https://is.gd/x"));
+        let table = Table::parse ("url,name
+https://a.test/
+https://b.test/,B,extra
+", Format::Csv);
+
+        let report = shorten_table (&shortener, &table, 0, &ShortenRequest::new ("", "is.gd"), |_| true);
+
+        assert_eq!(report.table.headers, vec!["url", "name", "short_url", "provider", "error"]);
+        assert_eq!(report.table.rows, vec![
+            vec!["https://a.test/", "", "https://is.gd/x", "is.gd", ""],
+            vec!["https://b.test/", "B", "https://is.gd/x", "is.gd", ""],
+        ]);
+    }
+}
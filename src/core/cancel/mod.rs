@@ -0,0 +1,37 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Shared between a front-end and a running request: once set, the request
+/// stops before its next provider or redirect with `ShortenError::Cancelled`.
+/// An HTTP request already sent is not interrupted, it ends at the timeout
+/// of the `HttpClient` at the latest.
+#[derive(Clone, Default)]
+pub struct CancelFlag (Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn new () -> CancelFlag {
+        CancelFlag::default ()
+    }
+
+    pub fn cancel (&self) {
+        self.0.store (true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled (&self) -> bool {
+        self.0.load (Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for CancelFlag {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CancelFlag ({})", self.is_cancelled ())
+    }
+}
+
+/// Flags are equal when both are set or both are not.
+impl PartialEq for CancelFlag {
+    fn eq (&self, other: &CancelFlag) -> bool {
+        self.is_cancelled () == other.is_cancelled ()
+    }
+}
@@ -0,0 +1,302 @@
+//! Rules which strip tracking junk (`utm_*`, `fbclid`, ...) from URLs
+//! before they are shortened, so it does not end up in permanent links.
+
+use std::error::Error;
+use std::fmt;
+
+use regex::Regex;
+
+/// A rule given in the settings that cannot be used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleError (pub String);
+
+impl fmt::Display for RuleError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for RuleError {}
+
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Drops the query parameters whose name matches one of the patterns,
+    /// `*` standing for any run of characters.
+    RemoveParams (Vec<String>),
+    /// Replaces every match of the expression in the whole URL.
+    Replace (Regex, String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// Stable identifier, used to switch the rule off.
+    pub id: String,
+    /// Name shown to users.
+    pub name: String,
+    pub action: Action,
+}
+
+impl Rule {
+    pub fn params (id: &str, name: &str, patterns: &[&str]) -> Rule {
+        Rule {
+            id: id.to_owned (),
+            name: name.to_owned (),
+            action: Action::RemoveParams (patterns.iter ().map (|pattern| pattern.to_string ()).collect ()),
+        }
+    }
+
+    /// A rule written by the user, one of
+    ///
+    /// * `param:<name>[,<name>...]`, names may contain `*`
+    /// * `regex:<expression>`, matches are removed
+    /// * `regex:<expression> => <replacement>`, with `$1` for groups
+    ///
+    /// The text of the rule is its id and name.
+    pub fn parse (text: &str) -> Result<Rule, RuleError> {
+        let text = text.trim ();
+        let action = if text.starts_with ("param:") {
+            let patterns: Vec<String> = text["param:".len ()..].split (',')
+                .map (|pattern| pattern.trim ().to_owned ())
+                .filter (|pattern| !pattern.is_empty ())
+                .collect ();
+            if patterns.is_empty () {
+                return Err (RuleError (format!("\"{}\" names no parameter", text)));
+            }
+            Action::RemoveParams (patterns)
+        } else if text.starts_with ("regex:") {
+            let rule = &text["regex:".len ()..];
+            let (pattern, replacement) = match rule.find (" => ") {
+                Some (arrow) => (&rule[..arrow], &rule[arrow + 4..]),
+                None => (rule, ""),
+            };
+            let pattern = Regex::new (pattern.trim ())
+                .map_err (|error| RuleError (format!("\"{}\" is not a valid expression: {}", pattern.trim (), error)))?;
+            Action::Replace (pattern, replacement.trim ().to_owned ())
+        } else {
+            return Err (RuleError (format!("\"{}\" must start with param: or regex:", text)));
+        };
+
+        Ok (Rule {
+            id: text.to_owned (),
+            name: text.to_owned (),
+            action,
+        })
+    }
+
+    /// `url` with the rule applied, and the parts it removed.
+    fn apply (&self, url: &str) -> (String, Vec<String>) {
+        match self.action {
+            Action::RemoveParams (ref patterns) => remove_params (url, patterns),
+            Action::Replace (ref pattern, ref replacement) => {
+                let removed: Vec<String> = pattern.find_iter (url)
+                    .map (|found| found.as_str ().to_owned ())
+                    .filter (|found| !found.is_empty ())
+                    .collect ();
+                if removed.is_empty () {
+                    return (url.to_owned (), removed);
+                }
+                (pattern.replace_all (url, replacement.as_str ()).into_owned (), removed)
+            },
+        }
+    }
+}
+
+/// The rule sets shipped with srtnr.
+pub fn builtin_rules () -> Vec<Rule> {
+    vec![
+        Rule::params ("utm", "Campaign parameters (utm_*)", &["utm_*"]),
+        Rule::params ("facebook", "Facebook", &["fbclid", "fb_action_ids", "fb_action_types", "fb_ref", "fb_source"]),
+        Rule::params ("google", "Google Ads and Analytics", &["gclid", "gclsrc", "dclid", "gbraid", "wbraid", "_ga", "_gl"]),
+        Rule::params ("mailchimp", "Mailchimp", &["mc_cid", "mc_eid"]),
+        Rule::params ("microsoft", "Microsoft Ads", &["msclkid"]),
+        Rule::params ("hubspot", "HubSpot", &["_hsenc", "_hsmi", "__hssc", "__hstc", "__hsfp", "hsCtaTracking"]),
+        Rule::params ("marketo", "Marketo", &["mkt_tok"]),
+        Rule::params ("social", "Instagram, TikTok, Twitter and Yandex", &["igshid", "ttclid", "twclid", "yclid"]),
+    ]
+}
+
+/// What one rule took out of a URL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub rule: String,
+    pub rule_name: String,
+    /// The removed `name=value` pairs, or the matches of an expression.
+    pub removed: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cleaned {
+    pub url: String,
+    /// One entry per rule that changed the URL, in the order they ran.
+    pub changes: Vec<Change>,
+}
+
+/// Runs rules one after the other.
+#[derive(Clone, Debug)]
+pub struct Cleaner {
+    rules: Vec<Rule>,
+}
+
+impl Cleaner {
+    pub fn new (rules: Vec<Rule>) -> Cleaner {
+        Cleaner { rules }
+    }
+
+    pub fn rules (&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Drops the rule `id`, e.g. because the user wants to keep what it
+    /// removes for one link.
+    pub fn disable (&mut self, id: &str) {
+        self.rules.retain (|rule| rule.id != id);
+    }
+
+    pub fn clean (&self, url: &str) -> Cleaned {
+        let mut url = url.to_owned ();
+        let mut changes = Vec::new ();
+        for rule in &self.rules {
+            let (cleaned, removed) = rule.apply (&url);
+            if !removed.is_empty () {
+                changes.push (Change {
+                    rule: rule.id.clone (),
+                    rule_name: rule.name.clone (),
+                    removed,
+                });
+            }
+            url = cleaned;
+        }
+        Cleaned { url, changes }
+    }
+}
+
+/// Works on the text of the URL, so whatever is kept stays byte for byte
+/// the same.
+fn remove_params (url: &str, patterns: &[String]) -> (String, Vec<String>) {
+    let (rest, fragment) = match url.find ('#') {
+        Some (hash) => (&url[..hash], &url[hash..]),
+        None => (url, ""),
+    };
+    let (base, query) = match rest.find ('?') {
+        Some (question) => (&rest[..question], &rest[question + 1..]),
+        None => return (url.to_owned (), Vec::new ()),
+    };
+
+    let mut kept = Vec::new ();
+    let mut removed = Vec::new ();
+    for pair in query.split ('&') {
+        let name = pair.split ('=').next ().unwrap_or ("");
+        if !name.is_empty () && patterns.iter ().any (|pattern| glob_match (pattern, name)) {
+            removed.push (pair.to_owned ());
+        } else if !pair.is_empty () {
+            kept.push (pair);
+        }
+    }
+    if removed.is_empty () {
+        return (url.to_owned (), removed);
+    }
+
+    let query = if kept.is_empty () { String::new () } else { format!("?{}", kept.join ("&")) };
+    (format!("{}{}{}", base, query, fragment), removed)
+}
+
+/// `*` matches any run of characters, everything else itself. Parameter
+/// names are compared case-insensitively.
+fn glob_match (pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase ();
+    let name = name.to_ascii_lowercase ();
+    let parts: Vec<&str> = pattern.split ('*').collect ();
+    if parts.len () == 1 {
+        return pattern == name;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len () - 1];
+    if name.len () < first.len () + last.len () || !name.starts_with (first) || !name.ends_with (last) {
+        return false;
+    }
+    let mut rest = &name[first.len ()..name.len () - last.len ()];
+    for part in &parts[1..parts.len () - 1] {
+        match rest.find (part) {
+            Some (found) => rest = &rest[found + part.len ()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin () -> Cleaner {
+        Cleaner::new (builtin_rules ())
+    }
+
+    #[test]
+    fn strips_tracking_parameters_and_keeps_the_rest () {
+        let cleaned = builtin ().clean ("https://a.test/p?utm_source=x&id=3&fbclid=abc&utm_medium=y#top");
+
+        assert_eq!(cleaned.url, "https://a.test/p?id=3#top");
+        assert_eq!(cleaned.changes, vec![
+            Change { rule: "utm".to_owned (), rule_name: "Campaign parameters (utm_*)".to_owned (), removed: vec!["utm_source=x".to_owned (), "utm_medium=y".to_owned ()] },
+            Change { rule: "facebook".to_owned (), rule_name: "Facebook".to_owned (), removed: vec!["fbclid=abc".to_owned ()] },
+        ]);
+    }
+
+    #[test]
+    fn drops_the_question_mark_with_the_last_parameter () {
+        assert_eq!(builtin ().clean ("https://a.test/?gclid=1").url, "https://a.test/");
+    }
+
+    #[test]
+    fn leaves_clean_urls_byte_for_byte () {
+        let url = "https://a.test/p?b=%2F&a=1&&c#utm_source=x";
+        let cleaned = builtin ().clean (url);
+
+        assert_eq!(cleaned.url, url);
+        assert!(cleaned.changes.is_empty ());
+    }
+
+    #[test]
+    fn disabled_rules_do_not_run () {
+        let mut cleaner = builtin ();
+        cleaner.disable ("utm");
+
+        assert_eq!(cleaner.clean ("https://a.test/?utm_source=x&fbclid=1").url, "https://a.test/?utm_source=x");
+    }
+
+    #[test]
+    fn globs_ignore_case_and_match_runs () {
+        assert!(glob_match ("utm_*", "UTM_Source"));
+        assert!(glob_match ("a*b*c", "axxbyyc"));
+        assert!(glob_match ("*", ""));
+        assert!(!glob_match ("a*b*c", "acb"));
+        assert!(!glob_match ("ab*ba", "aba"));
+        assert!(!glob_match ("fbclid", "fbclid2"));
+    }
+
+    #[test]
+    fn parses_parameter_rules () {
+        let rule = Rule::parse (" param:ref, src_* ").unwrap ();
+
+        assert_eq!(rule.id, "param:ref, src_*");
+        assert_eq!(Cleaner::new (vec![rule]).clean ("https://a.test/?ref=1&src_a=2&keep=3").url, "https://a.test/?keep=3");
+    }
+
+    #[test]
+    fn parses_expression_rules_with_replacement () {
+        let rule = Rule::parse (r"regex:/ref=[^/]+/ => /").unwrap ();
+        let cleaned = Cleaner::new (vec![rule]).clean ("https://shop.test/item/ref=abc/42");
+
+        assert_eq!(cleaned.url, "https://shop.test/item/42");
+        assert_eq!(cleaned.changes[0].removed, vec!["/ref=abc/".to_owned ()]);
+    }
+
+    #[test]
+    fn refuses_unusable_rules () {
+        assert!(Rule::parse ("param: ,").is_err ());
+        assert!(Rule::parse ("regex:(").is_err ());
+        assert!(Rule::parse ("utm_source").is_err ());
+    }
+}
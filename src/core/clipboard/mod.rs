@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Where short URLs get copied to.
+pub trait Clipboard: Send + Sync {
+    fn set_text (&self, text: &str) -> Result<(), String>;
+}
+
+/// Never copies anything, for `--no-clipboard` and for front-ends that
+/// handle the clipboard themselves.
+pub struct NoClipboard;
+
+impl Clipboard for NoClipboard {
+    fn set_text (&self, _text: &str) -> Result<(), String> {
+        Ok (())
+    }
+}
+
+/// Copies through the usual command line tools, so it works without GTK.
+pub struct CommandClipboard;
+
+impl CommandClipboard {
+    fn candidates () -> Vec<Vec<&'static str>> {
+        let mut candidates = Vec::new ();
+        if env::var_os ("WAYLAND_DISPLAY").is_some () {
+            candidates.push (vec!["wl-copy"]);
+        }
+        if env::var_os ("DISPLAY").is_some () {
+            candidates.push (vec!["xclip", "-selection", "clipboard"]);
+            candidates.push (vec!["xsel", "--clipboard", "--input"]);
+        }
+        candidates
+    }
+}
+
+impl Clipboard for CommandClipboard {
+    fn set_text (&self, text: &str) -> Result<(), String> {
+        for command in CommandClipboard::candidates () {
+            let child = Command::new (command[0])
+                .args (&command[1..])
+                .stdin (Stdio::piped ())
+                .stdout (Stdio::null ())
+                .stderr (Stdio::null ())
+                .spawn ();
+
+            let mut child = match child {
+                Ok (child) => child,
+                Err (_) => continue,
+            };
+            if let Some (ref mut stdin) = child.stdin {
+                stdin.write_all (text.as_bytes ()).map_err (|error| error.to_string ())?;
+            }
+            // Closing stdin lets the tool take ownership of the selection.
+            child.stdin.take ();
+            match child.wait () {
+                Ok (ref status) if status.success () => return Ok (()),
+                _ => continue,
+            }
+        }
+        Err ("No clipboard tool (wl-copy, xclip or xsel) available".to_owned ())
+    }
+}
+
+/// Short links remembered by `CopiedUrlFilter`, older ones are forgotten.
+const PRODUCED_LIMIT: usize = 100;
+
+/// Decides which copied text is worth shortening, for clipboard watchers.
+pub struct CopiedUrlFilter {
+    /// Shorter links are left alone.
+    pub min_length: usize,
+    last_seen: Option<String>,
+    produced: VecDeque<String>,
+}
+
+impl CopiedUrlFilter {
+    pub fn new (min_length: usize) -> CopiedUrlFilter {
+        CopiedUrlFilter {
+            min_length,
+            last_seen: None,
+            produced: VecDeque::new (),
+        }
+    }
+
+    /// A link srtnr put on the clipboard itself, never offered back.
+    pub fn remember_produced (&mut self, url: &str) {
+        if self.produced.len () == PRODUCED_LIMIT {
+            self.produced.pop_front ();
+        }
+        self.produced.push_back (url.trim ().to_owned ());
+    }
+
+    /// The link in `text` when it was newly copied, is a single http(s)
+    /// URL of at least `min_length` characters and not one srtnr made.
+    pub fn candidate (&mut self, text: &str) -> Option<String> {
+        let text = text.trim ();
+        if self.last_seen.as_ref ().map (|last| last == text).unwrap_or (false) {
+            return None;
+        }
+        self.last_seen = Some (text.to_owned ());
+
+        // Anything else with a dot, like a sentence, is not a copied link.
+        let lower = text.to_ascii_lowercase ();
+        if !lower.starts_with ("http://") && !lower.starts_with ("https://") {
+            return None;
+        }
+        if text.contains (char::is_whitespace) || text.chars ().count () < self.min_length {
+            return None;
+        }
+        if self.produced.iter ().any (|produced| produced == text) {
+            return None;
+        }
+        Some (text.to_owned ())
+    }
+}
@@ -0,0 +1,129 @@
+//! Settings of the providers which need more than a token, e.g. the address
+//! of a self-hosted instance, kept in
+//! `$XDG_CONFIG_HOME/com.github.arshubham.srtnr/providers.json`. Their
+//! secrets go to the credential store.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_json::{self, Map, Value};
+
+use super::paths;
+use super::ShortenError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError (pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from (error: io::Error) -> ConfigError {
+        ConfigError (error.to_string ())
+    }
+}
+
+impl From<ConfigError> for ShortenError {
+    fn from (error: ConfigError) -> ShortenError {
+        ShortenError::Unavailable (format!("Could not read the provider settings: {}", error.0))
+    }
+}
+
+/// A setting of a provider, edited in the preferences.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConfigField {
+    pub key: &'static str,
+    pub label: &'static str,
+    /// Shown while the entry is empty.
+    pub placeholder: &'static str,
+    /// `(value, label)` of every choice, free text when empty. The first
+    /// one applies while the setting is unset.
+    pub choices: &'static [(&'static str, &'static str)],
+}
+
+/// The settings of every provider, one JSON object of strings per provider
+/// id. The file is read on every call, so changes made in the preferences
+/// apply right away, also to the command line.
+pub struct ProviderConfig {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl ProviderConfig {
+    pub fn new () -> ProviderConfig {
+        ProviderConfig::with_path (paths::config_dir ().join ("providers.json"))
+    }
+
+    pub fn with_path (path: PathBuf) -> ProviderConfig {
+        ProviderConfig {
+            path,
+            lock: Mutex::new (()),
+        }
+    }
+
+    /// The value of `key` for `provider`, `None` when unset or empty.
+    pub fn get (&self, provider: &str, key: &str) -> Result<Option<String>, ConfigError> {
+        let _guard = self.lock.lock ().map_err (|_| ConfigError ("Provider settings lock poisoned".to_owned ()))?;
+        Ok (self.load ()?
+            .get (provider)
+            .and_then (|settings| settings[key].as_str ())
+            .map (|value| value.trim ().to_owned ())
+            .filter (|value| !value.is_empty ()))
+    }
+
+    /// Sets `key` of `provider`, an empty `value` removes it.
+    pub fn set (&self, provider: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+        let _guard = self.lock.lock ().map_err (|_| ConfigError ("Provider settings lock poisoned".to_owned ()))?;
+        let mut all = self.load ()?;
+        let mut settings = match all.remove (provider) {
+            Some (Value::Object (settings)) => settings,
+            _ => Map::new (),
+        };
+        if value.trim ().is_empty () {
+            settings.remove (key);
+        } else {
+            settings.insert (key.to_owned (), Value::String (value.trim ().to_owned ()));
+        }
+        if !settings.is_empty () {
+            all.insert (provider.to_owned (), Value::Object (settings));
+        }
+        self.save (&all)
+    }
+
+    fn load (&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut text = String::new ();
+        match File::open (&self.path) {
+            Ok (mut file) => { file.read_to_string (&mut text)?; },
+            Err (ref error) if error.kind () == ErrorKind::NotFound => return Ok (Map::new ()),
+            Err (error) => return Err (error.into ()),
+        }
+        match serde_json::from_str (&text) {
+            Ok (Value::Object (all)) => Ok (all),
+            _ => Err (ConfigError (format!("{} is not a provider settings file", self.path.display ()))),
+        }
+    }
+
+    fn save (&self, all: &Map<String, Value>) -> Result<(), ConfigError> {
+        if let Some (dir) = self.path.parent () {
+            fs::create_dir_all (dir)?;
+        }
+        let temporary = self.path.with_extension ("json.tmp");
+        {
+            let mut file = File::create (&temporary)?;
+            let text = serde_json::to_string_pretty (all).map_err (|error| ConfigError (error.to_string ()))?;
+            writeln!(file, "{}", text)?;
+            file.sync_all ()?;
+        }
+        fs::rename (&temporary, &self.path)?;
+        Ok (())
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Mutex;
+
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde_json;
+
+use core::paths;
+use super::{CredentialError, CredentialStore};
+
+const MAGIC: &[u8] = b"SRTNR1";
+const SALT_LEN: usize = 16;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const ITERATIONS: usize = 100_000;
+
+impl From<ErrorStack> for CredentialError {
+    fn from (error: ErrorStack) -> CredentialError {
+        CredentialError (error.to_string ())
+    }
+}
+
+/// Secrets in an AES-GCM encrypted JSON file, used when no keyring daemon
+/// is running.
+///
+/// The key is derived from the machine id and the user name, so the file is
+/// useless when copied elsewhere (backups, dotfile repos), but it does not
+/// protect against other programs running as the same user.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    /// The salt of the file and the key derived from it, derived once as
+    /// it takes `ITERATIONS` rounds.
+    key: Mutex<Option<([u8; SALT_LEN], Vec<u8>)>>,
+}
+
+impl EncryptedFileStore {
+    pub fn new () -> EncryptedFileStore {
+        EncryptedFileStore::with_path (paths::data_dir ().join ("credentials"))
+    }
+
+    pub fn with_path (path: PathBuf) -> EncryptedFileStore {
+        EncryptedFileStore {
+            path,
+            key: Mutex::new (None),
+        }
+    }
+
+    /// The key of `salt`, derived only when the salt changed.
+    fn key (cached: &mut Option<([u8; SALT_LEN], Vec<u8>)>, salt: &[u8]) -> Result<Vec<u8>, CredentialError> {
+        if let Some ((ref cached_salt, ref key)) = *cached {
+            if &cached_salt[..] == salt {
+                return Ok (key.clone ());
+            }
+        }
+        let key = EncryptedFileStore::derive_key (salt)?;
+        let mut new_salt = [0; SALT_LEN];
+        new_salt.copy_from_slice (salt);
+        *cached = Some ((new_salt, key.clone ()));
+        Ok (key)
+    }
+
+    fn derive_key (salt: &[u8]) -> Result<Vec<u8>, CredentialError> {
+        let mut passphrase = Vec::new ();
+        for machine_id in &["/etc/machine-id", "/var/lib/dbus/machine-id"] {
+            if let Ok (mut file) = File::open (machine_id) {
+                file.read_to_end (&mut passphrase)?;
+                break;
+            }
+        }
+        passphrase.extend_from_slice (paths::user_name ().as_bytes ());
+
+        let mut key = vec![0; KEY_LEN];
+        pbkdf2_hmac (&passphrase, salt, ITERATIONS, MessageDigest::sha256 (), &mut key)?;
+        Ok (key)
+    }
+
+    fn load (&self, cached: &mut Option<([u8; SALT_LEN], Vec<u8>)>) -> Result<HashMap<String, String>, CredentialError> {
+        let mut data = Vec::new ();
+        match File::open (&self.path) {
+            Ok (mut file) => { file.read_to_end (&mut data)?; },
+            Err (ref error) if error.kind () == ErrorKind::NotFound => return Ok (HashMap::new ()),
+            Err (error) => return Err (error.into ()),
+        }
+
+        let header = MAGIC.len () + SALT_LEN + IV_LEN + TAG_LEN;
+        if data.len () < header || !data.starts_with (MAGIC) {
+            return Err (CredentialError (format!("{} is not a credentials file", self.path.display ())));
+        }
+        let (salt, rest) = data[MAGIC.len ()..].split_at (SALT_LEN);
+        let (iv, rest) = rest.split_at (IV_LEN);
+        let (tag, ciphertext) = rest.split_at (TAG_LEN);
+
+        let key = EncryptedFileStore::key (cached, salt)?;
+        let plaintext = decrypt_aead (Cipher::aes_256_gcm (), &key, Some (iv), MAGIC, ciphertext, tag)
+            .map_err (|_| CredentialError (format!("Could not decrypt {}", self.path.display ())))?;
+
+        serde_json::from_slice (&plaintext).map_err (|error| CredentialError (error.to_string ()))
+    }
+
+    /// Keeps the salt of the last key, the IV is new on every save.
+    fn save (&self, cached: &mut Option<([u8; SALT_LEN], Vec<u8>)>, secrets: &HashMap<String, String>) -> Result<(), CredentialError> {
+        let plaintext = serde_json::to_vec (secrets).map_err (|error| CredentialError (error.to_string ()))?;
+
+        let mut salt = [0; SALT_LEN];
+        let mut iv = [0; IV_LEN];
+        let mut tag = [0; TAG_LEN];
+        match *cached {
+            Some ((cached_salt, _)) => salt = cached_salt,
+            None => rand_bytes (&mut salt)?,
+        }
+        rand_bytes (&mut iv)?;
+        let key = EncryptedFileStore::key (cached, &salt)?;
+        let ciphertext = encrypt_aead (Cipher::aes_256_gcm (), &key, Some (&iv[..]), MAGIC, &plaintext, &mut tag)?;
+
+        if let Some (dir) = self.path.parent () {
+            fs::create_dir_all (dir)?;
+        }
+        // Renamed over the old file once complete, a crash or a full disk
+        // leaves the old secrets instead of a truncated file.
+        let temporary = self.path.with_extension (format!("{}.tmp", process::id ()));
+        {
+            let mut file = OpenOptions::new ()
+                .write (true)
+                .create (true)
+                .truncate (true)
+                .mode (0o600)
+                .open (&temporary)?;
+            file.write_all (MAGIC)?;
+            file.write_all (&salt)?;
+            file.write_all (&iv)?;
+            file.write_all (&tag)?;
+            file.write_all (&ciphertext)?;
+            file.sync_all ()?;
+        }
+        fs::rename (&temporary, &self.path)?;
+        Ok (())
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn get (&self, provider: &str) -> Result<Option<String>, CredentialError> {
+        let mut key = self.key.lock ().unwrap ();
+        Ok (self.load (&mut key)?.remove (provider))
+    }
+
+    fn set (&self, provider: &str, secret: &str) -> Result<(), CredentialError> {
+        let mut key = self.key.lock ().unwrap ();
+        let mut secrets = self.load (&mut key)?;
+        if secrets.get (provider).map (|stored| stored == secret).unwrap_or (false) {
+            return Ok (());
+        }
+        secrets.insert (provider.to_owned (), secret.to_owned ());
+        self.save (&mut key, &secrets)
+    }
+
+    fn delete (&self, provider: &str) -> Result<(), CredentialError> {
+        let mut key = self.key.lock ().unwrap ();
+        let mut secrets = self.load (&mut key)?;
+        if secrets.remove (provider).is_some () {
+            self.save (&mut key, &secrets)?;
+        }
+        Ok (())
+    }
+
+    fn description (&self) -> String {
+        format!("No keyring available, stored encrypted in {}", self.path.display ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::testing::temporary_path;
+
+    #[test]
+    fn secrets_survive_other_stores_of_the_file () {
+        let path = temporary_path ("credentials");
+        let store = EncryptedFileStore::with_path (path.clone ());
+        store.set ("bitly.com", "token-1").unwrap ();
+        store.set ("kutt", "token-2").unwrap ();
+        store.delete ("kutt").unwrap ();
+
+        let other = EncryptedFileStore::with_path (path.clone ());
+        assert_eq!(other.get ("bitly.com"), Ok (Some ("token-1".to_owned ())));
+        assert_eq!(other.get ("kutt"), Ok (None));
+        other.set ("shlink", "token-3").unwrap ();
+        assert_eq!(store.get ("shlink"), Ok (Some ("token-3".to_owned ())));
+        assert!(!fs::read (&path).unwrap ().windows (7).any (|bytes| bytes == b"token-1"));
+    }
+
+    #[test]
+    fn the_key_is_derived_once_per_salt () {
+        let store = EncryptedFileStore::with_path (temporary_path ("credentials"));
+        store.set ("bitly.com", "token-1").unwrap ();
+        let salt = store.key.lock ().unwrap ().as_ref ().map (|&(salt, _)| salt);
+        store.set ("kutt", "token-2").unwrap ();
+        assert_eq!(store.key.lock ().unwrap ().as_ref ().map (|&(salt, _)| salt), salt);
+    }
+
+    #[test]
+    fn saving_replaces_the_file_with_a_private_one () {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temporary_path ("credentials");
+        let store = EncryptedFileStore::with_path (path.clone ());
+        store.set ("bitly.com", "token-1").unwrap ();
+        store.set ("kutt", "token-2").unwrap ();
+
+        assert_eq!(fs::metadata (&path).unwrap ().permissions ().mode () & 0o777, 0o600);
+        let leftovers = fs::read_dir (path.parent ().unwrap ()).unwrap ()
+            .filter (|entry| entry.as_ref ().unwrap ().file_name ().to_string_lossy ().ends_with (".tmp"))
+            .count ();
+        assert_eq!(leftovers, 0);
+    }
+}
@@ -0,0 +1,55 @@
+//! Per-provider API tokens, kept in the Secret Service keyring or, when no
+//! keyring daemon is running, in an encrypted file.
+
+mod file;
+mod secret;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+pub use self::file::EncryptedFileStore;
+pub use self::secret::SecretServiceStore;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CredentialError (pub String);
+
+impl fmt::Display for CredentialError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for CredentialError {}
+
+impl From<io::Error> for CredentialError {
+    fn from (error: io::Error) -> CredentialError {
+        CredentialError (error.to_string ())
+    }
+}
+
+/// Storage for provider secrets, keyed by provider id.
+pub trait CredentialStore: Send + Sync {
+    fn get (&self, provider: &str) -> Result<Option<String>, CredentialError>;
+
+    fn set (&self, provider: &str, secret: &str) -> Result<(), CredentialError>;
+
+    fn delete (&self, provider: &str) -> Result<(), CredentialError>;
+
+    /// Where the secrets end up, shown in the preferences.
+    fn description (&self) -> String;
+}
+
+/// The keyring when a Secret Service daemon answers, the encrypted file
+/// otherwise, with a warning saying why the keyring was not used.
+pub fn default_store () -> (Arc<dyn CredentialStore>, Option<String>) {
+    let keyring = SecretServiceStore;
+    match keyring.get ("") {
+        Ok (_) => (Arc::new (keyring), None),
+        Err (error) => {
+            let warning = format!("The keyring could not be used, the tokens are kept in an encrypted file instead: {}", error);
+            (Arc::new (EncryptedFileStore::new ()), Some (warning))
+        },
+    }
+}
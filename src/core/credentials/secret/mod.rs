@@ -0,0 +1,127 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use glib_sys;
+use glib_sys::{gboolean, gpointer, GError, GHashTable};
+
+use super::{CredentialError, CredentialStore};
+
+const SCHEMA_NAME: &str = "com.github.arshubham.srtnr.Credentials";
+const SECRET_SCHEMA_NONE: c_int = 0;
+
+#[repr(C)]
+struct SecretSchema {
+    _private: [u8; 0],
+}
+
+#[link(name = "secret-1")]
+extern "C" {
+    fn secret_schema_newv (name: *const c_char, flags: c_int, attribute_names_and_types: *mut GHashTable) -> *mut SecretSchema;
+    fn secret_schema_unref (schema: *mut SecretSchema);
+    fn secret_password_storev_sync (schema: *const SecretSchema, attributes: *mut GHashTable, collection: *const c_char, label: *const c_char, password: *const c_char, cancellable: gpointer, error: *mut *mut GError) -> gboolean;
+    fn secret_password_lookupv_sync (schema: *const SecretSchema, attributes: *mut GHashTable, cancellable: gpointer, error: *mut *mut GError) -> *mut c_char;
+    fn secret_password_clearv_sync (schema: *const SecretSchema, attributes: *mut GHashTable, cancellable: gpointer, error: *mut *mut GError) -> gboolean;
+    fn secret_password_free (password: *mut c_char);
+}
+
+/// Secrets stored through libsecret, one item per provider.
+pub struct SecretServiceStore;
+
+/// Owns the schema and attribute table of one libsecret call.
+struct Query {
+    schema: *mut SecretSchema,
+    attributes: *mut GHashTable,
+    _strings: Vec<CString>,
+}
+
+impl Query {
+    fn new (provider: &str) -> Result<Query, CredentialError> {
+        let name = CString::new (SCHEMA_NAME).unwrap ();
+        let key = CString::new ("provider").unwrap ();
+        let value = CString::new (provider)
+            .map_err (|_| CredentialError ("Invalid provider id".to_owned ()))?;
+
+        unsafe {
+            // Attribute types are stored as pointers, SECRET_SCHEMA_ATTRIBUTE_STRING is 0.
+            let types = glib_sys::g_hash_table_new (Some (glib_sys::g_str_hash), Some (glib_sys::g_str_equal));
+            glib_sys::g_hash_table_insert (types, key.as_ptr () as gpointer, ptr::null_mut ());
+            let schema = secret_schema_newv (name.as_ptr (), SECRET_SCHEMA_NONE, types);
+            glib_sys::g_hash_table_unref (types);
+
+            let attributes = glib_sys::g_hash_table_new (Some (glib_sys::g_str_hash), Some (glib_sys::g_str_equal));
+            glib_sys::g_hash_table_insert (attributes, key.as_ptr () as gpointer, value.as_ptr () as gpointer);
+
+            Ok (Query {
+                schema,
+                attributes,
+                _strings: vec![name, key, value],
+            })
+        }
+    }
+}
+
+impl Drop for Query {
+    fn drop (&mut self) {
+        unsafe {
+            glib_sys::g_hash_table_unref (self.attributes);
+            secret_schema_unref (self.schema);
+        }
+    }
+}
+
+unsafe fn take_error (error: *mut GError) -> CredentialError {
+    let message = CStr::from_ptr ((*error).message).to_string_lossy ().into_owned ();
+    glib_sys::g_error_free (error);
+    CredentialError (message)
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn get (&self, provider: &str) -> Result<Option<String>, CredentialError> {
+        let query = Query::new (provider)?;
+        let mut error = ptr::null_mut ();
+        unsafe {
+            let password = secret_password_lookupv_sync (query.schema, query.attributes, ptr::null_mut (), &mut error);
+            if !error.is_null () {
+                return Err (take_error (error));
+            }
+            if password.is_null () {
+                return Ok (None);
+            }
+            let secret = CStr::from_ptr (password).to_string_lossy ().into_owned ();
+            secret_password_free (password);
+            Ok (Some (secret))
+        }
+    }
+
+    fn set (&self, provider: &str, secret: &str) -> Result<(), CredentialError> {
+        let query = Query::new (provider)?;
+        let label = CString::new (format!("Srtnr token for {}", provider)).unwrap ();
+        let password = CString::new (secret)
+            .map_err (|_| CredentialError ("Invalid token".to_owned ()))?;
+        let mut error = ptr::null_mut ();
+        unsafe {
+            secret_password_storev_sync (query.schema, query.attributes, ptr::null (), label.as_ptr (), password.as_ptr (), ptr::null_mut (), &mut error);
+            if !error.is_null () {
+                return Err (take_error (error));
+            }
+        }
+        Ok (())
+    }
+
+    fn delete (&self, provider: &str) -> Result<(), CredentialError> {
+        let query = Query::new (provider)?;
+        let mut error = ptr::null_mut ();
+        unsafe {
+            secret_password_clearv_sync (query.schema, query.attributes, ptr::null_mut (), &mut error);
+            if !error.is_null () {
+                return Err (take_error (error));
+            }
+        }
+        Ok (())
+    }
+
+    fn description (&self) -> String {
+        "Stored in the login keyring".to_owned ()
+    }
+}
@@ -0,0 +1,506 @@
+//! Providers defined by users in
+//! `$XDG_CONFIG_HOME/com.github.arshubham.srtnr/custom-providers.json`,
+//! without a new build. A definition says how to send the request and where
+//! the short link is in the answer:
+//!
+//! ```json
+//! { "providers": [ {
+//!     "id": "team",
+//!     "name": "Team links",
+//!     "method": "POST",
+//!     "url": "https://s.example.com/api/links",
+//!     "headers": { "Authorization": "Bearer {token}", "Content-Type": "application/json" },
+//!     "body": "{ \"target\": \"{url}\", \"slug\": \"{alias}\" }",
+//!     "extract": { "json": "/link" },
+//!     "error": { "json": "/message" }
+//! } ] }
+//! ```
+//!
+//! `{url}`, `{alias}` and `{token}` are filled in, encoded for the query in
+//! `url` and as the `Content-Type` asks in `body`. The token is kept in the
+//! credential store under the id. `extract` is `"text"`, `{"json":
+//! "/pointer"}`, `{"xpath": "//element/@attribute"}` or `{"regex":
+//! "pattern", "group": 1}`.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_json::{self, Map, Value};
+
+use super::paths;
+use super::{Method, AUTOMATIC};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefinitionError (pub String);
+
+impl fmt::Display for DefinitionError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for DefinitionError {}
+
+impl From<io::Error> for DefinitionError {
+    fn from (error: io::Error) -> DefinitionError {
+        DefinitionError (error.to_string ())
+    }
+}
+
+/// Where the short link is in the answer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Extract {
+    /// The whole answer.
+    Text,
+    /// A JSON pointer (RFC 6901), e.g. `/data/link`.
+    Json (String),
+    /// Elements separated by `/`, each looked for anywhere inside the one
+    /// before, then `@attribute` or the text, e.g. `//result/shorturl`.
+    XPath (String),
+    /// The `group` of the first match, 0 being the whole match.
+    Regex { pattern: String, group: usize },
+}
+
+impl Extract {
+    fn from_json (value: &Value, key: &str) -> Result<Extract, DefinitionError> {
+        if value.as_str () == Some ("text") {
+            return Ok (Extract::Text);
+        }
+        if let Some (pointer) = value["json"].as_str () {
+            if !pointer.is_empty () && !pointer.starts_with ('/') {
+                return Err (DefinitionError (format!("The JSON pointer of \"{}\" must start with /", key)));
+            }
+            return Ok (Extract::Json (pointer.to_owned ()));
+        }
+        if let Some (path) = value["xpath"].as_str () {
+            if !path.starts_with ('/') {
+                return Err (DefinitionError (format!("The path of \"{}\" must start with /", key)));
+            }
+            return Ok (Extract::XPath (path.to_owned ()));
+        }
+        if let Some (pattern) = value["regex"].as_str () {
+            let regex = Regex::new (pattern)
+                .map_err (|error| DefinitionError (format!("Invalid regular expression in \"{}\": {}", key, error)))?;
+            let group = match value["group"].as_u64 () {
+                Some (group) => group as usize,
+                None if regex.captures_len () > 1 => 1,
+                None => 0,
+            };
+            if group >= regex.captures_len () {
+                return Err (DefinitionError (format!("The regular expression of \"{}\" has no group {}", key, group)));
+            }
+            return Ok (Extract::Regex { pattern: pattern.to_owned (), group });
+        }
+        Err (DefinitionError (format!("\"{}\" must be \"text\" or have \"json\", \"xpath\" or \"regex\"", key)))
+    }
+
+    fn to_json (&self) -> Value {
+        match *self {
+            Extract::Text => json!("text"),
+            Extract::Json (ref pointer) => json!({ "json": pointer }),
+            Extract::XPath (ref path) => json!({ "xpath": path }),
+            Extract::Regex { ref pattern, group } => json!({ "regex": pattern, "group": group }),
+        }
+    }
+
+    /// The part of `body` the rule points at, trimmed, `None` when it is
+    /// not there or empty.
+    pub fn apply (&self, body: &str) -> Option<String> {
+        let found = match *self {
+            Extract::Text => Some (body.to_owned ()),
+            Extract::Json (ref pointer) => {
+                let json: Value = serde_json::from_str (body).ok ()?;
+                match *json.pointer (pointer)? {
+                    Value::String (ref text) => Some (text.clone ()),
+                    Value::Null => None,
+                    ref other => Some (other.to_string ()),
+                }
+            },
+            Extract::XPath (ref path) => xpath (body, path),
+            Extract::Regex { ref pattern, group } => {
+                let regex = Regex::new (pattern).ok ()?;
+                let captures = regex.captures (body)?;
+                captures.get (group).map (|found| found.as_str ().to_owned ())
+            },
+        };
+        found.map (|text| text.trim ().to_owned ()).filter (|text| !text.is_empty ())
+    }
+}
+
+/// A provider of the definitions file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProviderDefinition {
+    pub id: String,
+    pub name: String,
+    pub method: Method,
+    /// Template of the address, its placeholders are percent-encoded.
+    pub url: String,
+    /// Templates of the headers, filled in as they are.
+    pub headers: Vec<(String, String)>,
+    /// Template of the body, encoded as its `Content-Type` header asks.
+    pub body: Option<String>,
+    pub extract: Extract,
+    /// Where the message is in an answer other than 2xx.
+    pub error: Option<Extract>,
+}
+
+impl ProviderDefinition {
+    pub fn from_json (value: &Value) -> Result<ProviderDefinition, DefinitionError> {
+        let id = value["id"].as_str ().unwrap_or ("").trim ().to_owned ();
+        if id.is_empty () {
+            return Err (DefinitionError ("A provider has no \"id\"".to_owned ()));
+        }
+        if id == AUTOMATIC || !id.chars ().all (|c| c.is_ascii_alphanumeric () || "._-".contains (c)) {
+            return Err (DefinitionError (format!("\"{}\" cannot be used as id, only letters, digits, \".\", \"_\" and \"-\" are allowed", id)));
+        }
+        let fail = |message: &str| DefinitionError (format!("{}: {}", id, message));
+
+        let method = match value["method"].as_str ().unwrap_or ("GET").to_uppercase ().as_str () {
+            "GET" => Method::Get,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "PATCH" => Method::Patch,
+            method => return Err (fail (&format!("unknown method \"{}\"", method))),
+        };
+        let url = value["url"].as_str ().unwrap_or ("").trim ().to_owned ();
+        if !url.starts_with ("https://") && !url.starts_with ("http://") {
+            return Err (fail ("\"url\" must start with https:// or http://"));
+        }
+
+        let mut headers = Vec::new ();
+        match value["headers"] {
+            Value::Null => {},
+            Value::Object (ref all) => for (name, value) in all {
+                let value = value.as_str ().ok_or_else (|| fail (&format!("the header \"{}\" is not a string", name)))?;
+                headers.push ((name.clone (), value.to_owned ()));
+            },
+            _ => return Err (fail ("\"headers\" must be an object of strings")),
+        }
+        let body = match value["body"] {
+            Value::Null => None,
+            Value::String (ref body) => Some (body.clone ()),
+            _ => return Err (fail ("\"body\" must be a string")),
+        };
+
+        let extract = Extract::from_json (&value["extract"], "extract").map_err (|error| fail (&error.0))?;
+        let error = match value["error"] {
+            Value::Null => None,
+            ref error => Some (Extract::from_json (error, "error").map_err (|error| fail (&error.0))?),
+        };
+
+        Ok (ProviderDefinition {
+            name: value["name"].as_str ().map (|name| name.trim ().to_owned ()).filter (|name| !name.is_empty ()).unwrap_or_else (|| id.clone ()),
+            id,
+            method,
+            url,
+            headers,
+            body,
+            extract,
+            error,
+        })
+    }
+
+    pub fn to_json (&self) -> Value {
+        let method = match self.method {
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            _ => "GET",
+        };
+        let mut headers = Map::new ();
+        for &(ref name, ref value) in &self.headers {
+            headers.insert (name.clone (), Value::String (value.clone ()));
+        }
+
+        let mut json = json!({
+            "id": self.id,
+            "name": self.name,
+            "method": method,
+            "url": self.url,
+            "extract": self.extract.to_json (),
+        });
+        if !headers.is_empty () {
+            json["headers"] = Value::Object (headers);
+        }
+        if let Some (ref body) = self.body {
+            json["body"] = json!(body);
+        }
+        if let Some (ref error) = self.error {
+            json["error"] = error.to_json ();
+        }
+        json
+    }
+
+    /// Whether a template asks for `{placeholder}`.
+    pub fn uses (&self, placeholder: &str) -> bool {
+        let placeholder = format!("{{{}}}", placeholder);
+        self.url.contains (&placeholder)
+            || self.body.as_ref ().map (|body| body.contains (&placeholder)).unwrap_or (false)
+            || self.headers.iter ().any (|&(_, ref value)| value.contains (&placeholder))
+    }
+
+    /// The `Content-Type` header of the definition, if any.
+    pub fn content_type (&self) -> Option<&str> {
+        self.headers.iter ()
+            .find (|&&(ref name, _)| name.eq_ignore_ascii_case ("content-type"))
+            .map (|&(_, ref value)| value.as_str ())
+    }
+}
+
+/// How a value is written into a template.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// As it is.
+    Raw,
+    /// Percent-encoded, for addresses and form bodies.
+    Query,
+    /// Escaped for a JSON string, the quotes being part of the template.
+    Json,
+}
+
+impl Encoding {
+    /// The encoding a body with this `Content-Type` needs.
+    pub fn for_content_type (content_type: Option<&str>) -> Encoding {
+        match content_type.map (|content_type| content_type.to_lowercase ()) {
+            Some (ref content_type) if content_type.contains ("json") => Encoding::Json,
+            Some (ref content_type) if content_type.contains ("x-www-form-urlencoded") => Encoding::Query,
+            _ => Encoding::Raw,
+        }
+    }
+
+    fn encode (self, value: &str) -> String {
+        match self {
+            Encoding::Raw => value.to_owned (),
+            Encoding::Query => value.bytes ()
+                .map (|byte| match byte {
+                    byte if byte.is_ascii_alphanumeric () || b"-._~".contains (&byte) => (byte as char).to_string (),
+                    byte => format!("%{:02X}", byte),
+                })
+                .collect (),
+            Encoding::Json => {
+                let quoted = Value::String (value.to_owned ()).to_string ();
+                quoted[1..quoted.len () - 1].to_owned ()
+            },
+        }
+    }
+}
+
+/// `template` with every `{name}` of `values` replaced in one pass, so a
+/// value containing a placeholder is left alone. Other braces stay.
+pub fn fill (template: &str, values: &[(&str, &str)], encoding: Encoding) -> String {
+    let mut filled = String::with_capacity (template.len ());
+    let mut rest = template;
+    while let Some (open) = rest.find ('{') {
+        filled.push_str (&rest[..open]);
+        rest = &rest[open..];
+        let found = values.iter ().find (|&&(name, _)| {
+            rest.len () > name.len () + 1 && rest[1..].starts_with (name) && rest[name.len () + 1..].starts_with ('}')
+        });
+        match found {
+            Some (&(name, value)) => {
+                filled.push_str (&encoding.encode (value));
+                rest = &rest[name.len () + 2..];
+            },
+            None => {
+                filled.push ('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    filled.push_str (rest);
+    filled
+}
+
+/// Reads a definitions file: `{"providers": [...]}`, a list of definitions
+/// or a single one.
+pub fn parse_definitions (text: &str) -> Result<Vec<ProviderDefinition>, DefinitionError> {
+    let json: Value = serde_json::from_str (text)
+        .map_err (|error| DefinitionError (format!("Not a JSON file: {}", error)))?;
+    let list = match json {
+        Value::Object (ref object) if object.contains_key ("providers") => match json["providers"] {
+            Value::Array (ref list) => list.clone (),
+            _ => return Err (DefinitionError ("\"providers\" must be a list".to_owned ())),
+        },
+        Value::Array (ref list) => list.clone (),
+        Value::Object (_) => vec![json.clone ()],
+        _ => return Err (DefinitionError ("Expected provider definitions".to_owned ())),
+    };
+
+    let mut definitions: Vec<ProviderDefinition> = Vec::new ();
+    for value in &list {
+        let definition = ProviderDefinition::from_json (value)?;
+        if definitions.iter ().any (|other| other.id == definition.id) {
+            return Err (DefinitionError (format!("The id \"{}\" is used twice", definition.id)));
+        }
+        definitions.push (definition);
+    }
+    Ok (definitions)
+}
+
+/// The definitions file of the user.
+pub struct DefinitionFile {
+    path: PathBuf,
+}
+
+impl DefinitionFile {
+    pub fn new () -> DefinitionFile {
+        DefinitionFile::with_path (paths::config_dir ().join ("custom-providers.json"))
+    }
+
+    pub fn with_path (path: PathBuf) -> DefinitionFile {
+        DefinitionFile { path }
+    }
+
+    pub fn path (&self) -> &Path {
+        &self.path
+    }
+
+    /// Every definition, none while the file does not exist.
+    pub fn load (&self) -> Result<Vec<ProviderDefinition>, DefinitionError> {
+        let mut text = String::new ();
+        match File::open (&self.path) {
+            Ok (mut file) => { file.read_to_string (&mut text)?; },
+            Err (ref error) if error.kind () == ErrorKind::NotFound => return Ok (Vec::new ()),
+            Err (error) => return Err (error.into ()),
+        }
+        parse_definitions (&text)
+            .map_err (|error| DefinitionError (format!("{}: {}", self.path.display (), error)))
+    }
+
+    pub fn save (&self, definitions: &[ProviderDefinition]) -> Result<(), DefinitionError> {
+        if let Some (dir) = self.path.parent () {
+            fs::create_dir_all (dir)?;
+        }
+        let temporary = self.path.with_extension ("json.tmp");
+        write_definitions (&temporary, definitions)?;
+        fs::rename (&temporary, &self.path)?;
+        Ok (())
+    }
+
+    /// Adds the definitions of `file`, replacing the ones with the same id.
+    /// Returns the ids imported.
+    pub fn import (&self, file: &Path) -> Result<Vec<String>, DefinitionError> {
+        let mut text = String::new ();
+        File::open (file)?.read_to_string (&mut text)?;
+        let imported = parse_definitions (&text)
+            .map_err (|error| DefinitionError (format!("{}: {}", file.display (), error)))?;
+
+        let mut definitions = self.load ()?;
+        definitions.retain (|definition| !imported.iter ().any (|new| new.id == definition.id));
+        definitions.extend (imported.iter ().cloned ());
+        self.save (&definitions)?;
+        Ok (imported.into_iter ().map (|definition| definition.id).collect ())
+    }
+
+    /// Writes every definition to `file`, to share them. Tokens are not part
+    /// of the definitions.
+    pub fn export (&self, file: &Path) -> Result<usize, DefinitionError> {
+        let definitions = self.load ()?;
+        write_definitions (file, &definitions)?;
+        Ok (definitions.len ())
+    }
+
+    pub fn remove (&self, id: &str) -> Result<(), DefinitionError> {
+        let mut definitions = self.load ()?;
+        definitions.retain (|definition| definition.id != id);
+        self.save (&definitions)
+    }
+}
+
+fn write_definitions (path: &Path, definitions: &[ProviderDefinition]) -> Result<(), DefinitionError> {
+    let json = json!({ "providers": definitions.iter ().map (|definition| definition.to_json ()).collect::<Vec<_>> () });
+    let text = serde_json::to_string_pretty (&json).map_err (|error| DefinitionError (error.to_string ()))?;
+    let mut file = File::create (path)?;
+    writeln!(file, "{}", text)?;
+    file.sync_all ()?;
+    Ok (())
+}
+
+/// Follows `path` through the elements of `document`, see `Extract::XPath`.
+fn xpath (document: &str, path: &str) -> Option<String> {
+    let lower = document.to_ascii_lowercase ();
+    let mut scope = (0, document.len ());
+    let mut start_tag: Option<(usize, usize)> = None;
+
+    for step in path.split ('/').filter (|step| !step.is_empty ()) {
+        if step.starts_with ('@') {
+            let (from, to) = start_tag?;
+            return attribute (&document[from..to], &step[1..]).map (|value| unescape (&value));
+        }
+        if step == "text()" {
+            break;
+        }
+
+        let name = step.to_ascii_lowercase ();
+        let open = format!("<{}", name);
+        let mut from = scope.0;
+        let found = loop {
+            let at = from + lower[from..scope.1].find (&open)?;
+            let after = lower[at + open.len ()..].chars ().next ();
+            if after.map (|c| c.is_whitespace () || c == '>' || c == '/').unwrap_or (false) {
+                break at;
+            }
+            from = at + open.len ();
+        };
+        let tag_end = found + lower[found..scope.1].find ('>')? + 1;
+        start_tag = Some ((found, tag_end));
+        scope = if document[..tag_end].ends_with ("/>") {
+            (tag_end, tag_end)
+        } else {
+            let close = lower[tag_end..scope.1].find (&format!("</{}", name)).map (|at| tag_end + at).unwrap_or (scope.1);
+            (tag_end, close)
+        };
+    }
+
+    start_tag?;
+    Some (unescape (&strip_tags (&document[scope.0..scope.1])))
+}
+
+/// The value of `name` in a start tag, quoted or not.
+fn attribute (tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase ();
+    let name = name.to_ascii_lowercase ();
+    let mut from = 0;
+    loop {
+        let at = from + lower[from..].find (&name)?;
+        from = at + name.len ();
+        let before = lower[..at].chars ().last ();
+        let rest = lower[from..].trim_start ();
+        if !before.map (|c| c.is_whitespace ()).unwrap_or (false) || !rest.starts_with ('=') {
+            continue;
+        }
+        let value = tag[tag.len () - rest.len () + 1..].trim_start ();
+        return match value.chars ().next () {
+            Some (quote) if quote == '"' || quote == '\'' => value[1..].find (quote).map (|end| value[1..end + 1].to_owned ()),
+            _ => Some (value.split (|c: char| c.is_whitespace () || c == '>' || c == '/').next ().unwrap_or ("").to_owned ()),
+        };
+    }
+}
+
+fn strip_tags (text: &str) -> String {
+    let text = text.replace ("<![CDATA[", "").replace ("]]>", "");
+    let mut stripped = String::with_capacity (text.len ());
+    let mut in_tag = false;
+    for c in text.chars () {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => stripped.push (c),
+            _ => {},
+        }
+    }
+    stripped
+}
+
+fn unescape (text: &str) -> String {
+    text.replace ("&lt;", "<")
+        .replace ("&gt;", ">")
+        .replace ("&quot;", "\"")
+        .replace ("&#39;", "'")
+        .replace ("&apos;", "'")
+        .replace ("&amp;", "&")
+}
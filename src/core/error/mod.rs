@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::credentials::CredentialError;
+
+/// Everything that can go wrong while shortening a URL.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShortenError {
+    /// The input could not be turned into a valid URL.
+    InvalidUrl (String),
+    /// No provider with this id is registered.
+    UnknownProvider (String),
+    /// The provider could not be reached or refused the request.
+    Unavailable (String),
+    /// The provider answered with something that is not a short URL.
+    Decode (String),
+    /// The provider needs a token and none is stored for it.
+    MissingCredentials (String),
+    /// The credential store could not be read.
+    Credentials (String),
+    /// Every provider of a fallback chain failed, with the error of each.
+    AllFailed (Vec<(String, ShortenError)>),
+    /// Expanding ran into a redirect loop or too many redirects.
+    Redirects (String),
+    /// The custom alias is already used by another link.
+    AliasTaken (String),
+    /// The custom alias is not accepted, or the provider has no aliases.
+    InvalidAlias (String),
+    /// The account reached a limit of the provider, e.g. links per month.
+    QuotaExceeded (String),
+    /// The `CancelFlag` of the request was set.
+    Cancelled,
+}
+
+impl fmt::Display for ShortenError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShortenError::InvalidUrl (ref url) => write!(f, "\"{}\" is not a valid URL", url),
+            ShortenError::UnknownProvider (ref id) => write!(f, "Unknown provider \"{}\"", id),
+            ShortenError::Unavailable (ref message) => write!(f, "{}", message),
+            ShortenError::Decode (ref message) => write!(f, "{}", message),
+            ShortenError::MissingCredentials (ref id) => write!(f, "No API token set for {}", id),
+            ShortenError::Credentials (ref message) => write!(f, "Could not read credentials: {}", message),
+            ShortenError::AllFailed (ref failures) => {
+                write!(f, "All providers failed")?;
+                for &(ref id, ref error) in failures {
+                    write!(f, "\n{}: {}", id, error)?;
+                }
+                Ok (())
+            },
+            ShortenError::Redirects (ref message) => write!(f, "{}", message),
+            ShortenError::AliasTaken (ref alias) => write!(f, "The alias \"{}\" is already taken", alias),
+            ShortenError::InvalidAlias (ref message) => write!(f, "{}", message),
+            ShortenError::QuotaExceeded (ref message) => write!(f, "{}", message),
+            ShortenError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl Error for ShortenError {}
+
+/// `ErrorKind::Other` is taken for a decode error, the rest for the
+/// service being out of reach.
+impl From<io::Error> for ShortenError {
+    fn from (error: io::Error) -> ShortenError {
+        match error.kind () {
+            io::ErrorKind::Other => ShortenError::Decode (error.to_string ()),
+            _ => ShortenError::Unavailable (error.to_string ()),
+        }
+    }
+}
+
+impl From<CredentialError> for ShortenError {
+    fn from (error: CredentialError) -> ShortenError {
+        ShortenError::Credentials (error.0)
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+
+use reqwest::Url;
+
+use super::{normalize, CancelFlag, HttpClient, HttpRequest, HttpResponse, ShortenError};
+
+/// How much of a page is read when looking for a `<meta refresh>`.
+const META_REFRESH_MAX_BODY: u64 = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpandOptions {
+    /// Redirects followed before giving up.
+    pub max_redirects: usize,
+    /// Also follow `<meta http-equiv="refresh">` of HTML pages, which
+    /// costs a GET of the page.
+    pub meta_refresh: bool,
+    /// Checked before each hop.
+    pub cancel: CancelFlag,
+}
+
+impl Default for ExpandOptions {
+    fn default () -> ExpandOptions {
+        ExpandOptions {
+            max_redirects: 10,
+            meta_refresh: true,
+            cancel: CancelFlag::new (),
+        }
+    }
+}
+
+/// One request of the chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hop {
+    pub url: String,
+    pub status: u16,
+    /// The page was left through a `<meta refresh>` rather than a 3xx.
+    pub meta_refresh: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expansion {
+    /// Every URL visited, the short URL first and the destination last.
+    pub hops: Vec<Hop>,
+}
+
+impl Expansion {
+    pub fn destination (&self) -> &str {
+        self.hops.last ().map (|hop| hop.url.as_str ()).unwrap_or ("")
+    }
+}
+
+/// Resolves a short URL to where it finally points.
+pub fn expand (url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+    expand_chain (url, http, &ExpandOptions::default ())
+        .map (|expansion| expansion.destination ().to_owned ())
+}
+
+/// Follows the redirects of `url` one by one, with HEAD requests where
+/// possible, and records every hop.
+pub fn expand_chain (url: &str, http: &dyn HttpClient, options: &ExpandOptions) -> Result<Expansion, ShortenError> {
+    let mut url = normalize (url.trim ())?;
+    let mut hops: Vec<Hop> = Vec::new ();
+    let mut visited = HashSet::new ();
+
+    loop {
+        if options.cancel.is_cancelled () {
+            return Err (ShortenError::Cancelled);
+        }
+        if !visited.insert (url.clone ()) {
+            let mut chain: Vec<&str> = hops.iter ().map (|hop| hop.url.as_str ()).collect ();
+            chain.push (&url);
+            return Err (ShortenError::Redirects (format!("Redirect loop: {}", chain.join (" -> "))));
+        }
+
+        let mut response = http.send (&HttpRequest::head (url.as_str ()).no_redirects ())?;
+        // Some servers only answer GET.
+        if response.status == 405 || response.status == 501 {
+            response = fetch_page (&url, http)?;
+        }
+
+        if hops.is_empty () && response.status >= 400 {
+            return Err (ShortenError::Unavailable (format!("{} answered with HTTP {}", url, response.status)));
+        }
+
+        let mut next = None;
+        let mut meta_refresh = false;
+        if response.status >= 300 && response.status < 400 {
+            next = response.header ("Location").map (|location| location.to_owned ());
+        } else if options.meta_refresh && response.is_success () && is_html (&response) {
+            if response.body.is_empty () {
+                response = fetch_page (&url, http)?;
+            }
+            next = find_meta_refresh (&response.body);
+            meta_refresh = next.is_some ();
+        }
+
+        hops.push (Hop {
+            url: url.clone (),
+            status: response.status,
+            meta_refresh,
+        });
+
+        let next = match next.and_then (|next| resolve (&url, &next)) {
+            Some (next) => next,
+            None => return Ok (Expansion { hops }),
+        };
+        if hops.len () > options.max_redirects {
+            return Err (ShortenError::Redirects (format!("More than {} redirects, last one to {}", options.max_redirects, next)));
+        }
+        url = next;
+    }
+}
+
+fn fetch_page (url: &str, http: &dyn HttpClient) -> Result<HttpResponse, ShortenError> {
+    let request = HttpRequest::get (url)
+        .no_redirects ()
+        .max_body (META_REFRESH_MAX_BODY);
+    Ok (http.send (&request)?)
+}
+
+fn is_html (response: &HttpResponse) -> bool {
+    response.header ("Content-Type")
+        .map (|content_type| content_type.to_ascii_lowercase ().contains ("html"))
+        .unwrap_or (false)
+}
+
+/// `Location` and refresh targets may be relative.
+fn resolve (base: &str, target: &str) -> Option<String> {
+    let base = Url::parse (base).ok ()?;
+    let target = base.join (target.trim ()).ok ()?;
+    match target.scheme () {
+        "http" | "https" => Some (target.into_string ()),
+        _ => None,
+    }
+}
+
+/// The URL of the first `<meta http-equiv="refresh" content="0; url=...">`
+/// in `html`.
+fn find_meta_refresh (html: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets, so they apply to `html` too.
+    let lower = html.to_ascii_lowercase ();
+    let mut offset = 0;
+
+    while let Some (start) = lower[offset..].find ("<meta") {
+        let start = offset + start;
+        let end = lower[start..].find ('>').map (|end| start + end)?;
+        offset = end;
+
+        let tag = &lower[start..end];
+        if !tag.contains ("http-equiv") || !tag.contains ("refresh") {
+            continue;
+        }
+        let content = match attribute (tag, "content") {
+            Some ((from, to)) => &html[start + from..start + to],
+            None => continue,
+        };
+        let url_start = match content.to_ascii_lowercase ().find ("url=") {
+            Some (url_start) => url_start + 4,
+            None => continue,
+        };
+        let url = content[url_start..].trim ().trim_matches (|c| c == '\'' || c == '"');
+        if !url.is_empty () {
+            return Some (url.to_owned ());
+        }
+    }
+    None
+}
+
+/// Byte range of the quoted value of `name` inside `tag`.
+fn attribute (tag: &str, name: &str) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    while let Some (found) = tag[offset..].find (name) {
+        let after_name = offset + found + name.len ();
+        offset = after_name;
+
+        let rest = &tag[after_name..];
+        let equals = rest.len () - rest.trim_start ().len ();
+        if !rest[equals..].starts_with ('=') {
+            continue;
+        }
+        let value = &rest[equals + 1..];
+        let value_start = after_name + equals + 1 + (value.len () - value.trim_start ().len ());
+        let quote = match tag[value_start..].chars ().next () {
+            Some (quote) if quote == '"' || quote == '\'' => quote,
+            _ => continue,
+        };
+        let value_end = tag[value_start + 1..].find (quote)? + value_start + 1;
+        return Some ((value_start + 1, value_end));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Method;
+    use super::super::testing::FakeHttp;
+
+    fn hops (expansion: &Expansion) -> Vec<(&str, u16, bool)> {
+        expansion.hops.iter ().map (|hop| (hop.url.as_str (), hop.status, hop.meta_refresh)).collect ()
+    }
+
+    #[test]
+    fn follows_redirects_hop_by_hop () {
+        let http = FakeHttp::new ()
+            .answer ("https://s.test/a", 301, "").header ("Location", "https://t.test/b")
+            .answer ("https://t.test/b", 302, "").header ("location", "/c?x=1")
+            .answer ("https://t.test/c?x=1", 200, "");
+        let expansion = expand_chain ("s.test/a", &http, &ExpandOptions::default ()).unwrap ();
+
+        assert_eq!(hops (&expansion), vec![("https://s.test/a", 301, false), ("https://t.test/b", 302, false), ("https://t.test/c?x=1", 200, false)]);
+        assert_eq!(expansion.destination (), "https://t.test/c?x=1");
+        assert!(http.sent ().iter ().all (|request| request.method == Method::Head && !request.follow_redirects));
+    }
+
+    #[test]
+    fn stops_at_a_loop () {
+        let http = FakeHttp::new ()
+            .answer ("https://s.test/a", 301, "").header ("Location", "https://s.test/b")
+            .answer ("https://s.test/b", 301, "").header ("Location", "https://s.test/a");
+
+        match expand_chain ("https://s.test/a", &http, &ExpandOptions::default ()) {
+            Err (ShortenError::Redirects (message)) => assert_eq!(message, "Redirect loop: https://s.test/a -> https://s.test/b -> https://s.test/a"),
+            other => panic!("expected a loop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stops_after_max_redirects () {
+        let http = FakeHttp::new ()
+            .answer ("https://s.test/1", 301, "").header ("Location", "/2")
+            .answer ("https://s.test/2", 301, "").header ("Location", "/3")
+            .answer ("https://s.test/3", 301, "").header ("Location", "/4");
+        let options = ExpandOptions { max_redirects: 2, ..ExpandOptions::default () };
+
+        match expand_chain ("https://s.test/1", &http, &options) {
+            Err (ShortenError::Redirects (message)) => assert_eq!(message, "More than 2 redirects, last one to https://s.test/4"),
+            other => panic!("expected too many redirects, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn follows_meta_refresh_of_html_pages () {
+        let page = "<html><head><META HTTP-EQUIV='Refresh' CONTENT='0; URL=https://t.test/Landing'></head></html>";
+        let http = FakeHttp::new ()
+            .answer_to (Method::Head, "https://s.test/a", 200, "").header ("Content-Type", "text/html; charset=utf-8")
+            .answer_to (Method::Get, "https://s.test/a", 200, page).header ("Content-Type", "text/html; charset=utf-8")
+            .answer ("https://t.test/Landing", 200, "").header ("Content-Type", "text/plain");
+        let expansion = expand_chain ("https://s.test/a", &http, &ExpandOptions::default ()).unwrap ();
+
+        assert_eq!(hops (&expansion), vec![("https://s.test/a", 200, true), ("https://t.test/Landing", 200, false)]);
+        assert_eq!(http.sent ()[1].max_body, Some (META_REFRESH_MAX_BODY));
+    }
+
+    #[test]
+    fn meta_refresh_can_be_switched_off () {
+        let http = FakeHttp::new ()
+            .answer_to (Method::Head, "https://s.test/a", 200, "").header ("Content-Type", "text/html")
+            .answer_to (Method::Get, "https://s.test/a", 200, "<meta http-equiv=\"refresh\" content=\"0;url=/b\">");
+        let options = ExpandOptions { meta_refresh: false, ..ExpandOptions::default () };
+
+        assert_eq!(expand_chain ("https://s.test/a", &http, &options).unwrap ().destination (), "https://s.test/a");
+        assert_eq!(http.sent_one ().method, Method::Head);
+    }
+
+    #[test]
+    fn falls_back_to_get_when_head_is_refused () {
+        let http = FakeHttp::new ()
+            .answer_to (Method::Head, "https://s.test/a", 405, "")
+            .answer_to (Method::Get, "https://s.test/a", 302, "").header ("Location", "https://t.test/")
+            .answer ("https://t.test/", 200, "");
+        let expansion = expand_chain ("https://s.test/a", &http, &ExpandOptions::default ()).unwrap ();
+
+        assert_eq!(hops (&expansion), vec![("https://s.test/a", 302, false), ("https://t.test/", 200, false)]);
+        let methods: Vec<Method> = http.sent ().iter ().map (|request| request.method).collect ();
+        assert_eq!(methods, vec![Method::Head, Method::Get, Method::Head]);
+    }
+
+    #[test]
+    fn stops_before_the_next_hop_once_cancelled () {
+        let options = ExpandOptions::default ();
+        let cancel = options.cancel.clone ();
+        let http = FakeHttp::new ().answer ("https://s.test/a", 301, "").header ("Location", "https://t.test/b");
+        cancel.cancel ();
+        assert_eq!(expand_chain ("https://s.test/a", &http, &options), Err (ShortenError::Cancelled));
+        assert!(http.sent ().is_empty ());
+    }
+
+    #[test]
+    fn a_broken_short_link_is_an_error () {
+        let http = FakeHttp::new ().answer ("https://s.test/gone", 404, "");
+        assert_eq!(expand ("https://s.test/gone", &http), Err (ShortenError::Unavailable ("https://s.test/gone answered with HTTP 404".to_owned ())));
+    }
+
+    #[test]
+    fn ignores_redirects_to_other_schemes () {
+        let http = FakeHttp::new ().answer ("https://s.test/a", 302, "").header ("Location", "mailto:me@example.com");
+        assert_eq!(expand ("https://s.test/a", &http), Ok ("https://s.test/a".to_owned ()));
+    }
+}
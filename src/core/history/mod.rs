@@ -0,0 +1,347 @@
+//! Every shortening, successful or not, kept as one JSON object per line in
+//! `$XDG_DATA_HOME/com.github.arshubham.srtnr/history.jsonl`.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libc;
+use openssl::rand::rand_bytes;
+use serde_json::{self, Value};
+
+use super::paths;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryError (pub String);
+
+impl fmt::Display for HistoryError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for HistoryError {}
+
+impl From<io::Error> for HistoryError {
+    fn from (error: io::Error) -> HistoryError {
+        HistoryError (error.to_string ())
+    }
+}
+
+/// One shortening attempt.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub long_url: String,
+    /// Empty when the attempt failed.
+    pub short_url: String,
+    /// Id of the provider which produced the link, or the one asked for.
+    pub provider: String,
+    /// Why the attempt failed, `None` on success.
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn succeeded (&self) -> bool {
+        self.error.is_none ()
+    }
+
+    /// Whether every whitespace separated term of `query` shows up in the
+    /// URLs, the provider or the error, ignoring case.
+    pub fn matches (&self, query: &str) -> bool {
+        let haystack = format!("{}\n{}\n{}\n{}",
+                               self.long_url, self.short_url, self.provider,
+                               self.error.as_ref ().map (|error| error.as_str ()).unwrap_or ("")).to_lowercase ();
+        query.split_whitespace ().all (|term| haystack.contains (&term.to_lowercase ()))
+    }
+
+    fn to_json (&self) -> Value {
+        json!({
+            "id": self.id,
+            "timestamp": self.timestamp,
+            "long_url": self.long_url,
+            "short_url": self.short_url,
+            "provider": self.provider,
+            "error": self.error,
+        })
+    }
+
+    fn from_json (value: &Value) -> Option<HistoryEntry> {
+        Some (HistoryEntry {
+            id: value["id"].as_u64 ()?,
+            timestamp: value["timestamp"].as_i64 ()?,
+            long_url: value["long_url"].as_str ()?.to_owned (),
+            short_url: value["short_url"].as_str ().unwrap_or ("").to_owned (),
+            provider: value["provider"].as_str ().unwrap_or ("").to_owned (),
+            error: value["error"].as_str ().map (|error| error.to_owned ()),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    NewestFirst,
+    OldestFirst,
+    LongUrl,
+    ShortUrl,
+    Provider,
+}
+
+impl SortOrder {
+    fn compare (&self, a: &HistoryEntry, b: &HistoryEntry) -> Ordering {
+        let newest_first = b.timestamp.cmp (&a.timestamp).then (b.id.cmp (&a.id));
+        match *self {
+            SortOrder::NewestFirst => newest_first,
+            SortOrder::OldestFirst => newest_first.reverse (),
+            SortOrder::LongUrl => a.long_url.to_lowercase ().cmp (&b.long_url.to_lowercase ()).then (newest_first),
+            SortOrder::ShortUrl => a.short_url.to_lowercase ().cmp (&b.short_url.to_lowercase ()).then (newest_first),
+            SortOrder::Provider => a.provider.cmp (&b.provider).then (newest_first),
+        }
+    }
+}
+
+/// The history file. New entries are appended, deleting rewrites the file.
+///
+/// The GUI, the command line and the D-Bus service each have their own
+/// `History` on the same file, so ids are not counted: they are the time
+/// in milliseconds followed by 16 random bits. Changes to the file hold a
+/// `FileLock`, the `Mutex` only orders the threads of one process.
+pub struct History {
+    path: PathBuf,
+    /// The last id given out by this process, which the next one exceeds.
+    last_id: Mutex<u64>,
+}
+
+/// An advisory lock on `<history>.lock`, released when dropped. The
+/// history file itself is replaced by `write`, so it cannot carry the lock.
+struct FileLock (File);
+
+impl FileLock {
+    fn exclusive (history: &Path) -> Result<FileLock, HistoryError> {
+        if let Some (dir) = history.parent () {
+            fs::create_dir_all (dir)?;
+        }
+        let file = OpenOptions::new ().write (true).create (true).truncate (false).open (history.with_extension ("jsonl.lock"))?;
+        if unsafe { libc::flock (file.as_raw_fd (), libc::LOCK_EX) } != 0 {
+            return Err (io::Error::last_os_error ().into ());
+        }
+        Ok (FileLock (file))
+    }
+}
+
+impl Drop for FileLock {
+    fn drop (&mut self) {
+        unsafe { libc::flock (self.0.as_raw_fd (), libc::LOCK_UN) };
+    }
+}
+
+impl History {
+    pub fn new () -> History {
+        History::with_path (paths::data_dir ().join ("history.jsonl"))
+    }
+
+    pub fn with_path (path: PathBuf) -> History {
+        History {
+            path,
+            last_id: Mutex::new (0),
+        }
+    }
+
+    /// Appends an attempt stamped with the current time and returns it.
+    pub fn record (&self, long_url: &str, short_url: &str, provider: &str, error: Option<String>) -> Result<HistoryEntry, HistoryError> {
+        let mut last_id = self.last_id.lock ().map_err (|_| HistoryError ("History lock poisoned".to_owned ()))?;
+        let id = new_id ()?.max (*last_id + 1);
+
+        let entry = HistoryEntry {
+            id,
+            timestamp: now (),
+            long_url: long_url.to_owned (),
+            short_url: short_url.to_owned (),
+            provider: provider.to_owned (),
+            error,
+        };
+
+        let _lock = FileLock::exclusive (&self.path)?;
+        let mut file = OpenOptions::new ().append (true).create (true).open (&self.path)?;
+        writeln!(file, "{}", entry.to_json ())?;
+
+        *last_id = id;
+        Ok (entry)
+    }
+
+    /// All entries, newest first.
+    pub fn entries (&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        self.search ("", SortOrder::NewestFirst)
+    }
+
+    /// Entries matching `query` (see `HistoryEntry::matches`), an empty
+    /// query matches everything.
+    pub fn search (&self, query: &str, order: SortOrder) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let _guard = self.last_id.lock ().map_err (|_| HistoryError ("History lock poisoned".to_owned ()))?;
+        let mut entries: Vec<HistoryEntry> = self.read ()?
+            .into_iter ()
+            .filter (|entry| entry.matches (query))
+            .collect ();
+        entries.sort_by (|a, b| order.compare (a, b));
+        Ok (entries)
+    }
+
+    pub fn get (&self, id: u64) -> Result<Option<HistoryEntry>, HistoryError> {
+        Ok (self.entries ()?.into_iter ().find (|entry| entry.id == id))
+    }
+
+    /// Short links whose entry matches `query`, newest first and at most
+    /// `limit`. A link shortened several times shows up once.
+    pub fn find_links (&self, query: &str, limit: usize) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let mut seen = HashSet::new ();
+        Ok (self.search (query, SortOrder::NewestFirst)?
+            .into_iter ()
+            .filter (|entry| entry.succeeded () && seen.insert (entry.short_url.clone ()))
+            .take (limit)
+            .collect ())
+    }
+
+    pub fn delete (&self, id: u64) -> Result<(), HistoryError> {
+        let _guard = self.last_id.lock ().map_err (|_| HistoryError ("History lock poisoned".to_owned ()))?;
+        let _lock = FileLock::exclusive (&self.path)?;
+        let entries = self.read ()?;
+        if entries.iter ().any (|entry| entry.id == id) {
+            self.write (entries.iter ().filter (|entry| entry.id != id))?;
+        }
+        Ok (())
+    }
+
+    pub fn clear (&self) -> Result<(), HistoryError> {
+        let _guard = self.last_id.lock ().map_err (|_| HistoryError ("History lock poisoned".to_owned ()))?;
+        let _lock = FileLock::exclusive (&self.path)?;
+        match fs::remove_file (&self.path) {
+            Err (ref error) if error.kind () != ErrorKind::NotFound => Err (HistoryError (error.to_string ())),
+            _ => Ok (()),
+        }
+    }
+
+    /// Lines which are not valid entries (a crash halfway through a write)
+    /// are skipped.
+    fn read (&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let file = match File::open (&self.path) {
+            Ok (file) => file,
+            Err (ref error) if error.kind () == ErrorKind::NotFound => return Ok (Vec::new ()),
+            Err (error) => return Err (error.into ()),
+        };
+
+        let mut entries = Vec::new ();
+        for line in BufReader::new (file).lines () {
+            let line = line?;
+            if let Some (entry) = serde_json::from_str (&line).ok ().and_then (|value| HistoryEntry::from_json (&value)) {
+                entries.push (entry);
+            }
+        }
+        Ok (entries)
+    }
+
+    /// Writes to a temporary file first so a crash cannot lose the history.
+    /// Its name is unique to the process, in case a lock is ignored.
+    fn write<'a, I: Iterator<Item = &'a HistoryEntry>> (&self, entries: I) -> Result<(), HistoryError> {
+        let temporary = self.path.with_extension (format!("jsonl.{}.tmp", process::id ()));
+        {
+            let mut file = File::create (&temporary)?;
+            for entry in entries {
+                writeln!(file, "{}", entry.to_json ())?;
+            }
+            file.sync_all ()?;
+        }
+        fs::rename (&temporary, &self.path)?;
+        Ok (())
+    }
+}
+
+/// Milliseconds since the epoch in the high bits, random low bits so two
+/// processes recording in the same millisecond do not collide.
+fn new_id () -> Result<u64, HistoryError> {
+    let millis = SystemTime::now ().duration_since (UNIX_EPOCH)
+        .map (|elapsed| elapsed.as_secs () * 1000 + u64::from (elapsed.subsec_nanos () / 1_000_000))
+        .unwrap_or (0);
+    let mut random = [0u8; 2];
+    rand_bytes (&mut random).map_err (|error| HistoryError (error.to_string ()))?;
+    Ok (millis << 16 | u64::from (random[0]) << 8 | u64::from (random[1]))
+}
+
+fn now () -> i64 {
+    SystemTime::now ().duration_since (UNIX_EPOCH)
+        .map (|elapsed| elapsed.as_secs () as i64)
+        .unwrap_or (0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::temporary_path;
+
+    #[test]
+    fn processes_sharing_the_file_do_not_reuse_ids () {
+        let path = temporary_path ("history.jsonl");
+        let gui = History::with_path (path.clone ());
+        let cli = History::with_path (path.clone ());
+
+        let mut ids = HashSet::new ();
+        for _ in 0..50 {
+            ids.insert (gui.record ("https://example.com/", "https://is.gd/a", "is.gd", None).unwrap ().id);
+            ids.insert (cli.record ("https://example.com/", "", "v.gd", Some ("timed out".to_owned ())).unwrap ().id);
+        }
+        assert_eq!(ids.len (), 100);
+        assert_eq!(History::with_path (path).entries ().unwrap ().len (), 100);
+    }
+
+    #[test]
+    fn ids_grow_within_a_process () {
+        let history = History::with_path (temporary_path ("history.jsonl"));
+        let first = history.record ("https://example.com/", "https://is.gd/a", "is.gd", None).unwrap ().id;
+        let second = history.record ("https://example.com/", "https://is.gd/b", "is.gd", None).unwrap ().id;
+        assert!(second > first);
+    }
+
+    #[test]
+    fn deletes_by_id () {
+        let history = History::with_path (temporary_path ("history.jsonl"));
+        let kept = history.record ("https://a.test/", "https://is.gd/a", "is.gd", None).unwrap ();
+        let deleted = history.record ("https://b.test/", "https://is.gd/b", "is.gd", None).unwrap ();
+
+        history.delete (deleted.id).unwrap ();
+        assert_eq!(history.get (deleted.id), Ok (None));
+        assert_eq!(history.entries (), Ok (vec![kept]));
+    }
+
+    #[test]
+    fn appends_of_other_processes_survive_a_delete () {
+        let path = temporary_path ("history.jsonl");
+        let deleted = History::with_path (path.clone ()).record ("https://a.test/", "https://is.gd/a", "is.gd", None).unwrap ();
+
+        let writers: Vec<_> = (0..4).map (|_| {
+            let path = path.clone ();
+            std::thread::spawn (move || {
+                let history = History::with_path (path);
+                for _ in 0..25 {
+                    history.record ("https://b.test/", "https://is.gd/b", "is.gd", None).unwrap ();
+                }
+            })
+        }).collect ();
+        History::with_path (path.clone ()).delete (deleted.id).unwrap ();
+        for writer in writers {
+            writer.join ().unwrap ();
+        }
+
+        let entries = History::with_path (path).entries ().unwrap ();
+        assert_eq!(entries.len (), 100);
+        assert!(entries.iter ().all (|entry| entry.id != deleted.id));
+    }
+}
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::time::Duration;
+
+use reqwest;
+use reqwest::header::Headers;
+use reqwest::RedirectPolicy;
+
+use super::ShortenError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+/// A plain HTTP request, independent of the HTTP library doing the work.
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Let the back-end follow 3xx answers, true by default.
+    pub follow_redirects: bool,
+    /// Stop reading the response body after this many bytes.
+    pub max_body: Option<u64>,
+}
+
+impl HttpRequest {
+    pub fn new<S: Into<String>> (method: Method, url: S) -> HttpRequest {
+        HttpRequest {
+            method,
+            url: url.into (),
+            headers: Vec::new (),
+            body: None,
+            follow_redirects: true,
+            max_body: None,
+        }
+    }
+
+    pub fn get<S: Into<String>> (url: S) -> HttpRequest {
+        HttpRequest::new (Method::Get, url)
+    }
+
+    pub fn head<S: Into<String>> (url: S) -> HttpRequest {
+        HttpRequest::new (Method::Head, url)
+    }
+
+    pub fn post<S: Into<String>> (url: S) -> HttpRequest {
+        HttpRequest::new (Method::Post, url)
+    }
+
+    pub fn header<N: Into<String>, V: Into<String>> (mut self, name: N, value: V) -> HttpRequest {
+        self.headers.push ((name.into (), value.into ()));
+        self
+    }
+
+    pub fn body<S: Into<String>> (mut self, body: S) -> HttpRequest {
+        self.body = Some (body.into ());
+        self
+    }
+
+    /// Hands 3xx answers back instead of following them.
+    pub fn no_redirects (mut self) -> HttpRequest {
+        self.follow_redirects = false;
+        self
+    }
+
+    pub fn max_body (mut self, bytes: u64) -> HttpRequest {
+        self.max_body = Some (bytes);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    /// Final URL, after the redirects the back-end followed.
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// First value of the header `name`, compared case-insensitively.
+    pub fn header (&self, name: &str) -> Option<&str> {
+        self.headers.iter ()
+            .find (|&&(ref key, _)| key.eq_ignore_ascii_case (name))
+            .map (|&(_, ref value)| value.as_str ())
+    }
+
+    pub fn is_success (&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+/// The request never got an HTTP answer (DNS, TLS, timeout, ...).
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpError (pub String);
+
+impl fmt::Display for HttpError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for HttpError {}
+
+impl From<HttpError> for ShortenError {
+    fn from (error: HttpError) -> ShortenError {
+        ShortenError::Unavailable (error.0)
+    }
+}
+
+/// HTTP back-end used by providers. Front-ends pass `ReqwestClient`, tests
+/// can pass anything that answers requests.
+pub trait HttpClient: Send + Sync {
+    fn send (&self, request: &HttpRequest) -> Result<HttpResponse, HttpError>;
+}
+
+pub struct ReqwestClient {
+    client: reqwest::Client,
+    /// Same as `client` but hands redirects back, the redirect policy is
+    /// fixed when a reqwest client is built.
+    no_redirect_client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    pub fn new (timeout: Duration) -> Result<ReqwestClient, HttpError> {
+        let client = reqwest::Client::builder ()
+            .timeout (timeout)
+            .build ()
+            .map_err (|error| HttpError (error.to_string ()))?;
+        let no_redirect_client = reqwest::Client::builder ()
+            .timeout (timeout)
+            .redirect (RedirectPolicy::none ())
+            .build ()
+            .map_err (|error| HttpError (error.to_string ()))?;
+
+        Ok (ReqwestClient { client, no_redirect_client })
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn send (&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::Get,
+            Method::Head => reqwest::Method::Head,
+            Method::Post => reqwest::Method::Post,
+            Method::Put => reqwest::Method::Put,
+            Method::Patch => reqwest::Method::Patch,
+            Method::Delete => reqwest::Method::Delete,
+        };
+
+        let mut headers = Headers::new ();
+        for &(ref name, ref value) in &request.headers {
+            headers.set_raw (name.clone (), value.clone ());
+        }
+
+        let client = if request.follow_redirects { &self.client } else { &self.no_redirect_client };
+        let mut builder = client.request (method, request.url.as_str ());
+        builder.headers (headers);
+        if let Some (ref body) = request.body {
+            builder.body (body.clone ());
+        }
+
+        let mut response = builder.send ().map_err (|error| HttpError (error.to_string ()))?;
+        let url = response.url ().as_str ().to_owned ();
+        let status = response.status ().as_u16 ();
+        let headers = response.headers ()
+            .iter ()
+            .map (|header| (header.name ().to_owned (), header.value_string ()))
+            .collect ();
+        let body = match request.max_body {
+            Some (limit) => {
+                let mut bytes = Vec::new ();
+                response.take (limit).read_to_end (&mut bytes).map_err (|error| HttpError (error.to_string ()))?;
+                String::from_utf8_lossy (&bytes).into_owned ()
+            },
+            None => response.text ().map_err (|error| HttpError (error.to_string ()))?,
+        };
+
+        Ok (HttpResponse { url, status, headers, body })
+    }
+}
@@ -0,0 +1,38 @@
+//! Typed shortening API: build a `ShortenRequest`, hand it to a `Shortener`
+//! and get a `ShortenOutcome` back.
+
+pub mod batch;
+pub mod clean;
+pub mod config;
+pub mod credentials;
+pub mod custom;
+pub mod history;
+pub mod qr;
+
+mod cancel;
+mod clipboard;
+mod error;
+mod expand;
+mod http;
+mod normalize;
+mod paths;
+mod provider;
+mod redirector;
+mod shortener;
+#[cfg(test)]
+mod testing;
+
+pub use self::cancel::CancelFlag;
+pub use self::clipboard::{Clipboard, CommandClipboard, CopiedUrlFilter, NoClipboard};
+pub use self::error::ShortenError;
+pub use self::expand::{expand, expand_chain, ExpandOptions, Expansion, Hop};
+pub use self::http::{HttpClient, HttpError, HttpRequest, HttpResponse, Method, ReqwestClient};
+pub use self::normalize::{is_valid_url, normalize, normalize_with, NormalizeOptions};
+pub use self::provider::{builtin_providers, custom_providers, legacy_provider_id, retired_reason, BuiltinProvider, CredentialProvider, LinkOptions, Provider, DEFAULT_PROVIDER};
+pub use self::provider::{BitlyAccount, BitlyGroup, BitlyProvider, BITLY};
+pub use self::provider::CustomProvider;
+pub use self::provider::{KuttProvider, KUTT};
+pub use self::provider::{ShlinkDomain, ShlinkProvider, SHLINK};
+pub use self::provider::{YourlsLink, YourlsProvider, YourlsStats, YOURLS};
+pub use self::redirector::{unwrap_redirector, Unwrapped};
+pub use self::shortener::{picked_provider, ShortenOutcome, ShortenRequest, Shortener, AUTOMATIC};
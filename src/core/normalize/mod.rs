@@ -0,0 +1,168 @@
+use reqwest::Url;
+use validator;
+
+use super::{HttpClient, HttpRequest, ShortenError};
+
+pub fn is_valid_url (url: &str) -> bool {
+    validator::validate_url (url)
+}
+
+/// How typed input is turned into a URL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NormalizeOptions {
+    /// Scheme used when none was typed.
+    pub default_scheme: String,
+    /// When no scheme was typed, check that the site answers over
+    /// `default_scheme` and fall back to the other one if not. Costs a
+    /// request.
+    pub probe: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default () -> NormalizeOptions {
+        NormalizeOptions {
+            default_scheme: "https".to_owned (),
+            probe: false,
+        }
+    }
+}
+
+/// Turns user input into the URL sent to providers: trims it, adds
+/// `https://` when no scheme was typed, lowercases the scheme and host,
+/// converts international domain names to punycode and percent-encodes
+/// what cannot appear in a URL.
+pub fn normalize (input: &str) -> Result<String, ShortenError> {
+    parse (input, &NormalizeOptions::default ()).map (|(url, _)| url.into_string ())
+}
+
+/// `normalize` with `options`, probing the scheme through `http` if asked.
+pub fn normalize_with (input: &str, options: &NormalizeOptions, http: &dyn HttpClient) -> Result<String, ShortenError> {
+    let (url, scheme_added) = parse (input, options)?;
+    if !scheme_added || !options.probe {
+        return Ok (url.into_string ());
+    }
+
+    // Any HTTP answer, even an error page, shows the scheme is served.
+    if http.send (&HttpRequest::head (url.as_str ())).is_ok () {
+        return Ok (url.into_string ());
+    }
+    let other = if url.scheme () == "https" { "http" } else { "https" };
+    let mut fallback = url.clone ();
+    if fallback.set_scheme (other).is_ok () && http.send (&HttpRequest::head (fallback.as_str ())).is_ok () {
+        return Ok (fallback.into_string ());
+    }
+    Ok (url.into_string ())
+}
+
+/// The parsed URL, and whether the scheme was added.
+fn parse (input: &str, options: &NormalizeOptions) -> Result<(Url, bool), ShortenError> {
+    let invalid = || ShortenError::InvalidUrl (input.trim ().to_owned ());
+
+    // Links pasted from mail and chat are often wrapped in <> or quotes.
+    let trimmed = input.trim ()
+        .trim_matches (|c| c == '<' || c == '>' || c == '"' || c == '\'')
+        .trim ();
+    if trimmed.is_empty () {
+        return Err (invalid ());
+    }
+
+    let scheme_added = !has_scheme (trimmed);
+    let url = if scheme_added {
+        Url::parse (&format!("{}://{}", options.default_scheme, trimmed))
+    } else {
+        Url::parse (trimmed)
+    };
+    let url = url.map_err (|_| invalid ())?;
+
+    match url.scheme () {
+        "http" | "https" => (),
+        _ => return Err (invalid ()),
+    }
+    // A bare word is more likely a typo than an intranet host.
+    match url.host_str () {
+        Some (host) if host.contains ('.') || host.contains (':') || host == "localhost" => (),
+        _ => return Err (invalid ()),
+    }
+    Ok ((url, scheme_added))
+}
+
+/// Whether `input` starts with `scheme://`, in any case, or with a
+/// `scheme:` such as `mailto:` which is not a host and port.
+fn has_scheme (input: &str) -> bool {
+    let is_scheme = |scheme: &str| {
+        scheme.chars ().next ().map (|c| c.is_ascii_alphabetic ()).unwrap_or (false)
+            && scheme.chars ().all (|c| c.is_ascii_alphanumeric () || c == '+' || c == '-' || c == '.')
+    };
+    if let Some (end) = input.find ("://") {
+        return is_scheme (&input[..end]);
+    }
+    match input.find (':') {
+        Some (end) => {
+            let scheme = &input[..end];
+            let port = input[end + 1..].chars ().next ().map (|c| c.is_ascii_digit ()).unwrap_or (false);
+            !scheme.contains ('.') && !port && is_scheme (scheme)
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::FakeHttp;
+
+    #[test]
+    fn adds_https_when_no_scheme_was_typed () {
+        assert_eq!(normalize ("example.com/page"), Ok ("https://example.com/page".to_owned ()));
+        assert_eq!(normalize ("localhost:8080"), Ok ("https://localhost:8080/".to_owned ()));
+    }
+
+    #[test]
+    fn lowercases_scheme_and_host_only () {
+        assert_eq!(normalize ("HTTP://Example.COM/Path?Q=A"), Ok ("http://example.com/Path?Q=A".to_owned ()));
+    }
+
+    #[test]
+    fn strips_wrapping_and_whitespace () {
+        assert_eq!(normalize ("  <https://example.com/a>  "), Ok ("https://example.com/a".to_owned ()));
+        assert_eq!(normalize ("\"example.com\""), Ok ("https://example.com/".to_owned ()));
+    }
+
+    #[test]
+    fn encodes_international_domains_and_spaces () {
+        assert_eq!(normalize ("bücher.de/a b"), Ok ("https://xn--bcher-kva.de/a%20b".to_owned ()));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_web_link () {
+        for input in &["", "   ", "hello", "ftp://example.com/", "javascript:alert(1)", "mailto:me@example.com"] {
+            assert_eq!(normalize (input), Err (ShortenError::InvalidUrl (input.trim ().to_owned ())), "{}", input);
+        }
+    }
+
+    #[test]
+    fn probe_falls_back_to_http () {
+        let http = FakeHttp::new ()
+            .fail ("https://example.com/", "connection refused")
+            .answer ("http://example.com/", 200, "");
+        let options = NormalizeOptions { probe: true, ..NormalizeOptions::default () };
+
+        assert_eq!(normalize_with ("example.com", &options, &http), Ok ("http://example.com/".to_owned ()));
+        assert_eq!(http.sent ().len (), 2);
+    }
+
+    #[test]
+    fn probe_keeps_https_when_nothing_answers () {
+        let options = NormalizeOptions { probe: true, ..NormalizeOptions::default () };
+        assert_eq!(normalize_with ("example.com", &options, &FakeHttp::new ()), Ok ("https://example.com/".to_owned ()));
+    }
+
+    #[test]
+    fn typed_scheme_is_never_probed () {
+        let http = FakeHttp::new ();
+        let options = NormalizeOptions { probe: true, ..NormalizeOptions::default () };
+
+        assert_eq!(normalize_with ("https://example.com", &options, &http), Ok ("https://example.com/".to_owned ()));
+        assert!(http.sent ().is_empty ());
+    }
+}
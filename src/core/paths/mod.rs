@@ -0,0 +1,31 @@
+use std::env;
+use std::path::PathBuf;
+
+const APP_ID: &str = "com.github.arshubham.srtnr";
+
+fn xdg_dir (variable: &str, fallback: &str) -> PathBuf {
+    match env::var_os (variable) {
+        Some (ref dir) if !dir.is_empty () => PathBuf::from (dir),
+        _ => {
+            let home = env::var_os ("HOME").unwrap_or_else (|| "/tmp".into ());
+            PathBuf::from (home).join (fallback)
+        },
+    }
+}
+
+/// `$XDG_DATA_HOME/com.github.arshubham.srtnr`, not created.
+pub fn data_dir () -> PathBuf {
+    xdg_dir ("XDG_DATA_HOME", ".local/share").join (APP_ID)
+}
+
+/// `$XDG_CONFIG_HOME/com.github.arshubham.srtnr`, not created.
+pub fn config_dir () -> PathBuf {
+    xdg_dir ("XDG_CONFIG_HOME", ".config").join (APP_ID)
+}
+
+/// Login name of the current user, empty when unknown.
+pub fn user_name () -> String {
+    env::var ("USER")
+        .or_else (|_| env::var ("LOGNAME"))
+        .unwrap_or_default ()
+}
@@ -0,0 +1,101 @@
+//! Custom aliases ("vanity slugs"). The `urlshortener` crate only sends the
+//! URL, so the services which accept an alias are called directly.
+
+use reqwest::Url;
+use serde_json::{self, Value};
+
+use super::super::{HttpClient, HttpRequest, HttpResponse, ShortenError};
+
+/// What a service accepts as alias.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AliasRule {
+    pub min_len: usize,
+    pub max_len: usize,
+    /// Allowed besides ASCII letters and digits.
+    pub extra_chars: &'static str,
+}
+
+impl AliasRule {
+    /// Rejects `alias` before anything is sent, with a message saying why.
+    pub fn check (&self, alias: &str) -> Result<(), ShortenError> {
+        let len = alias.chars ().count ();
+        if len < self.min_len || len > self.max_len {
+            let range = if self.min_len == self.max_len {
+                self.min_len.to_string ()
+            } else {
+                format!("{} to {}", self.min_len, self.max_len)
+            };
+            return Err (ShortenError::InvalidAlias (format!("The alias \"{}\" must be {} characters long", alias, range)));
+        }
+        if let Some (c) = alias.chars ().find (|&c| !c.is_ascii_alphanumeric () && !self.extra_chars.contains (c)) {
+            let allowed = if self.extra_chars.is_empty () {
+                "letters and digits".to_owned ()
+            } else {
+                format!("letters, digits and {}", self.extra_chars.chars ().map (|c| format!("\"{}\"", c)).collect::<Vec<_>> ().join (" "))
+            };
+            return Err (ShortenError::InvalidAlias (format!("The alias \"{}\" contains \"{}\", only {} are allowed", alias, c, allowed)));
+        }
+        Ok (())
+    }
+}
+
+/// is.gd and v.gd.
+pub const GD_RULE: AliasRule = AliasRule { min_len: 5, max_len: 30, extra_chars: "_" };
+/// YOURLS keywords, as configured by tny.im.
+pub const YOURLS_RULE: AliasRule = AliasRule { min_len: 1, max_len: 50, extra_chars: "" };
+
+/// `create.php` of is.gd and v.gd, which takes the alias as `shorturl`.
+pub fn gd (endpoint: &str, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+    GD_RULE.check (alias)?;
+    let endpoint = with_params (endpoint, &[("format", "json"), ("url", url), ("shorturl", alias)])?;
+    let json = decode (&http.send (&HttpRequest::get (endpoint))?)?;
+
+    if let Some (short_url) = json["shorturl"].as_str () {
+        return Ok (short_url.to_owned ());
+    }
+    let message = json["errormessage"].as_str ().unwrap_or ("Unknown error").to_owned ();
+    // 2 is a problem with the alias, taken or not allowed.
+    match json["errorcode"].as_u64 () {
+        Some (2) if message.contains ("already exists") => Err (ShortenError::AliasTaken (alias.to_owned ())),
+        Some (2) => Err (ShortenError::InvalidAlias (message)),
+        _ => Err (ShortenError::Unavailable (message)),
+    }
+}
+
+/// The public API of a YOURLS instance, which takes the alias as `keyword`.
+pub fn yourls (endpoint: &str, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+    YOURLS_RULE.check (alias)?;
+    let endpoint = with_params (endpoint, &[("action", "shorturl"), ("format", "json"), ("url", url), ("keyword", alias)])?;
+    yourls_answer (&decode (&http.send (&HttpRequest::get (endpoint))?)?, Some (alias))
+}
+
+/// The short link in the answer of the `shorturl` action of YOURLS.
+pub fn yourls_answer (json: &Value, alias: Option<&str>) -> Result<String, ShortenError> {
+    let message = json["message"].as_str ().unwrap_or ("Unknown error").to_owned ();
+    match (json["status"].as_str (), json["code"].as_str (), json["shorturl"].as_str (), alias) {
+        (Some ("success"), _, Some (short_url), _) => Ok (short_url.to_owned ()),
+        (_, Some ("error:keyword"), _, Some (alias)) => Err (ShortenError::AliasTaken (alias.to_owned ())),
+        // YOURLS gives every URL a single keyword and answers with it.
+        (_, Some ("error:url"), Some (short_url), Some (_)) => Err (ShortenError::InvalidAlias (format!("This URL was already shortened as {}, it cannot get another alias", short_url))),
+        (_, Some ("error:url"), Some (short_url), None) => Ok (short_url.to_owned ()),
+        _ => Err (ShortenError::Unavailable (message)),
+    }
+}
+
+pub fn with_params (endpoint: &str, params: &[(&str, &str)]) -> Result<String, ShortenError> {
+    Url::parse_with_params (endpoint, params)
+        .map (|url| url.into_string ())
+        .map_err (|error| ShortenError::Unavailable (error.to_string ()))
+}
+
+/// `params` encoded as the body of a form.
+pub fn form_body (params: &[(&str, &str)]) -> Result<String, ShortenError> {
+    let url = Url::parse_with_params ("http://localhost/", params)
+        .map_err (|error| ShortenError::Unavailable (error.to_string ()))?;
+    Ok (url.query ().unwrap_or ("").to_owned ())
+}
+
+pub fn decode (response: &HttpResponse) -> Result<Value, ShortenError> {
+    serde_json::from_str (&response.body)
+        .map_err (|_| ShortenError::Decode (format!("Unexpected answer (HTTP {}) from {}", response.status, response.url)))
+}
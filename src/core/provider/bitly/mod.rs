@@ -0,0 +1,309 @@
+//! bit.ly, through the v4 API. The v3 endpoint `urlshortener` calls is
+//! retired and took the token in the query string.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use super::super::config::{ConfigField, ProviderConfig};
+use super::super::credentials::CredentialStore;
+use super::super::{HttpClient, HttpRequest, HttpResponse, ShortenError};
+use super::alias::{decode, AliasRule};
+use super::{LinkOptions, Provider};
+
+/// The id `urlshortener` gave it, which the stored tokens and settings use.
+pub const BITLY: &str = "bitly.com";
+
+const API: &str = "https://api-ssl.bitly.com/v4";
+
+/// bit.ly custom back-halves.
+pub const BITLY_RULE: AliasRule = AliasRule { min_len: 1, max_len: 100, extra_chars: "-_" };
+
+const FIELDS: &[ConfigField] = &[
+    ConfigField { key: "group", label: "Group", placeholder: "The default group of the account", choices: &[] },
+    ConfigField { key: "domain", label: "Branded domain", placeholder: "bit.ly", choices: &[] },
+];
+
+/// A group of the account, with its branded short domains.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitlyGroup {
+    pub guid: String,
+    pub name: String,
+    pub domains: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitlyAccount {
+    /// Used when no group is set.
+    pub default_group: Option<String>,
+    pub groups: Vec<BitlyGroup>,
+}
+
+impl BitlyAccount {
+    /// The branded domains of every group, once each.
+    pub fn domains (&self) -> Vec<String> {
+        let mut domains: Vec<String> = Vec::new ();
+        for domain in self.groups.iter ().flat_map (|group| group.domains.iter ()) {
+            if !domains.contains (domain) {
+                domains.push (domain.clone ());
+            }
+        }
+        domains
+    }
+}
+
+/// bit.ly with the token stored as `bitly.com`, the group and branded
+/// domain set up in the preferences.
+pub struct BitlyProvider {
+    store: Arc<dyn CredentialStore>,
+    config: Arc<ProviderConfig>,
+}
+
+impl BitlyProvider {
+    pub fn new (store: Arc<dyn CredentialStore>, config: Arc<ProviderConfig>) -> BitlyProvider {
+        BitlyProvider { store, config }
+    }
+
+    /// The groups of the account and their branded domains.
+    pub fn account (&self, http: &dyn HttpClient) -> Result<BitlyAccount, ShortenError> {
+        let user = self.call (HttpRequest::get (format!("{}/user", API)), None, http)?;
+        let groups = self.call (HttpRequest::get (format!("{}/groups", API)), None, http)?;
+
+        let groups = groups["groups"].as_array ()
+            .ok_or_else (|| ShortenError::Decode ("bit.ly answered without groups".to_owned ()))?;
+        Ok (BitlyAccount {
+            default_group: user["default_group_guid"].as_str ().map (|guid| guid.to_owned ()),
+            groups: groups.iter ()
+                .filter_map (|group| {
+                    let guid = group["guid"].as_str ()?.to_owned ();
+                    Some (BitlyGroup {
+                        name: group["name"].as_str ().unwrap_or (&guid).to_owned (),
+                        domains: group["bsds"].as_array ()
+                            .map (|domains| domains.iter ().filter_map (|domain| domain.as_str ()).map (|domain| domain.to_owned ()).collect ())
+                            .unwrap_or_default (),
+                        guid,
+                    })
+                })
+                .collect (),
+        })
+    }
+
+    /// Keeps the branded domains of the account, to offer them in the
+    /// provider picker.
+    pub fn remember_domains (&self, domains: &[String]) -> Result<(), ShortenError> {
+        Ok (self.config.set (BITLY, "domains", &domains.join (","))?)
+    }
+
+    fn setting (&self, key: &str) -> Result<Option<String>, ShortenError> {
+        Ok (self.config.get (BITLY, key)?)
+    }
+
+    fn token (&self) -> Result<String, ShortenError> {
+        self.store.get (BITLY)?
+            .ok_or_else (|| ShortenError::MissingCredentials (BITLY.to_owned ()))
+    }
+
+    /// Sends `request` with the token, the answer unless bit.ly refused it.
+    /// `alias` is the back-half the request is about, if any.
+    fn call (&self, request: HttpRequest, alias: Option<&str>, http: &dyn HttpClient) -> Result<Value, ShortenError> {
+        let request = request
+            .header ("Authorization", format!("Bearer {}", self.token ()?))
+            .header ("Accept", "application/json");
+        let response = http.send (&request)?;
+        let json = decode (&response)?;
+        if response.is_success () {
+            Ok (json)
+        } else {
+            Err (failure (&response, &json, alias))
+        }
+    }
+
+    fn post (&self, path: &str, body: Value, alias: Option<&str>, http: &dyn HttpClient) -> Result<Value, ShortenError> {
+        let request = HttpRequest::post (format!("{}/{}", API, path))
+            .header ("Content-Type", "application/json")
+            .body (body.to_string ());
+        self.call (request, alias, http)
+    }
+}
+
+impl Provider for BitlyProvider {
+    fn id (&self) -> &str {
+        BITLY
+    }
+
+    fn name (&self) -> &str {
+        "bit.ly"
+    }
+
+    fn requires_credentials (&self) -> bool {
+        true
+    }
+
+    fn is_configured (&self) -> bool {
+        self.token ().is_ok ()
+    }
+
+    fn config_fields (&self) -> &'static [ConfigField] {
+        FIELDS
+    }
+
+    fn domains (&self) -> Vec<String> {
+        self.setting ("domains").ok ().and_then (|domains| domains)
+            .map (|domains| {
+                domains.split (',')
+                    .map (|domain| domain.trim ().to_owned ())
+                    .filter (|domain| !domain.is_empty ())
+                    .collect ()
+            })
+            .unwrap_or_default ()
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, None, &LinkOptions::default (), http)
+    }
+
+    /// bit.ly calls them custom back-halves.
+    fn supports_alias (&self) -> bool {
+        true
+    }
+
+    fn shorten_with_alias (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, Some (alias), &LinkOptions::default (), http)
+    }
+
+    /// Shortens, then adds the custom back-half to the new link. Custom
+    /// back-halves need a paid bit.ly plan.
+    fn shorten_with_options (&self, url: &str, alias: Option<&str>, options: &LinkOptions, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        if let Some (alias) = alias {
+            BITLY_RULE.check (alias)?;
+        }
+
+        let mut body = json!({ "long_url": url });
+        if let Some (group) = self.setting ("group")? {
+            body["group_guid"] = json!(group);
+        }
+        if let Some (domain) = options.domain.clone ().or (self.setting ("domain")?) {
+            body["domain"] = json!(domain);
+        }
+        let json = self.post ("shorten", body, None, http)?;
+
+        let alias = match alias {
+            Some (alias) => alias,
+            None => return json["link"].as_str ()
+                .map (|short_url| short_url.to_owned ())
+                .ok_or_else (|| ShortenError::Decode ("bit.ly answered without a short URL".to_owned ())),
+        };
+        let id = json["id"].as_str ()
+            .ok_or_else (|| ShortenError::Decode ("bit.ly answered without a link id".to_owned ()))?;
+        let domain = id.split ('/').next ().unwrap_or ("bit.ly");
+
+        let custom = format!("{}/{}", domain, alias);
+        self.post ("custom_bitlinks", json!({ "custom_bitlink": custom, "bitlink_id": id }), Some (alias), http)?;
+        Ok (format!("https://{}", custom))
+    }
+}
+
+/// bit.ly names the error in capitals in `message`, `description` says
+/// more when it is there.
+fn failure (response: &HttpResponse, json: &Value, alias: Option<&str>) -> ShortenError {
+    let code = json["message"].as_str ().unwrap_or ("").to_owned ();
+    let text = json["description"].as_str ()
+        .map (|description| format!("{} ({})", description, code))
+        .unwrap_or_else (|| if code.is_empty () { "Unknown error".to_owned () } else { code.clone () });
+
+    if response.status == 429 || code.contains ("LIMIT") {
+        return ShortenError::QuotaExceeded (format!("The limits of the bit.ly account are reached: {}", text));
+    }
+    match alias {
+        Some (alias) if response.status == 409 || code.contains ("ALREADY") => return ShortenError::AliasTaken (alias.to_owned ()),
+        Some (_) if response.status == 400 || response.status == 402 || response.status == 403 => {
+            return ShortenError::InvalidAlias (format!("bit.ly refused the custom back-half: {}", text));
+        },
+        _ => {},
+    }
+    if response.status == 401 || response.status == 403 {
+        ShortenError::Unavailable (format!("bit.ly refused the token: {}", text))
+    } else {
+        ShortenError::Unavailable (text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::testing::{header, json_body, provider_config, FakeHttp, MemoryStore};
+
+    fn provider (settings: &[(&str, &str, &str)]) -> BitlyProvider {
+        BitlyProvider::new (MemoryStore::with (&[(BITLY, "token-123")]), provider_config (settings))
+    }
+
+    #[test]
+    fn shortens_in_the_group_and_domain_set_up () {
+        let http = FakeHttp::new ().answer ("https://api-ssl.bitly.com/v4/shorten", 200, r#"{"id":"go.test/abc","link":"https://go.test/abc"}"#);
+        let provider = provider (&[(BITLY, "group", "Ba1"), (BITLY, "domain", "go.test")]);
+        assert_eq!(provider.shorten ("https://example.com/", &http), Ok ("https://go.test/abc".to_owned ()));
+
+        let request = http.sent_one ();
+        assert_eq!(header (&request, "Authorization"), Some ("Bearer token-123".to_owned ()));
+        assert!(!request.url.contains ("token-123"));
+        assert_eq!(json_body (&request), json!({ "long_url": "https://example.com/", "group_guid": "Ba1", "domain": "go.test" }));
+    }
+
+    #[test]
+    fn too_many_requests_is_a_quota_error () {
+        let http = FakeHttp::new ().answer ("https://api-ssl.bitly.com/v4/shorten", 429, r#"{"message":"RATE_LIMIT_EXCEEDED","description":"Slow down"}"#);
+        assert_eq!(provider (&[]).shorten ("https://example.com/", &http),
+                   Err (ShortenError::QuotaExceeded ("The limits of the bit.ly account are reached: Slow down (RATE_LIMIT_EXCEEDED)".to_owned ())));
+    }
+
+    #[test]
+    fn a_limit_code_is_a_quota_error_whatever_the_status () {
+        let http = FakeHttp::new ().answer ("https://api-ssl.bitly.com/v4/shorten", 403, r#"{"message":"MONTHLY_LIMIT_EXCEEDED"}"#);
+        assert_eq!(provider (&[]).shorten ("https://example.com/", &http),
+                   Err (ShortenError::QuotaExceeded ("The limits of the bit.ly account are reached: MONTHLY_LIMIT_EXCEEDED".to_owned ())));
+    }
+
+    #[test]
+    fn a_refused_token_is_reported () {
+        let http = FakeHttp::new ().answer ("https://api-ssl.bitly.com/v4/shorten", 403, r#"{"message":"FORBIDDEN"}"#);
+        assert_eq!(provider (&[]).shorten ("https://example.com/", &http),
+                   Err (ShortenError::Unavailable ("bit.ly refused the token: FORBIDDEN".to_owned ())));
+    }
+
+    #[test]
+    fn adds_the_custom_back_half_to_the_new_link () {
+        let http = FakeHttp::new ()
+            .answer ("https://api-ssl.bitly.com/v4/shorten", 200, r#"{"id":"bit.ly/abc","link":"https://bit.ly/abc"}"#)
+            .answer ("https://api-ssl.bitly.com/v4/custom_bitlinks", 200, r#"{}"#);
+        assert_eq!(provider (&[]).shorten_with_alias ("https://example.com/", "mine", &http), Ok ("https://bit.ly/mine".to_owned ()));
+        assert_eq!(json_body (&http.sent ()[1]), json!({ "custom_bitlink": "bit.ly/mine", "bitlink_id": "bit.ly/abc" }));
+    }
+
+    #[test]
+    fn a_taken_back_half_is_reported () {
+        let http = FakeHttp::new ()
+            .answer ("https://api-ssl.bitly.com/v4/shorten", 200, r#"{"id":"bit.ly/abc","link":"https://bit.ly/abc"}"#)
+            .answer ("https://api-ssl.bitly.com/v4/custom_bitlinks", 409, r#"{"message":"ALREADY_A_BITLY_LINK"}"#);
+        assert_eq!(provider (&[]).shorten_with_alias ("https://example.com/", "mine", &http), Err (ShortenError::AliasTaken ("mine".to_owned ())));
+    }
+
+    #[test]
+    fn reads_groups_and_branded_domains () {
+        let http = FakeHttp::new ()
+            .answer ("https://api-ssl.bitly.com/v4/user", 200, r#"{"default_group_guid":"Ba1"}"#)
+            .answer ("https://api-ssl.bitly.com/v4/groups", 200,
+                     r#"{"groups":[{"guid":"Ba1","name":"Me","bsds":["go.test"]},{"guid":"Bb2","bsds":["go.test","b.test"]}]}"#);
+        let account = provider (&[]).account (&http).unwrap ();
+
+        assert_eq!(account.default_group, Some ("Ba1".to_owned ()));
+        assert_eq!(account.groups[1].name, "Bb2");
+        assert_eq!(account.domains (), vec!["go.test", "b.test"]);
+    }
+
+    #[test]
+    fn remembers_the_domains_for_the_picker () {
+        let provider = provider (&[]);
+        provider.remember_domains (&["go.test".to_owned (), "b.test".to_owned ()]).unwrap ();
+        assert_eq!(Provider::domains (&provider), vec!["go.test", "b.test"]);
+    }
+}
@@ -0,0 +1,116 @@
+//! The requests of the `urlshortener` services, sent through the
+//! `HttpClient` of the `Shortener`. The crate brings its own reqwest client
+//! and only exports the list of services, so what it sends and how it reads
+//! the answers is done again here.
+
+use serde_json::Value;
+use urlshortener::Provider as Service;
+
+use super::super::{HttpClient, HttpRequest, HttpResponse, ShortenError};
+use super::alias::{form_body, with_params};
+
+/// Some services only answer browsers.
+const BROWSER_AGENT: &str = "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:58.0) Gecko/20100101 Firefox/58.0";
+
+/// Where the short link is in the answer of a service.
+enum Answer {
+    /// The whole answer.
+    Text,
+    /// The first value of the key, wherever it is in the JSON, after a
+    /// prefix the service leaves out.
+    Json (&'static str, &'static str),
+    /// The text of the first element.
+    Xml (&'static str),
+    /// The `data-clipboard-text` attribute of the page.
+    Clipboard,
+}
+
+/// Shortens `url` with `service`, through `http`.
+pub fn generate (service: &Service, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+    let (request, answer) = request (service, url)?;
+    let response = http.send (&request)?;
+    if !response.is_success () {
+        return Err (ShortenError::Unavailable (format!("{} answered HTTP {}", service.to_name (), response.status)));
+    }
+    parse (&answer, &response)
+        .ok_or_else (|| ShortenError::Decode (format!("No short URL in the answer of {}", service.to_name ())))
+}
+
+fn request (service: &Service, url: &str) -> Result<(HttpRequest, Answer), ShortenError> {
+    let get = |endpoint: &str, params: &[(&str, &str)]| with_params (endpoint, params).map (HttpRequest::get);
+    let form = |endpoint: &str, key: &str| -> Result<HttpRequest, ShortenError> {
+        Ok (HttpRequest::post (endpoint)
+            .header ("Content-Type", "application/x-www-form-urlencoded")
+            .body (form_body (&[(key, url)])?))
+    };
+    let json = |endpoint: String, body: Value| {
+        HttpRequest::post (endpoint)
+            .header ("Content-Type", "application/json")
+            .body (body.to_string ())
+    };
+
+    Ok (match *service {
+        Service::Abv8 => (get ("http://abv8.me/", &[("url", url)])?, Answer::Text),
+        Service::BamBz => (form ("https://bam.bz/api/short", "target")?, Answer::Json ("url", "")),
+        // Shortened by `BitlyProvider` with the v4 API, never through here.
+        Service::BitLy { .. } => return Err (ShortenError::UnknownProvider (service.to_name ().to_owned ())),
+        Service::Bmeo => (get ("http://bmeo.org/api.php", &[("url", url)])?, Answer::Json ("short", "")),
+        Service::BnGy => (get ("https://bn.gy/API.asmx/CreateUrl", &[("real_url", url)])?, Answer::Xml ("ShortenedUrl")),
+        Service::FifoCc => (get ("https://fifo.cc/api/v2", &[("url", url)])?, Answer::Json ("shortner", "http://fifo.cc/")),
+        Service::GooGl { ref api_key } => {
+            let endpoint = with_params ("https://www.googleapis.com/urlshortener/v1/url", &[("key", api_key)])?;
+            (json (endpoint, json!({ "longUrl": url })), Answer::Json ("id", ""))
+        },
+        Service::HmmRs => {
+            let request = json ("http://hmm.rs/x/shorten".to_owned (), json!({ "url": url })).header ("User-Agent", BROWSER_AGENT);
+            (request, Answer::Json ("shortUrl", ""))
+        },
+        Service::HecSu => (get ("https://hec.su/api", &[("url", url), ("method", "xml")])?, Answer::Xml ("short")),
+        Service::IsGd => (get ("https://is.gd/create.php", &[("format", "simple"), ("url", url)])?, Answer::Text),
+        Service::NowLinks => (get ("http://nowlinks.net/api", &[("url", url)])?, Answer::Text),
+        Service::PhxCoIn => (get ("http://phx.co.in/shrink.asp", &[("url", url)])?, Answer::Text),
+        Service::PsbeCo => (get ("http://psbe.co/API.asmx/CreateUrl", &[("real_url", url)])?, Answer::Xml ("ShortUrl")),
+        Service::SCoop => (get ("http://s.coop/devapi.php", &[("action", "shorturl"), ("url", url), ("format", "RETURN")])?, Answer::Text),
+        Service::SirBz => (form ("http://sirbz.com/api/shorten_url", "url")?, Answer::Json ("short_link", "")),
+        Service::Rlu => (get ("http://rlu.ru/index.sema", &[("a", "api"), ("link", url)])?, Answer::Text),
+        Service::TinyUrl => (get ("http://tinyurl.com/create.php", &[("url", url)])?, Answer::Clipboard),
+        Service::TinyPh => (form ("http://tiny.ph/api/url/create", "url")?, Answer::Json ("hash", "http://tiny.ph/")),
+        Service::TnyIm => (get ("http://tny.im/yourls-api.php", &[("action", "shorturl"), ("url", url)])?, Answer::Xml ("shorturl")),
+        Service::UrlShortenerIo => (form ("http://url-shortener.io/shorten", "url_param")?, Answer::Text),
+        Service::VGd => (get ("https://v.gd/create.php", &[("format", "simple"), ("url", url)])?, Answer::Text),
+    })
+}
+
+fn parse (answer: &Answer, response: &HttpResponse) -> Option<String> {
+    let body = &response.body;
+    let found = match *answer {
+        Answer::Text => Some (body.clone ()),
+        Answer::Json (key, prefix) => {
+            let json: Value = ::serde_json::from_str (body).ok ()?;
+            find_key (&json, key)
+                .and_then (|value| value.as_str ())
+                .map (|value| format!("{}{}", prefix, value))
+        },
+        Answer::Xml (tag) => {
+            let start = body.find (&format!("<{}>", tag))? + tag.len () + 2;
+            let end = body[start..].find (&format!("</{}>", tag))?;
+            Some (body[start..start + end].to_owned ())
+        },
+        Answer::Clipboard => {
+            let start = body.find ("data-clipboard-text=\"")? + "data-clipboard-text=\"".len ();
+            let end = body[start..].find ('"')?;
+            Some (body[start..start + end].to_owned ())
+        },
+    };
+    found.map (|text| text.trim ().to_owned ()).filter (|text| !text.is_empty ())
+}
+
+/// The value of the first `key`, depth first.
+fn find_key<'a> (json: &'a Value, key: &str) -> Option<&'a Value> {
+    match *json {
+        Value::Object (ref map) => map.get (key)
+            .or_else (|| map.values ().filter_map (|value| find_key (value, key)).next ()),
+        Value::Array (ref values) => values.iter ().filter_map (|value| find_key (value, key)).next (),
+        _ => None,
+    }
+}
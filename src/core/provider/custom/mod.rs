@@ -0,0 +1,93 @@
+//! A provider of the definitions file, see `core::custom`.
+
+use std::sync::Arc;
+
+use super::super::credentials::CredentialStore;
+use super::super::custom::{fill, Encoding, ProviderDefinition};
+use super::super::{is_valid_url, HttpClient, HttpRequest, ShortenError};
+use super::Provider;
+
+pub struct CustomProvider {
+    definition: ProviderDefinition,
+    store: Arc<dyn CredentialStore>,
+}
+
+impl CustomProvider {
+    pub fn new (definition: ProviderDefinition, store: Arc<dyn CredentialStore>) -> CustomProvider {
+        CustomProvider { definition, store }
+    }
+
+    fn token (&self) -> Result<String, ShortenError> {
+        if !self.requires_credentials () {
+            return Ok (String::new ());
+        }
+        self.store.get (&self.definition.id)?
+            .ok_or_else (|| ShortenError::MissingCredentials (self.definition.id.clone ()))
+    }
+
+    fn send (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        let token = self.token ()?;
+        let values = [("url", url), ("alias", alias), ("token", token.as_str ())];
+        let definition = &self.definition;
+
+        let mut request = HttpRequest::new (definition.method, fill (&definition.url, &values, Encoding::Query));
+        for &(ref name, ref value) in &definition.headers {
+            request = request.header (name.as_str (), fill (value, &values, Encoding::Raw));
+        }
+        if let Some (ref body) = definition.body {
+            let encoding = Encoding::for_content_type (definition.content_type ());
+            request = request.body (fill (body, &values, encoding));
+        }
+
+        let response = http.send (&request)?;
+        if !response.is_success () {
+            let message = definition.error.as_ref ()
+                .and_then (|error| error.apply (&response.body))
+                .unwrap_or_else (|| format!("HTTP {}", response.status));
+            return Err (ShortenError::Unavailable (format!("{} refused the link: {}", definition.name, message)));
+        }
+
+        let short_url = definition.extract.apply (&response.body)
+            .ok_or_else (|| ShortenError::Decode (format!("No short link found in the answer of {}", definition.name)))?;
+        if is_valid_url (&short_url) {
+            Ok (short_url)
+        } else {
+            Err (ShortenError::Decode (format!("{} answered \"{}\", which is not a link", definition.name, short_url)))
+        }
+    }
+}
+
+impl Provider for CustomProvider {
+    fn id (&self) -> &str {
+        &self.definition.id
+    }
+
+    fn name (&self) -> &str {
+        &self.definition.name
+    }
+
+    /// When a template asks for `{token}`.
+    fn requires_credentials (&self) -> bool {
+        self.definition.uses ("token")
+    }
+
+    fn is_configured (&self) -> bool {
+        self.token ().is_ok ()
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.send (url, "", http)
+    }
+
+    /// When a template asks for `{alias}`.
+    fn supports_alias (&self) -> bool {
+        self.definition.uses ("alias")
+    }
+
+    fn shorten_with_alias (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        if !self.supports_alias () {
+            return Err (ShortenError::InvalidAlias (format!("{} does not support custom aliases", self.name ())));
+        }
+        self.send (url, alias, http)
+    }
+}
@@ -0,0 +1,229 @@
+//! Kutt, on kutt.it or an instance of its own, through `/api/v2/links`.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use super::super::config::{ConfigField, ProviderConfig};
+use super::super::credentials::CredentialStore;
+use super::super::{HttpClient, HttpRequest, HttpResponse, ShortenError};
+use super::alias::{decode, AliasRule};
+use super::{LinkOptions, Provider};
+
+pub const KUTT: &str = "kutt";
+
+const DEFAULT_HOST: &str = "https://kutt.it";
+
+/// Custom addresses, as the Kutt validator accepts them.
+pub const KUTT_RULE: AliasRule = AliasRule { min_len: 1, max_len: 64, extra_chars: "-_" };
+
+const FIELDS: &[ConfigField] = &[
+    ConfigField { key: "url", label: "Address", placeholder: DEFAULT_HOST, choices: &[] },
+    ConfigField { key: "domain", label: "Domain", placeholder: "The domain of the instance", choices: &[] },
+];
+
+/// kutt.it, or the instance set up in the preferences. The API key is
+/// stored as the token of `kutt`.
+pub struct KuttProvider {
+    store: Arc<dyn CredentialStore>,
+    config: Arc<ProviderConfig>,
+}
+
+impl KuttProvider {
+    pub fn new (store: Arc<dyn CredentialStore>, config: Arc<ProviderConfig>) -> KuttProvider {
+        KuttProvider { store, config }
+    }
+
+    fn setting (&self, key: &str) -> Result<Option<String>, ShortenError> {
+        Ok (self.config.get (KUTT, key)?)
+    }
+
+    fn endpoint (&self) -> Result<String, ShortenError> {
+        let host = self.setting ("url")?.unwrap_or_else (|| DEFAULT_HOST.to_owned ());
+        Ok (format!("{}/api/v2/links", host.trim_end_matches ('/')))
+    }
+
+    fn api_key (&self) -> Result<String, ShortenError> {
+        self.store.get (KUTT)?
+            .ok_or_else (|| ShortenError::MissingCredentials (KUTT.to_owned ()))
+    }
+}
+
+impl Provider for KuttProvider {
+    fn id (&self) -> &str {
+        KUTT
+    }
+
+    fn name (&self) -> &str {
+        "Kutt"
+    }
+
+    fn requires_credentials (&self) -> bool {
+        true
+    }
+
+    fn is_configured (&self) -> bool {
+        self.api_key ().is_ok ()
+    }
+
+    fn config_fields (&self) -> &'static [ConfigField] {
+        FIELDS
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, None, &LinkOptions::default (), http)
+    }
+
+    /// Kutt calls them custom addresses.
+    fn supports_alias (&self) -> bool {
+        true
+    }
+
+    fn shorten_with_alias (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, Some (alias), &LinkOptions::default (), http)
+    }
+
+    fn shorten_with_options (&self, url: &str, alias: Option<&str>, options: &LinkOptions, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        let mut body = json!({ "target": url });
+        if let Some (alias) = alias {
+            KUTT_RULE.check (alias)?;
+            body["customurl"] = json!(alias);
+        }
+        if let Some (ref password) = options.password {
+            body["password"] = json!(password);
+        }
+        if let Some (ref expire_in) = options.expire_in {
+            body["expire_in"] = json!(expire_in);
+        }
+        if let Some (ref description) = options.description {
+            body["description"] = json!(description);
+        }
+        if let Some (domain) = options.domain.clone ().or (self.setting ("domain")?) {
+            body["domain"] = json!(domain);
+        }
+        if options.find_if_exists {
+            body["reuse"] = json!(true);
+        }
+
+        let request = HttpRequest::post (self.endpoint ()?)
+            .header ("X-API-KEY", self.api_key ()?)
+            .header ("Content-Type", "application/json")
+            .header ("Accept", "application/json")
+            .body (body.to_string ());
+        let response = http.send (&request)?;
+        let json = decode (&response)?;
+        if !response.is_success () {
+            return Err (failure (&response, &json, url, alias));
+        }
+        json["link"].as_str ()
+            .map (|short_url| short_url.to_owned ())
+            .ok_or_else (|| ShortenError::Decode ("Kutt answered without a short URL".to_owned ()))
+    }
+}
+
+/// Kutt answers `{"error": "..."}` with a sentence meant for people, only
+/// the status and the wording tell the errors apart.
+fn failure (response: &HttpResponse, json: &Value, url: &str, alias: Option<&str>) -> ShortenError {
+    let message = json["error"].as_str ()
+        .or_else (|| json["message"].as_str ())
+        .unwrap_or ("Unknown error")
+        .to_owned ();
+    let lower = message.to_lowercase ();
+
+    match response.status {
+        401 | 403 => return ShortenError::Unavailable (format!("Kutt refused the API key: {}", message)),
+        429 => return ShortenError::Unavailable (format!("Too many links for Kutt right now: {}", message)),
+        _ => {},
+    }
+    match alias {
+        Some (alias) if lower.contains ("custom url") && lower.contains ("already") => return ShortenError::AliasTaken (alias.to_owned ()),
+        Some (_) if lower.contains ("custom url") => return ShortenError::InvalidAlias (message),
+        _ => {},
+    }
+    if lower.contains ("url is not valid") || lower.contains ("target") {
+        ShortenError::InvalidUrl (url.to_owned ())
+    } else if lower.contains ("domain") {
+        ShortenError::Unavailable (format!("Kutt does not know the domain: {}", message))
+    } else {
+        ShortenError::Unavailable (message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::testing::{header, json_body, provider_config, FakeHttp, MemoryStore};
+
+    const API: &str = "https://kutt.it/api/v2/links";
+
+    fn provider (settings: &[(&str, &str, &str)]) -> KuttProvider {
+        KuttProvider::new (MemoryStore::with (&[(KUTT, "key-123")]), provider_config (settings))
+    }
+
+    #[test]
+    fn shortens_on_kutt_it_by_default () {
+        let http = FakeHttp::new ().answer (API, 201, r#"{"id":"1","link":"https://kutt.it/abc","target":"https://example.com/"}"#);
+        assert_eq!(provider (&[]).shorten ("https://example.com/", &http), Ok ("https://kutt.it/abc".to_owned ()));
+
+        let request = http.sent_one ();
+        assert_eq!(header (&request, "X-API-KEY"), Some ("key-123".to_owned ()));
+        assert_eq!(json_body (&request), json!({ "target": "https://example.com/" }));
+    }
+
+    #[test]
+    fn sends_every_option_to_the_instance () {
+        let http = FakeHttp::new ().answer ("https://k.test/api/v2/links", 201, r#"{"link":"https://go.test/mine"}"#);
+        let options = LinkOptions {
+            password: Some ("open sesame".to_owned ()),
+            expire_in: Some ("2 days".to_owned ()),
+            description: Some ("Docs".to_owned ()),
+            find_if_exists: true,
+            ..LinkOptions::default ()
+        };
+        let provider = provider (&[(KUTT, "url", "https://k.test/"), (KUTT, "domain", "go.test")]);
+        provider.shorten_with_options ("https://example.com/", Some ("mine"), &options, &http).unwrap ();
+
+        assert_eq!(json_body (&http.sent_one ()), json!({
+            "target": "https://example.com/",
+            "customurl": "mine",
+            "password": "open sesame",
+            "expire_in": "2 days",
+            "description": "Docs",
+            "domain": "go.test",
+            "reuse": true,
+        }));
+    }
+
+    #[test]
+    fn reads_the_errors_meant_for_people () {
+        let cases = vec![
+            (400, r#"{"error":"Custom URL is already in use."}"#, Some ("mine"), ShortenError::AliasTaken ("mine".to_owned ())),
+            (400, r#"{"error":"Custom URL is not valid."}"#, Some ("mine"), ShortenError::InvalidAlias ("Custom URL is not valid.".to_owned ())),
+            (400, r#"{"error":"URL is not valid."}"#, None, ShortenError::InvalidUrl ("https://example.com/".to_owned ())),
+            (400, r#"{"error":"Domain is not valid."}"#, None, ShortenError::Unavailable ("Kutt does not know the domain: Domain is not valid.".to_owned ())),
+            (401, r#"{"error":"Unauthorized."}"#, None, ShortenError::Unavailable ("Kutt refused the API key: Unauthorized.".to_owned ())),
+            (429, r#"{"message":"You have reached your hourly limit."}"#, None, ShortenError::Unavailable ("Too many links for Kutt right now: You have reached your hourly limit.".to_owned ())),
+        ];
+        for (status, body, alias, expected) in cases {
+            let http = FakeHttp::new ().answer (API, status, body);
+            let options = LinkOptions::default ();
+            assert_eq!(provider (&[]).shorten_with_options ("https://example.com/", alias, &options, &http), Err (expected), "{}", body);
+        }
+    }
+
+    #[test]
+    fn an_unreadable_answer_is_a_decode_error () {
+        let http = FakeHttp::new ().answer (API, 502, "<html>Bad gateway</html>");
+        match provider (&[]).shorten ("https://example.com/", &http) {
+            Err (ShortenError::Decode (_)) => (),
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn aliases_are_checked_before_sending () {
+        let http = FakeHttp::new ();
+        assert!(provider (&[]).shorten_with_alias ("https://example.com/", "no spaces", &http).is_err ());
+        assert!(http.sent ().is_empty ());
+    }
+}
@@ -0,0 +1,270 @@
+mod alias;
+mod bitly;
+mod builtin;
+mod custom;
+mod kutt;
+mod shlink;
+mod yourls;
+
+use std::sync::Arc;
+
+use urlshortener;
+
+use super::config::{ConfigField, ProviderConfig};
+use super::credentials::CredentialStore;
+use super::custom::ProviderDefinition;
+use super::{HttpClient, ShortenError};
+
+pub use self::bitly::{BitlyAccount, BitlyGroup, BitlyProvider, BITLY};
+pub use self::custom::CustomProvider;
+pub use self::kutt::{KuttProvider, KUTT};
+pub use self::shlink::{ShlinkDomain, ShlinkProvider, SHLINK};
+pub use self::yourls::{YourlsLink, YourlsProvider, YourlsStats, YOURLS};
+
+/// What a request asks of the link besides the URL and alias, for the
+/// providers which take it. The others ignore it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkOptions {
+    /// Title of the page, kept by the instance instead of fetching it.
+    pub title: Option<String>,
+    /// Tags to file the link under.
+    pub tags: Vec<String>,
+    /// Domain of the short link, for instances serving several.
+    pub domain: Option<String>,
+    /// ISO 8601 date after which the link stops redirecting.
+    pub valid_until: Option<String>,
+    /// Visits after which the link stops redirecting.
+    pub max_visits: Option<u32>,
+    /// Answer with an existing link of the URL matching the other options
+    /// instead of making a new one.
+    pub find_if_exists: bool,
+    /// Asked before redirecting.
+    pub password: Option<String>,
+    /// How long the link works, e.g. "2 hours" or "7 days".
+    pub expire_in: Option<String>,
+    /// Shown with the link in the account of the service.
+    pub description: Option<String>,
+}
+
+/// A URL shortening service.
+pub trait Provider: Send + Sync {
+    /// Stable identifier, e.g. `is.gd`.
+    fn id (&self) -> &str;
+
+    /// Name shown to users.
+    fn name (&self) -> &str {
+        self.id ()
+    }
+
+    /// Whether the provider only works once a token is stored for it.
+    fn requires_credentials (&self) -> bool {
+        false
+    }
+
+    /// Whether the provider can be used right now, false while a required
+    /// token is missing.
+    fn is_configured (&self) -> bool {
+        true
+    }
+
+    /// Settings such as the address of a self-hosted instance, kept in the
+    /// `ProviderConfig` under `id`.
+    fn config_fields (&self) -> &'static [ConfigField] {
+        &[]
+    }
+
+    /// Whether a secret is kept for it in the credential store, also when
+    /// it can do without one.
+    fn uses_secret (&self) -> bool {
+        self.requires_credentials ()
+    }
+
+    /// Domains besides the default one the links can be made on, each
+    /// offered on its own in the provider picker.
+    fn domains (&self) -> Vec<String> {
+        Vec::new ()
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError>;
+
+    /// Whether `shorten_with_alias` can be used.
+    fn supports_alias (&self) -> bool {
+        false
+    }
+
+    /// Shortens `url` to a link ending in `alias`.
+    fn shorten_with_alias (&self, _url: &str, _alias: &str, _http: &dyn HttpClient) -> Result<String, ShortenError> {
+        Err (ShortenError::InvalidAlias (format!("{} does not support custom aliases", self.name ())))
+    }
+
+    /// Shortens `url` with the `alias`, if any, and the `options` the
+    /// provider knows.
+    fn shorten_with_options (&self, url: &str, alias: Option<&str>, _options: &LinkOptions, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        match alias {
+            Some (alias) => self.shorten_with_alias (url, alias, http),
+            None => self.shorten (url, http),
+        }
+    }
+}
+
+/// Used when nothing else was chosen.
+pub const DEFAULT_PROVIDER: &str = "is.gd";
+
+/// The services srtnr offered before every `urlshortener` provider was
+/// listed, in the order the old integer `default-provider` setting indexes.
+const LEGACY_ORDER: &[&str] = &["goo.gl", "bitly.com", "is.gd", "bam.bz", "tny.im", "hmm.rs"];
+
+/// Services which stopped working, with the reason shown to users.
+const RETIRED: &[(&str, &str)] = &[
+    ("goo.gl", "Google shut down goo.gl"),
+];
+
+/// The providers of `definitions`, leaving out the ones whose id is taken
+/// by a provider of `builtin`.
+pub fn custom_providers (store: Arc<dyn CredentialStore>, definitions: Vec<ProviderDefinition>, builtin: &[Box<dyn Provider>]) -> Vec<Box<dyn Provider>> {
+    definitions.into_iter ()
+        .filter (|definition| !builtin.iter ().any (|provider| provider.id () == definition.id))
+        .map (|definition| Box::new (CustomProvider::new (definition, store.clone ())) as Box<dyn Provider>)
+        .collect ()
+}
+
+/// The id behind an index of the old integer `default-provider` setting.
+pub fn legacy_provider_id (index: i32) -> Option<&'static str> {
+    if index < 0 {
+        return None;
+    }
+    LEGACY_ORDER.get (index as usize).cloned ()
+}
+
+/// Why `id` no longer works, `None` for working providers.
+pub fn retired_reason (id: &str) -> Option<&'static str> {
+    RETIRED.iter ()
+        .find (|&&(retired, _)| retired == id)
+        .map (|&(_, reason)| reason)
+}
+
+/// The providers offered by every front-end, in display order: the ones
+/// srtnr always had, then the rest of `urlshortener::PROVIDERS` in the
+/// crate's order of quality, then the self-hosted ones set up in `config`.
+/// Retired providers come last, they are only kept so old settings and
+/// scripts get a clear error.
+pub fn builtin_providers (store: Arc<dyn CredentialStore>, config: Arc<ProviderConfig>) -> Vec<Box<dyn Provider>> {
+    let mut providers: Vec<Box<dyn Provider>> = vec![
+        Box::new (CredentialProvider::googl (store.clone ())),
+        Box::new (BitlyProvider::new (store.clone (), config.clone ())),
+    ];
+    for provider in urlshortener::PROVIDERS {
+        providers.push (Box::new (BuiltinProvider::new (provider.clone ())));
+    }
+    providers.push (Box::new (YourlsProvider::new (store.clone (), config.clone ())));
+    providers.push (Box::new (ShlinkProvider::new (store.clone (), config.clone ())));
+    providers.push (Box::new (KuttProvider::new (store, config)));
+
+    providers.sort_by_key (|provider| {
+        let position = LEGACY_ORDER.iter ()
+            .position (|id| *id == provider.id ())
+            .unwrap_or (LEGACY_ORDER.len ());
+        (retired_reason (provider.id ()).is_some (), position)
+    });
+    providers
+}
+
+/// One of the services implemented by the `urlshortener` crate.
+pub struct BuiltinProvider {
+    inner: urlshortener::Provider,
+}
+
+impl BuiltinProvider {
+    pub fn new (inner: urlshortener::Provider) -> BuiltinProvider {
+        BuiltinProvider { inner }
+    }
+}
+
+impl Provider for BuiltinProvider {
+    fn id (&self) -> &str {
+        self.inner.to_name ()
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        builtin::generate (&self.inner, url, http)
+    }
+
+    fn supports_alias (&self) -> bool {
+        match self.id () {
+            "is.gd" | "v.gd" | "tny.im" => true,
+            _ => false,
+        }
+    }
+
+    fn shorten_with_alias (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        match self.id () {
+            "is.gd" => alias::gd ("https://is.gd/create.php", url, alias, http),
+            "v.gd" => alias::gd ("https://v.gd/create.php", url, alias, http),
+            "tny.im" => alias::yourls ("https://tny.im/yourls-api.php", url, alias, http),
+            _ => Err (ShortenError::InvalidAlias (format!("{} does not support custom aliases", self.name ()))),
+        }
+    }
+}
+
+/// A `urlshortener` service that needs a token (goo.gl). The token
+/// is read from the credential store on every request, so changes made in
+/// the preferences apply right away.
+pub struct CredentialProvider {
+    template: urlshortener::Provider,
+    store: Arc<dyn CredentialStore>,
+}
+
+impl CredentialProvider {
+    pub fn googl (store: Arc<dyn CredentialStore>) -> CredentialProvider {
+        CredentialProvider {
+            template: urlshortener::Provider::GooGl { api_key: String::new () },
+            store,
+        }
+    }
+
+    fn secret (&self) -> Result<String, ShortenError> {
+        self.store.get (self.id ())?
+            .ok_or_else (|| ShortenError::MissingCredentials (self.id ().to_owned ()))
+    }
+}
+
+impl Provider for CredentialProvider {
+    fn id (&self) -> &str {
+        self.template.to_name ()
+    }
+
+    fn requires_credentials (&self) -> bool {
+        true
+    }
+
+    fn is_configured (&self) -> bool {
+        match self.store.get (self.id ()) {
+            Ok (Some (_)) => true,
+            _ => false,
+        }
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        let secret = self.secret ()?;
+
+        let provider = match self.template {
+            urlshortener::Provider::GooGl { .. } => urlshortener::Provider::GooGl { api_key: secret },
+            ref other => other.clone (),
+        };
+        builtin::generate (&provider, url, http)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::testing::{provider_config, MemoryStore};
+
+    #[test]
+    fn retired_providers_come_last () {
+        let providers = builtin_providers (MemoryStore::with (&[]), provider_config (&[]));
+        let ids: Vec<&str> = providers.iter ().map (|provider| provider.id ()).collect ();
+        assert_eq!(&ids[..3], &["bitly.com", "is.gd", "bam.bz"]);
+        assert_eq!(ids.last (), Some (&"goo.gl"));
+    }
+}
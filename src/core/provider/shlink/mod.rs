@@ -0,0 +1,277 @@
+//! A self-hosted Shlink instance, through its REST API (v2 or v3).
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use super::super::config::{ConfigField, ProviderConfig};
+use super::super::credentials::CredentialStore;
+use super::super::{HttpClient, HttpRequest, HttpResponse, ShortenError};
+use super::alias::{decode, AliasRule};
+use super::{LinkOptions, Provider};
+
+pub const SHLINK: &str = "shlink";
+
+/// Custom slugs, which Shlink keeps as typed.
+pub const SHLINK_RULE: AliasRule = AliasRule { min_len: 1, max_len: 255, extra_chars: "-_" };
+
+const FIELDS: &[ConfigField] = &[
+    ConfigField { key: "url", label: "Address", placeholder: "https://s.test", choices: &[] },
+    ConfigField { key: "api-version", label: "REST API", placeholder: "", choices: &[("3", "Version 3"), ("2", "Version 2")] },
+    ConfigField { key: "domain", label: "Domain", placeholder: "The default domain", choices: &[] },
+];
+
+/// A domain the instance serves short links on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShlinkDomain {
+    pub authority: String,
+    /// The one used when a request names none.
+    pub is_default: bool,
+}
+
+/// The instance set up in the preferences, the API key being stored as the
+/// token of `shlink`.
+pub struct ShlinkProvider {
+    store: Arc<dyn CredentialStore>,
+    config: Arc<ProviderConfig>,
+}
+
+impl ShlinkProvider {
+    pub fn new (store: Arc<dyn CredentialStore>, config: Arc<ProviderConfig>) -> ShlinkProvider {
+        ShlinkProvider { store, config }
+    }
+
+    /// Every domain of the instance, to pick one for the short links.
+    pub fn domains (&self, http: &dyn HttpClient) -> Result<Vec<ShlinkDomain>, ShortenError> {
+        let response = http.send (&self.request (HttpRequest::get (self.endpoint ("domains")?))?)?;
+        let json = decode (&response)?;
+        if !response.is_success () {
+            return Err (failure (&response, &json, None));
+        }
+
+        let data = json["domains"]["data"].as_array ()
+            .ok_or_else (|| ShortenError::Decode ("Shlink answered without domains".to_owned ()))?;
+        Ok (data.iter ()
+            .filter_map (|domain| {
+                domain["domain"].as_str ().map (|authority| ShlinkDomain {
+                    authority: authority.to_owned (),
+                    is_default: domain["isDefault"].as_bool ().unwrap_or (false),
+                })
+            })
+            .collect ())
+    }
+
+    fn setting (&self, key: &str) -> Result<Option<String>, ShortenError> {
+        Ok (self.config.get (SHLINK, key)?)
+    }
+
+    /// `<address>/rest/v<version>/<path>`.
+    fn endpoint (&self, path: &str) -> Result<String, ShortenError> {
+        let url = self.setting ("url")?
+            .ok_or_else (|| ShortenError::Unavailable ("The address of the Shlink instance is not set".to_owned ()))?;
+        let version = self.setting ("api-version")?.unwrap_or_else (|| "3".to_owned ());
+        Ok (format!("{}/rest/v{}/{}", url.trim_end_matches ('/'), version, path))
+    }
+
+    fn api_key (&self) -> Result<String, ShortenError> {
+        self.store.get (SHLINK)?
+            .ok_or_else (|| ShortenError::MissingCredentials (SHLINK.to_owned ()))
+    }
+
+    fn request (&self, request: HttpRequest) -> Result<HttpRequest, ShortenError> {
+        Ok (request
+            .header ("X-Api-Key", self.api_key ()?)
+            .header ("Accept", "application/json"))
+    }
+}
+
+impl Provider for ShlinkProvider {
+    fn id (&self) -> &str {
+        SHLINK
+    }
+
+    fn name (&self) -> &str {
+        "Shlink"
+    }
+
+    fn requires_credentials (&self) -> bool {
+        true
+    }
+
+    fn is_configured (&self) -> bool {
+        self.endpoint ("short-urls").is_ok () && self.api_key ().is_ok ()
+    }
+
+    fn config_fields (&self) -> &'static [ConfigField] {
+        FIELDS
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, None, &LinkOptions::default (), http)
+    }
+
+    /// Shlink calls them custom slugs.
+    fn supports_alias (&self) -> bool {
+        true
+    }
+
+    fn shorten_with_alias (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, Some (alias), &LinkOptions::default (), http)
+    }
+
+    fn shorten_with_options (&self, url: &str, alias: Option<&str>, options: &LinkOptions, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        let mut body = json!({ "longUrl": url });
+        if let Some (alias) = alias {
+            SHLINK_RULE.check (alias)?;
+            body["customSlug"] = json!(alias);
+        }
+        if !options.tags.is_empty () {
+            body["tags"] = json!(options.tags);
+        }
+        if let Some (domain) = options.domain.clone ().or (self.setting ("domain")?) {
+            body["domain"] = json!(domain);
+        }
+        if let Some (ref valid_until) = options.valid_until {
+            body["validUntil"] = json!(valid_until);
+        }
+        if let Some (max_visits) = options.max_visits {
+            body["maxVisits"] = json!(max_visits);
+        }
+        if options.find_if_exists {
+            body["findIfExists"] = json!(true);
+        }
+
+        let request = self.request (HttpRequest::post (self.endpoint ("short-urls")?))?
+            .header ("Content-Type", "application/json")
+            .body (body.to_string ());
+        let response = http.send (&request)?;
+        let json = decode (&response)?;
+        if !response.is_success () {
+            return Err (failure (&response, &json, alias));
+        }
+        json["shortUrl"].as_str ()
+            .map (|short_url| short_url.to_owned ())
+            .ok_or_else (|| ShortenError::Decode ("Shlink answered without a short URL".to_owned ()))
+    }
+}
+
+/// The problem details (RFC 7807) Shlink answers with. v2 names the type
+/// in capitals, v3 with a URL ending in its name.
+fn failure (response: &HttpResponse, json: &Value, alias: Option<&str>) -> ShortenError {
+    let problem = json["type"].as_str ().unwrap_or ("");
+    let problem = problem.rsplit ('/').next ().unwrap_or ("").to_lowercase ().replace ('_', "-");
+    let detail = json["detail"].as_str ()
+        .or_else (|| json["title"].as_str ())
+        .unwrap_or ("Unknown error")
+        .to_owned ();
+    let invalid_slug = json["invalidElements"].as_array ()
+        .map (|elements| elements.iter ().any (|element| element.as_str () == Some ("customSlug")))
+        .unwrap_or (false);
+
+    match (problem.as_str (), alias) {
+        ("non-unique-slug", Some (alias)) | ("invalid-slug", Some (alias)) => ShortenError::AliasTaken (alias.to_owned ()),
+        ("invalid-url", _) => ShortenError::InvalidUrl (json["url"].as_str ().unwrap_or (&response.url).to_owned ()),
+        ("invalid-data", Some (_)) | ("invalid-argument", Some (_)) if invalid_slug => ShortenError::InvalidAlias (detail),
+        _ if response.status == 401 || response.status == 403 => ShortenError::Unavailable (format!("Shlink refused the API key: {}", detail)),
+        _ => ShortenError::Unavailable (detail),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::Method;
+    use super::super::super::testing::{header, json_body, provider_config, FakeHttp, MemoryStore};
+
+    const CREATED: &str = r#"{"shortCode":"abc","shortUrl":"https://s.test/abc","longUrl":"https://example.com/"}"#;
+
+    fn provider (settings: &[(&str, &str)]) -> ShlinkProvider {
+        let mut all = vec![(SHLINK, "url", "https://s.test/")];
+        all.extend (settings.iter ().map (|&(key, value)| (SHLINK, key, value)));
+        ShlinkProvider::new (MemoryStore::with (&[(SHLINK, "key-123")]), provider_config (&all))
+    }
+
+    #[test]
+    fn shortens_through_the_v3_api_by_default () {
+        let http = FakeHttp::new ().answer ("https://s.test/rest/v3/short-urls", 200, CREATED);
+        assert_eq!(provider (&[]).shorten ("https://example.com/", &http), Ok ("https://s.test/abc".to_owned ()));
+
+        let request = http.sent_one ();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(header (&request, "X-Api-Key"), Some ("key-123".to_owned ()));
+        assert_eq!(json_body (&request), json!({ "longUrl": "https://example.com/" }));
+    }
+
+    #[test]
+    fn the_api_version_is_a_setting () {
+        let http = FakeHttp::new ().answer ("https://s.test/rest/v2/short-urls", 200, CREATED);
+        assert!(provider (&[("api-version", "2")]).shorten ("https://example.com/", &http).is_ok ());
+    }
+
+    #[test]
+    fn sends_every_option () {
+        let http = FakeHttp::new ().answer ("https://s.test/rest/v3/short-urls", 200, CREATED);
+        let options = LinkOptions {
+            tags: vec!["work".to_owned (), "docs".to_owned ()],
+            valid_until: Some ("2030-01-01T00:00:00+00:00".to_owned ()),
+            max_visits: Some (10),
+            find_if_exists: true,
+            ..LinkOptions::default ()
+        };
+        provider (&[("domain", "go.test")]).shorten_with_options ("https://example.com/", Some ("mine"), &options, &http).unwrap ();
+
+        assert_eq!(json_body (&http.sent_one ()), json!({
+            "longUrl": "https://example.com/",
+            "customSlug": "mine",
+            "tags": ["work", "docs"],
+            "domain": "go.test",
+            "validUntil": "2030-01-01T00:00:00+00:00",
+            "maxVisits": 10,
+            "findIfExists": true,
+        }));
+    }
+
+    #[test]
+    fn the_domain_of_the_request_wins_over_the_setting () {
+        let http = FakeHttp::new ().answer ("https://s.test/rest/v3/short-urls", 200, CREATED);
+        let options = LinkOptions { domain: Some ("other.test".to_owned ()), ..LinkOptions::default () };
+        provider (&[("domain", "go.test")]).shorten_with_options ("https://example.com/", None, &options, &http).unwrap ();
+        assert_eq!(json_body (&http.sent_one ())["domain"], "other.test");
+    }
+
+    #[test]
+    fn maps_v2_and_v3_problems () {
+        let cases = vec![
+            (400, r#"{"type":"INVALID_SLUG","detail":"Provided slug \"mine\" is already in use."}"#, ShortenError::AliasTaken ("mine".to_owned ())),
+            (400, r#"{"type":"https://shlink.io/api/error/non-unique-slug","detail":"Provided slug \"mine\" is already in use."}"#, ShortenError::AliasTaken ("mine".to_owned ())),
+            (400, r#"{"type":"https://shlink.io/api/error/invalid-url","detail":"Provided URL is invalid.","url":"https://example.com/"}"#, ShortenError::InvalidUrl ("https://example.com/".to_owned ())),
+            (400, r#"{"type":"INVALID_ARGUMENT","detail":"Provided data is not valid","invalidElements":["customSlug"]}"#, ShortenError::InvalidAlias ("Provided data is not valid".to_owned ())),
+            (401, r#"{"type":"https://shlink.io/api/error/invalid-api-key","title":"Invalid API key"}"#, ShortenError::Unavailable ("Shlink refused the API key: Invalid API key".to_owned ())),
+            (500, r#"{"detail":"Database down"}"#, ShortenError::Unavailable ("Database down".to_owned ())),
+        ];
+        for (status, body, expected) in cases {
+            let http = FakeHttp::new ().answer ("https://s.test/rest/v3/short-urls", status, body);
+            assert_eq!(provider (&[]).shorten_with_alias ("https://example.com/", "mine", &http), Err (expected), "{}", body);
+        }
+    }
+
+    #[test]
+    fn lists_the_domains () {
+        let http = FakeHttp::new ().answer ("https://s.test/rest/v3/domains", 200,
+                                            r#"{"domains":{"data":[{"domain":"s.test","isDefault":true},{"domain":"go.test","isDefault":false}]}}"#);
+        assert_eq!(provider (&[]).domains (&http), Ok (vec![
+            ShlinkDomain { authority: "s.test".to_owned (), is_default: true },
+            ShlinkDomain { authority: "go.test".to_owned (), is_default: false },
+        ]));
+        assert_eq!(http.sent_one ().method, Method::Get);
+    }
+
+    #[test]
+    fn nothing_is_sent_without_an_api_key () {
+        let http = FakeHttp::new ();
+        let provider = ShlinkProvider::new (MemoryStore::with (&[]), provider_config (&[(SHLINK, "url", "https://s.test")]));
+        assert!(!provider.is_configured ());
+        assert_eq!(provider.domains (&http), Err (ShortenError::MissingCredentials (SHLINK.to_owned ())));
+        assert!(http.sent ().is_empty ());
+    }
+}
@@ -0,0 +1,336 @@
+//! A YOURLS instance of its own, through `yourls-api.php`. tny.im runs
+//! YOURLS too but only takes anonymous requests, see `alias::yourls`.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use openssl::hash::{hash, MessageDigest};
+use serde_json::Value;
+
+use super::super::config::{ConfigField, ProviderConfig};
+use super::super::credentials::CredentialStore;
+use super::super::{HttpClient, HttpRequest, ShortenError};
+use super::alias::{self, decode, form_body};
+use super::{LinkOptions, Provider};
+
+pub const YOURLS: &str = "yourls";
+
+const FIELDS: &[ConfigField] = &[
+    ConfigField { key: "url", label: "Address", placeholder: "https://sho.rt", choices: &[] },
+    ConfigField {
+        key: "auth",
+        label: "Sign in with",
+        placeholder: "",
+        choices: &[("signature", "Signature token"), ("password", "User name and password"), ("none", "Nothing, the API is public")],
+    },
+    ConfigField { key: "username", label: "User name", placeholder: "Only for the password", choices: &[] },
+];
+
+/// How requests are signed, the secret coming from the credential store.
+enum Auth {
+    None,
+    Password { username: String, password: String },
+    /// Sent as `md5(timestamp + token)`, which YOURLS accepts for a while
+    /// only, so the token itself never goes over the wire.
+    Signature (String),
+}
+
+/// A link of the instance, as the `expand` and `url-stats` actions
+/// describe it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct YourlsLink {
+    pub short_url: String,
+    pub long_url: String,
+    pub title: String,
+    /// Only known to `url-stats`.
+    pub clicks: Option<u64>,
+    /// When the link was made, as the instance writes it.
+    pub created: Option<String>,
+}
+
+/// What the `db-stats` action counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct YourlsStats {
+    pub links: u64,
+    pub clicks: u64,
+}
+
+/// The instance set up in the preferences: its address, how to sign in and
+/// the password or signature token, stored as the token of `yourls`.
+pub struct YourlsProvider {
+    store: Arc<dyn CredentialStore>,
+    config: Arc<ProviderConfig>,
+}
+
+impl YourlsProvider {
+    pub fn new (store: Arc<dyn CredentialStore>, config: Arc<ProviderConfig>) -> YourlsProvider {
+        YourlsProvider { store, config }
+    }
+
+    /// The destination of `short_url`, which can also be just its keyword.
+    pub fn expand (&self, short_url: &str, http: &dyn HttpClient) -> Result<YourlsLink, ShortenError> {
+        let json = self.call (&[("action", "expand"), ("shorturl", short_url)], http)?;
+        Ok (YourlsLink {
+            short_url: text (&json["shorturl"]).unwrap_or_else (|| short_url.to_owned ()),
+            long_url: text (&json["longurl"])
+                .ok_or_else (|| ShortenError::Decode ("YOURLS answered without the long URL".to_owned ()))?,
+            title: text (&json["title"]).unwrap_or_default (),
+            clicks: None,
+            created: None,
+        })
+    }
+
+    pub fn url_stats (&self, short_url: &str, http: &dyn HttpClient) -> Result<YourlsLink, ShortenError> {
+        let json = self.call (&[("action", "url-stats"), ("shorturl", short_url)], http)?;
+        let link = &json["link"];
+        Ok (YourlsLink {
+            short_url: text (&link["shorturl"]).unwrap_or_else (|| short_url.to_owned ()),
+            long_url: text (&link["url"])
+                .ok_or_else (|| ShortenError::Decode ("YOURLS answered without the statistics of the link".to_owned ()))?,
+            title: text (&link["title"]).unwrap_or_default (),
+            clicks: number (&link["clicks"]),
+            created: text (&link["timestamp"]),
+        })
+    }
+
+    pub fn db_stats (&self, http: &dyn HttpClient) -> Result<YourlsStats, ShortenError> {
+        let json = self.call (&[("action", "db-stats")], http)?;
+        let stats = &json["db-stats"];
+        match (number (&stats["total_links"]), number (&stats["total_clicks"])) {
+            (Some (links), Some (clicks)) => Ok (YourlsStats { links, clicks }),
+            _ => Err (ShortenError::Decode ("YOURLS answered without statistics".to_owned ())),
+        }
+    }
+
+    fn setting (&self, key: &str) -> Result<Option<String>, ShortenError> {
+        Ok (self.config.get (YOURLS, key)?)
+    }
+
+    /// `yourls-api.php` of the instance, the address may point at it already.
+    fn endpoint (&self) -> Result<String, ShortenError> {
+        let url = self.setting ("url")?
+            .ok_or_else (|| ShortenError::Unavailable ("The address of the YOURLS instance is not set".to_owned ()))?;
+        let url = url.trim_end_matches ('/');
+        if url.ends_with (".php") {
+            Ok (url.to_owned ())
+        } else {
+            Ok (format!("{}/yourls-api.php", url))
+        }
+    }
+
+    fn auth (&self) -> Result<Auth, ShortenError> {
+        let mode = self.setting ("auth")?.unwrap_or_else (|| "signature".to_owned ());
+        if mode == "none" {
+            return Ok (Auth::None);
+        }
+        let secret = self.store.get (YOURLS)?
+            .ok_or_else (|| ShortenError::MissingCredentials (YOURLS.to_owned ()))?;
+        if mode == "password" {
+            let username = self.setting ("username")?
+                .ok_or_else (|| ShortenError::MissingCredentials (YOURLS.to_owned ()))?;
+            Ok (Auth::Password { username, password: secret })
+        } else {
+            Ok (Auth::Signature (secret))
+        }
+    }
+
+    /// Sends `action` with its parameters and the sign in, an error unless
+    /// YOURLS reports success.
+    fn call (&self, params: &[(&str, &str)], http: &dyn HttpClient) -> Result<Value, ShortenError> {
+        let json = self.send (params, http)?;
+        if json["statusCode"].as_u64 () == Some (200) || json["status"].as_str () == Some ("success") {
+            Ok (json)
+        } else {
+            Err (ShortenError::Unavailable (message (&json)))
+        }
+    }
+
+    fn send (&self, params: &[(&str, &str)], http: &dyn HttpClient) -> Result<Value, ShortenError> {
+        let endpoint = self.endpoint ()?;
+        let auth = self.auth ()?;
+
+        let timestamp = SystemTime::now ().duration_since (UNIX_EPOCH)
+            .map (|elapsed| elapsed.as_secs ())
+            .unwrap_or (0)
+            .to_string ();
+        let signature;
+        let mut all: Vec<(&str, &str)> = vec![("format", "json")];
+        match auth {
+            Auth::None => {},
+            Auth::Password { ref username, ref password } => {
+                all.push (("username", username.as_str ()));
+                all.push (("password", password.as_str ()));
+            },
+            Auth::Signature (ref token) => {
+                signature = md5_hex (&format!("{}{}", timestamp, token))?;
+                all.push (("timestamp", timestamp.as_str ()));
+                all.push (("signature", signature.as_str ()));
+            },
+        }
+        all.extend_from_slice (params);
+
+        // POST keeps the password out of the server logs.
+        let request = HttpRequest::post (endpoint)
+            .header ("Content-Type", "application/x-www-form-urlencoded")
+            .body (form_body (&all)?);
+        let response = http.send (&request)?;
+        let json = decode (&response)?;
+        if response.status == 401 || response.status == 403 {
+            return Err (ShortenError::Unavailable (format!("YOURLS refused to sign in: {}", message (&json))));
+        }
+        Ok (json)
+    }
+}
+
+impl Provider for YourlsProvider {
+    fn id (&self) -> &str {
+        YOURLS
+    }
+
+    fn name (&self) -> &str {
+        "YOURLS"
+    }
+
+    fn requires_credentials (&self) -> bool {
+        self.setting ("auth").ok ().and_then (|mode| mode).map (|mode| mode != "none").unwrap_or (true)
+    }
+
+    fn is_configured (&self) -> bool {
+        self.endpoint ().is_ok () && self.auth ().is_ok ()
+    }
+
+    fn config_fields (&self) -> &'static [ConfigField] {
+        FIELDS
+    }
+
+    fn uses_secret (&self) -> bool {
+        true
+    }
+
+    fn shorten (&self, url: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, None, &LinkOptions::default (), http)
+    }
+
+    /// YOURLS calls them keywords.
+    fn supports_alias (&self) -> bool {
+        true
+    }
+
+    fn shorten_with_alias (&self, url: &str, alias: &str, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        self.shorten_with_options (url, Some (alias), &LinkOptions::default (), http)
+    }
+
+    fn shorten_with_options (&self, url: &str, alias: Option<&str>, options: &LinkOptions, http: &dyn HttpClient) -> Result<String, ShortenError> {
+        let mut params = vec![("action", "shorturl"), ("url", url)];
+        if let Some (alias) = alias {
+            alias::YOURLS_RULE.check (alias)?;
+            params.push (("keyword", alias));
+        }
+        if let Some (ref title) = options.title {
+            params.push (("title", title.as_str ()));
+        }
+        alias::yourls_answer (&self.send (&params, http)?, alias)
+    }
+}
+
+fn md5_hex (text: &str) -> Result<String, ShortenError> {
+    let digest = hash (MessageDigest::md5 (), text.as_bytes ())
+        .map_err (|error| ShortenError::Unavailable (error.to_string ()))?;
+    Ok (digest.iter ().map (|byte| format!("{:02x}", byte)).collect ())
+}
+
+fn message (json: &Value) -> String {
+    text (&json["message"]).unwrap_or_else (|| "Unknown error".to_owned ())
+}
+
+/// YOURLS writes numbers as strings in some versions.
+fn number (value: &Value) -> Option<u64> {
+    value.as_u64 ().or_else (|| value.as_str ().and_then (|text| text.trim ().parse ().ok ()))
+}
+
+fn text (value: &Value) -> Option<String> {
+    value.as_str ().map (|text| text.to_owned ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::testing::{form, provider_config, FakeHttp, MemoryStore};
+
+    const API: &str = "https://sho.rt/yourls-api.php";
+
+    fn provider (auth: &str, secret: Option<&str>) -> YourlsProvider {
+        let store = match secret {
+            Some (secret) => MemoryStore::with (&[(YOURLS, secret)]),
+            None => MemoryStore::with (&[]),
+        };
+        YourlsProvider::new (store, provider_config (&[(YOURLS, "url", "https://sho.rt/"), (YOURLS, "auth", auth), (YOURLS, "username", "me")]))
+    }
+
+    #[test]
+    fn signs_with_the_md5_of_timestamp_and_token () {
+        let http = FakeHttp::new ().answer (API, 200, r#"{"status":"success","shorturl":"https://sho.rt/abc"}"#);
+        let short_url = provider ("signature", Some ("s3cr3t")).shorten ("https://example.com/", &http).unwrap ();
+        assert_eq!(short_url, "https://sho.rt/abc");
+
+        let fields = form (&http.sent_one ());
+        let timestamp = &fields["timestamp"];
+        assert_eq!(fields["signature"], md5_hex (&format!("{}s3cr3t", timestamp)).unwrap ());
+        assert_eq!(fields["signature"].len (), 32);
+        assert!(!fields.contains_key ("password"));
+        assert!(!http.sent_one ().body.unwrap ().contains ("s3cr3t"));
+        assert_eq!(fields["action"], "shorturl");
+        assert_eq!(fields["format"], "json");
+    }
+
+    #[test]
+    fn md5_is_written_in_lowercase_hex () {
+        assert_eq!(md5_hex ("").unwrap (), "d41d8cd98f00b204e9800998ecf8427e");
+    }
+
+    #[test]
+    fn signs_in_with_a_password_or_not_at_all () {
+        let http = FakeHttp::new ().answer (API, 200, r#"{"status":"success","shorturl":"https://sho.rt/abc"}"#);
+        provider ("password", Some ("hunter2")).shorten ("https://example.com/", &http).unwrap ();
+        let fields = form (&http.sent_one ());
+        assert_eq!((fields["username"].as_str (), fields["password"].as_str ()), ("me", "hunter2"));
+        assert!(!fields.contains_key ("signature"));
+
+        let http = FakeHttp::new ().answer (API, 200, r#"{"status":"success","shorturl":"https://sho.rt/abc"}"#);
+        let public = provider ("none", None);
+        assert!(!public.requires_credentials ());
+        public.shorten ("https://example.com/", &http).unwrap ();
+        let fields = form (&http.sent_one ());
+        assert!(!fields.contains_key ("signature") && !fields.contains_key ("password"));
+    }
+
+    #[test]
+    fn a_missing_token_is_reported_before_sending () {
+        let http = FakeHttp::new ();
+        let provider = provider ("signature", None);
+        assert!(!provider.is_configured ());
+        assert_eq!(provider.shorten ("https://example.com/", &http), Err (ShortenError::MissingCredentials (YOURLS.to_owned ())));
+        assert!(http.sent ().is_empty ());
+    }
+
+    #[test]
+    fn a_refused_signature_is_reported () {
+        let http = FakeHttp::new ().answer (API, 403, r#"{"errorCode":403,"message":"Please log in"}"#);
+        assert_eq!(provider ("signature", Some ("old")).shorten ("https://example.com/", &http),
+                   Err (ShortenError::Unavailable ("YOURLS refused to sign in: Please log in".to_owned ())));
+    }
+
+    #[test]
+    fn a_taken_keyword_is_reported () {
+        let http = FakeHttp::new ().answer (API, 200, r#"{"status":"fail","code":"error:keyword","message":"Short URL mine already exists"}"#);
+        assert_eq!(provider ("signature", Some ("s3cr3t")).shorten_with_alias ("https://example.com/", "mine", &http),
+                   Err (ShortenError::AliasTaken ("mine".to_owned ())));
+        assert_eq!(form (&http.sent_one ())["keyword"], "mine");
+    }
+
+    #[test]
+    fn reads_statistics () {
+        let http = FakeHttp::new ().answer (API, 200, r#"{"statusCode":200,"db-stats":{"total_links":"12","total_clicks":345}}"#);
+        assert_eq!(provider ("signature", Some ("s3cr3t")).db_stats (&http), Ok (YourlsStats { links: 12, clicks: 345 }));
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt::Write;
+
+use super::QrCode;
+
+/// A colour written as `#rrggbb`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb (pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb (0, 0, 0);
+    pub const WHITE: Rgb = Rgb (255, 255, 255);
+
+    pub fn parse (hex: &str) -> Option<Rgb> {
+        let hex = hex.trim ().trim_start_matches ('#');
+        if hex.len () != 6 || !hex.is_ascii () {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix (&hex[i..i + 2], 16).ok ();
+        Some (Rgb (channel (0)?, channel (2)?, channel (4)?))
+    }
+
+    pub fn to_hex (&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QrStyle {
+    /// Light modules around the code. Readers expect 4.
+    pub margin: usize,
+    pub foreground: Rgb,
+    pub background: Rgb,
+}
+
+impl Default for QrStyle {
+    fn default () -> QrStyle {
+        QrStyle {
+            margin: 4,
+            foreground: Rgb::BLACK,
+            background: Rgb::WHITE,
+        }
+    }
+}
+
+impl QrCode {
+    /// Modules per side, quiet zone included.
+    pub fn width (&self, style: &QrStyle) -> usize {
+        self.size () + style.margin * 2
+    }
+
+    /// An SVG drawing with one path for all the dark modules, one user unit
+    /// per module.
+    pub fn to_svg (&self, style: &QrStyle) -> String {
+        let width = self.width (style);
+        let mut path = String::new ();
+        for y in 0..self.size () {
+            for x in 0..self.size () {
+                if self.is_dark (x, y) {
+                    let _ = write!(path, "M{},{}h1v1h-1z", x + style.margin, y + style.margin);
+                }
+            }
+        }
+
+        format!(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" viewBox=\"0 0 {0} {0}\" shape-rendering=\"crispEdges\">\n",
+            "<rect width=\"100%\" height=\"100%\" fill=\"{1}\"/>\n",
+            "<path d=\"{2}\" fill=\"{3}\"/>\n",
+            "</svg>\n"),
+            width, style.background.to_hex (), path, style.foreground.to_hex ())
+    }
+
+    /// Packed RGB rows, `scale` pixels per module. Returns the side in
+    /// pixels with the pixels.
+    pub fn to_rgb (&self, style: &QrStyle, scale: usize) -> (usize, Vec<u8>) {
+        let scale = scale.max (1);
+        let side = self.width (style) * scale;
+        let mut pixels = Vec::with_capacity (side * side * 3);
+        for py in 0..side {
+            for px in 0..side {
+                let colour = if self.is_dark_with_margin (px / scale, py / scale, style) {
+                    style.foreground
+                } else {
+                    style.background
+                };
+                pixels.extend_from_slice (&[colour.0, colour.1, colour.2]);
+            }
+        }
+        (side, pixels)
+    }
+
+    /// A two colour palette PNG, `scale` pixels per module.
+    pub fn to_png (&self, style: &QrStyle, scale: usize) -> Vec<u8> {
+        let scale = scale.max (1);
+        let side = self.width (style) * scale;
+
+        // One bit per pixel, every row prefixed with filter type 0.
+        let row_bytes = (side + 7) / 8;
+        let mut raw = Vec::with_capacity ((row_bytes + 1) * side);
+        for py in 0..side {
+            raw.push (0);
+            let mut row = vec![0u8; row_bytes];
+            for px in 0..side {
+                if self.is_dark_with_margin (px / scale, py / scale, style) {
+                    row[px / 8] |= 0x80 >> (px % 8);
+                }
+            }
+            raw.extend (row);
+        }
+
+        let mut header = Vec::with_capacity (13);
+        header.extend_from_slice (&(side as u32).to_be_bytes ());
+        header.extend_from_slice (&(side as u32).to_be_bytes ());
+        // Bit depth 1, indexed colour, deflate, no filter, no interlace.
+        header.extend_from_slice (&[1, 3, 0, 0, 0]);
+
+        let palette = [
+            style.background.0, style.background.1, style.background.2,
+            style.foreground.0, style.foreground.1, style.foreground.2,
+        ];
+
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        png_chunk (&mut png, b"IHDR", &header);
+        png_chunk (&mut png, b"PLTE", &palette);
+        png_chunk (&mut png, b"IDAT", &zlib_stored (&raw));
+        png_chunk (&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn is_dark_with_margin (&self, x: usize, y: usize, style: &QrStyle) -> bool {
+        x >= style.margin && y >= style.margin && self.is_dark (x - style.margin, y - style.margin)
+    }
+}
+
+fn png_chunk (png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice (&(data.len () as u32).to_be_bytes ());
+    let start = png.len ();
+    png.extend_from_slice (kind);
+    png.extend_from_slice (data);
+    let crc = crc32 (&png[start..]);
+    png.extend_from_slice (&crc.to_be_bytes ());
+}
+
+/// A zlib stream of uncompressed deflate blocks. The images are two
+/// colours and small, so compressing is not worth the code.
+fn zlib_stored (data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks (MAX_BLOCK).peekable ();
+    if blocks.peek ().is_none () {
+        stream.extend_from_slice (&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some (block) = blocks.next () {
+        let last = blocks.peek ().is_none ();
+        let length = block.len () as u16;
+        stream.push (if last { 1 } else { 0 });
+        stream.extend_from_slice (&length.to_le_bytes ());
+        stream.extend_from_slice (&(!length).to_le_bytes ());
+        stream.extend_from_slice (block);
+    }
+    stream.extend_from_slice (&adler32 (data).to_be_bytes ());
+    stream
+}
+
+fn adler32 (data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from (byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+fn crc32 (data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from (byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
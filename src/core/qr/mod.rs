@@ -0,0 +1,523 @@
+//! QR code encoding (ISO/IEC 18004) of short links, in byte mode, and
+//! rendering to PNG and SVG.
+
+mod image;
+
+use std::error::Error;
+use std::fmt;
+
+pub use self::image::{QrStyle, Rgb};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorCorrection {
+    /// Recovers about 7% of the code.
+    Low,
+    /// About 15%.
+    Medium,
+    /// About 25%.
+    Quartile,
+    /// About 30%, for codes that get printed small or dirty.
+    High,
+}
+
+impl ErrorCorrection {
+    /// From the usual one letter names, `L`, `M`, `Q` and `H`.
+    pub fn from_letter (letter: &str) -> Option<ErrorCorrection> {
+        match letter.trim ().to_uppercase ().as_str () {
+            "L" => Some (ErrorCorrection::Low),
+            "M" => Some (ErrorCorrection::Medium),
+            "Q" => Some (ErrorCorrection::Quartile),
+            "H" => Some (ErrorCorrection::High),
+            _ => None,
+        }
+    }
+
+    pub fn letter (&self) -> &'static str {
+        match *self {
+            ErrorCorrection::Low => "L",
+            ErrorCorrection::Medium => "M",
+            ErrorCorrection::Quartile => "Q",
+            ErrorCorrection::High => "H",
+        }
+    }
+
+    fn ordinal (&self) -> usize {
+        match *self {
+            ErrorCorrection::Low => 0,
+            ErrorCorrection::Medium => 1,
+            ErrorCorrection::Quartile => 2,
+            ErrorCorrection::High => 3,
+        }
+    }
+
+    /// The two bits stored in the format information.
+    fn format_bits (&self) -> u32 {
+        match *self {
+            ErrorCorrection::Low => 1,
+            ErrorCorrection::Medium => 0,
+            ErrorCorrection::Quartile => 3,
+            ErrorCorrection::High => 2,
+        }
+    }
+}
+
+/// The text does not fit in a version 40 code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QrError (pub String);
+
+impl fmt::Display for QrError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for QrError {}
+
+const MIN_VERSION: usize = 1;
+const MAX_VERSION: usize = 40;
+
+/// Error correction codewords per block, by level then version.
+const ECC_CODEWORDS_PER_BLOCK: [[usize; 41]; 4] = [
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],
+    [0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+];
+
+/// Error correction blocks, by level then version.
+const ERROR_CORRECTION_BLOCKS: [[usize; 41]; 4] = [
+    [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+    [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+    [0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],
+    [0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],
+];
+
+/// Penalty weights used to pick the mask.
+const PENALTY_RUN: i32 = 3;
+const PENALTY_BLOCK: i32 = 3;
+const PENALTY_FINDER: i32 = 40;
+const PENALTY_BALANCE: i32 = 10;
+
+/// A square grid of dark and light modules.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QrCode {
+    version: usize,
+    size: usize,
+    error_correction: ErrorCorrection,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encodes `text` as UTF-8 bytes in the smallest version that fits.
+    pub fn encode (text: &str, error_correction: ErrorCorrection) -> Result<QrCode, QrError> {
+        let data = text.as_bytes ();
+
+        let version = (MIN_VERSION..MAX_VERSION + 1)
+            .find (|&version| 4 + count_bits (version) + data.len () * 8 <= data_codewords (version, error_correction) * 8)
+            .ok_or_else (|| QrError (format!("{} bytes are too long for a QR code", data.len ())))?;
+
+        let mut bits = BitBuffer (Vec::new ());
+        bits.push (0b0100, 4);
+        bits.push (data.len () as u32, count_bits (version));
+        for &byte in data {
+            bits.push (u32::from (byte), 8);
+        }
+
+        // Terminator, byte alignment, then alternating pad bytes.
+        let capacity = data_codewords (version, error_correction) * 8;
+        let terminator = (capacity - bits.0.len ()).min (4);
+        bits.push (0, terminator);
+        let alignment = (8 - bits.0.len () % 8) % 8;
+        bits.push (0, alignment);
+        for &pad in [0xEC, 0x11].iter ().cycle () {
+            if bits.0.len () >= capacity {
+                break;
+            }
+            bits.push (pad, 8);
+        }
+
+        let codewords: Vec<u8> = bits.0.chunks (8)
+            .map (|byte| byte.iter ().fold (0, |value, &bit| (value << 1) | bit as u8))
+            .collect ();
+
+        let size = version * 4 + 17;
+        let mut code = QrCode {
+            version,
+            size,
+            error_correction,
+            modules: vec![false; size * size],
+            is_function: vec![false; size * size],
+        };
+        code.draw_function_patterns ();
+        let all_codewords = code.add_error_correction (&codewords);
+        code.draw_codewords (&all_codewords);
+
+        let mut best_mask = 0;
+        let mut best_penalty = i32::max_value ();
+        for mask in 0..8 {
+            code.apply_mask (mask);
+            code.draw_format_bits (mask);
+            let penalty = code.penalty ();
+            if penalty < best_penalty {
+                best_mask = mask;
+                best_penalty = penalty;
+            }
+            code.apply_mask (mask);
+        }
+        code.apply_mask (best_mask);
+        code.draw_format_bits (best_mask);
+        Ok (code)
+    }
+
+    pub fn version (&self) -> usize {
+        self.version
+    }
+
+    pub fn error_correction (&self) -> ErrorCorrection {
+        self.error_correction
+    }
+
+    /// Modules per side, without the quiet zone.
+    pub fn size (&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at column `x`, row `y` is dark. Outside the code
+    /// everything is light.
+    pub fn is_dark (&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y * self.size + x]
+    }
+
+    fn set_function (&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns (&mut self) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function (6, i, i % 2 == 0);
+            self.set_function (i, 6, i % 2 == 0);
+        }
+
+        self.draw_finder (3, 3);
+        self.draw_finder (size - 4, 3);
+        self.draw_finder (3, size - 4);
+
+        let positions = alignment_positions (self.version);
+        let last = positions.len ().saturating_sub (1);
+        for (i, &x) in positions.iter ().enumerate () {
+            for (j, &y) in positions.iter ().enumerate () {
+                // The finder patterns already sit in three of the corners.
+                let corner = (i == 0 && j == 0) || (i == 0 && j == last) || (i == last && j == 0);
+                if !corner {
+                    self.draw_alignment (x, y);
+                }
+            }
+        }
+
+        // Reserves the format areas, the real bits come with the mask.
+        self.draw_format_bits (0);
+        self.draw_version ();
+    }
+
+    fn draw_finder (&mut self, x: usize, y: usize) {
+        for dy in -4i32..5 {
+            for dx in -4i32..5 {
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if xx >= 0 && yy >= 0 && (xx as usize) < self.size && (yy as usize) < self.size {
+                    let distance = dx.abs ().max (dy.abs ());
+                    self.set_function (xx as usize, yy as usize, distance != 2 && distance != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment (&mut self, x: usize, y: usize) {
+        for dy in -2i32..3 {
+            for dx in -2i32..3 {
+                let distance = dx.abs ().max (dy.abs ());
+                self.set_function ((x as i32 + dx) as usize, (y as i32 + dy) as usize, distance != 1);
+            }
+        }
+    }
+
+    fn draw_format_bits (&mut self, mask: u32) {
+        let data = self.error_correction.format_bits () << 3 | mask;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: u32| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        // Around the top left finder.
+        for i in 0..6 {
+            self.set_function (8, i, bit (i as u32));
+        }
+        self.set_function (8, 7, bit (6));
+        self.set_function (8, 8, bit (7));
+        self.set_function (7, 8, bit (8));
+        for i in 9..15 {
+            self.set_function (14 - i, 8, bit (i as u32));
+        }
+
+        // Split between the other two finders.
+        for i in 0..8 {
+            self.set_function (size - 1 - i, 8, bit (i as u32));
+        }
+        for i in 8..15 {
+            self.set_function (8, size - 15 + i, bit (i as u32));
+        }
+        self.set_function (8, size - 8, true);
+    }
+
+    fn draw_version (&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let mut remainder = self.version as u32;
+        for _ in 0..12 {
+            remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+        }
+        let bits = (self.version as u32) << 12 | remainder;
+
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set_function (a, b, dark);
+            self.set_function (b, a, dark);
+        }
+    }
+
+    /// Splits `data` into blocks, appends their Reed-Solomon codewords and
+    /// interleaves the result.
+    fn add_error_correction (&self, data: &[u8]) -> Vec<u8> {
+        let level = self.error_correction.ordinal ();
+        let blocks_count = ERROR_CORRECTION_BLOCKS[level][self.version];
+        let block_ecc = ECC_CODEWORDS_PER_BLOCK[level][self.version];
+        let raw_codewords = raw_data_modules (self.version) / 8;
+        let short_blocks = blocks_count - raw_codewords % blocks_count;
+        let short_block_len = raw_codewords / blocks_count;
+
+        let divisor = reed_solomon_divisor (block_ecc);
+        let mut blocks = Vec::new ();
+        let mut offset = 0;
+        for i in 0..blocks_count {
+            let length = short_block_len - block_ecc + if i < short_blocks { 0 } else { 1 };
+            let mut block = data[offset..offset + length].to_vec ();
+            offset += length;
+            let ecc = reed_solomon_remainder (&block, &divisor);
+            // Short blocks get a placeholder so all blocks line up.
+            if i < short_blocks {
+                block.push (0);
+            }
+            block.extend (ecc);
+            blocks.push (block);
+        }
+
+        let mut result = Vec::with_capacity (raw_codewords);
+        for i in 0..blocks[0].len () {
+            for (j, block) in blocks.iter ().enumerate () {
+                if i != short_block_len - block_ecc || j >= short_blocks {
+                    result.push (block[i]);
+                }
+            }
+        }
+        result
+    }
+
+    /// Fills the non-function modules in the zigzag order, two columns at a
+    /// time from the right.
+    fn draw_codewords (&mut self, data: &[u8]) {
+        let size = self.size as i32;
+        let mut bit = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vertical } else { vertical } as usize;
+                    if !self.is_function[y * self.size + x] && bit < data.len () * 8 {
+                        self.modules[y * self.size + x] = (data[bit >> 3] >> (7 - (bit & 7))) & 1 != 0;
+                        bit += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// XORs `mask` over the data modules, applying it twice undoes it.
+    fn apply_mask (&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if invert && !self.is_function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    fn penalty (&self) -> i32 {
+        let size = self.size;
+        let mut penalty = 0;
+
+        let rows: Vec<Vec<bool>> = (0..size).map (|y| (0..size).map (|x| self.is_dark (x, y)).collect ()).collect ();
+        let columns: Vec<Vec<bool>> = (0..size).map (|x| (0..size).map (|y| self.is_dark (x, y)).collect ()).collect ();
+        for line in rows.iter ().chain (&columns) {
+            penalty += line_penalty (line);
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.is_dark (x, y);
+                if dark == self.is_dark (x + 1, y) && dark == self.is_dark (x, y + 1) && dark == self.is_dark (x + 1, y + 1) {
+                    penalty += PENALTY_BLOCK;
+                }
+            }
+        }
+
+        let total = (size * size) as i32;
+        let dark = self.modules.iter ().filter (|&&dark| dark).count () as i32;
+        let deviation = ((dark * 20 - total * 10).abs () + total - 1) / total - 1;
+        penalty + deviation * PENALTY_BALANCE
+    }
+}
+
+/// Runs of five or more equal modules, and 1:1:3:1:1 patterns next to four
+/// light modules, which look like finders to a reader.
+fn line_penalty (line: &[bool]) -> i32 {
+    let mut penalty = 0;
+    let mut run = 1;
+    for i in 1..line.len () + 1 {
+        if i < line.len () && line[i] == line[i - 1] {
+            run += 1;
+            continue;
+        }
+        if run >= 5 {
+            penalty += PENALTY_RUN + (run - 5);
+        }
+        run = 1;
+    }
+
+    const FINDER: [bool; 7] = [true, false, true, true, true, false, true];
+    for start in 0..line.len ().saturating_sub (6) {
+        if line[start..start + 7] != FINDER {
+            continue;
+        }
+        let light_before = start >= 4 && line[start - 4..start].iter ().all (|&dark| !dark);
+        let light_after = start + 11 <= line.len () && line[start + 7..start + 11].iter ().all (|&dark| !dark);
+        if light_before || light_after {
+            penalty += PENALTY_FINDER;
+        }
+    }
+    penalty
+}
+
+struct BitBuffer (Vec<bool>);
+
+impl BitBuffer {
+    fn push (&mut self, value: u32, bits: usize) {
+        for i in (0..bits).rev () {
+            self.0.push ((value >> i) & 1 != 0);
+        }
+    }
+}
+
+/// Width of the character count field in byte mode.
+fn count_bits (version: usize) -> usize {
+    if version < 10 { 8 } else { 16 }
+}
+
+/// Modules left for data and error correction once the function patterns
+/// are drawn.
+fn raw_data_modules (version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        result -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn data_codewords (version: usize, error_correction: ErrorCorrection) -> usize {
+    let level = error_correction.ordinal ();
+    raw_data_modules (version) / 8 - ECC_CODEWORDS_PER_BLOCK[level][version] * ERROR_CORRECTION_BLOCKS[level][version]
+}
+
+/// Centre coordinates of the alignment patterns, on both axes.
+fn alignment_positions (version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new ();
+    }
+    let count = version / 7 + 2;
+    let size = version * 4 + 17;
+    let step = if version == 32 { 26 } else { (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2 };
+
+    let mut positions: Vec<usize> = (0..count - 1).map (|i| size - 7 - i * step).collect ();
+    positions.push (6);
+    positions.reverse ();
+    positions
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_multiply (x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev () {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+/// Generator polynomial of the given degree, highest coefficient first and
+/// without the leading 1.
+fn reed_solomon_divisor (degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree - 1];
+    result.push (1);
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply (result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply (root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder (data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len ()];
+    for &byte in data {
+        let factor = byte ^ result.remove (0);
+        result.push (0);
+        for (value, &coefficient) in result.iter_mut ().zip (divisor) {
+            *value ^= gf_multiply (coefficient, factor);
+        }
+    }
+    result
+}
@@ -0,0 +1,160 @@
+//! Redirectors and safe-link wrappers (Google results, Outlook SafeLinks,
+//! Facebook, Slack, ...) carry their destination in the query string, so it
+//! can be read without any request.
+
+use reqwest::Url;
+
+use super::normalize;
+
+/// Wrappers inside wrappers are rare, more than this is likely a loop.
+const MAX_DEPTH: usize = 5;
+
+struct Redirector {
+    name: &'static str,
+    /// Whether the host belongs to the redirector.
+    host: fn (&str) -> bool,
+    /// Path of the redirect endpoint, `None` for any path.
+    path: Option<&'static str>,
+    /// Query parameters which may hold the destination, first match wins.
+    params: &'static [&'static str],
+}
+
+const REDIRECTORS: &[Redirector] = &[
+    Redirector { name: "Google search", host: is_google, path: Some ("/url"), params: &["q", "url"] },
+    Redirector { name: "Outlook Safe Links", host: is_outlook_safelinks, path: None, params: &["url"] },
+    Redirector { name: "Facebook", host: is_facebook, path: Some ("/l.php"), params: &["u"] },
+    Redirector { name: "Instagram", host: is_instagram, path: None, params: &["u"] },
+    Redirector { name: "Slack", host: is_slack, path: Some ("/link"), params: &["url"] },
+    Redirector { name: "YouTube", host: is_youtube, path: Some ("/redirect"), params: &["q"] },
+    Redirector { name: "Steam", host: is_steam, path: Some ("/linkfilter/"), params: &["url", "u"] },
+];
+
+/// The destination hidden behind one or more redirectors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unwrapped {
+    /// The normalized destination.
+    pub url: String,
+    /// Names of the redirectors that were removed, outermost first.
+    pub redirectors: Vec<&'static str>,
+}
+
+impl Unwrapped {
+    /// "Google search" or "Outlook Safe Links and Google search".
+    pub fn description (&self) -> String {
+        match self.redirectors.split_last () {
+            Some ((last, [])) => last.to_string (),
+            Some ((last, rest)) => format!("{} and {}", rest.join (", "), last),
+            None => String::new (),
+        }
+    }
+}
+
+/// The destination of `url` when it is a known redirector link, `None`
+/// for any other URL. Nothing is fetched.
+pub fn unwrap_redirector (url: &str) -> Option<Unwrapped> {
+    let mut url = normalize (url).ok ()?;
+    let mut redirectors = Vec::new ();
+
+    while redirectors.len () < MAX_DEPTH {
+        match destination (&url) {
+            Some ((name, target)) => {
+                redirectors.push (name);
+                url = target;
+            },
+            None => break,
+        }
+    }
+
+    if redirectors.is_empty () {
+        None
+    } else {
+        Some (Unwrapped { url, redirectors })
+    }
+}
+
+fn destination (url: &str) -> Option<(&'static str, String)> {
+    let parsed = Url::parse (url).ok ()?;
+    let host = parsed.host_str ()?.to_owned ();
+
+    let redirector = REDIRECTORS.iter ().find (|redirector| {
+        (redirector.host) (&host) && redirector.path.map (|path| parsed.path () == path).unwrap_or (true)
+    })?;
+
+    let target = redirector.params.iter ()
+        .filter_map (|param| parsed.query_pairs ().find (|&(ref name, _)| name == *param))
+        .map (|(_, value)| value.into_owned ())
+        .next ()?;
+    // A search term rather than a link does not normalize.
+    let target = normalize (&target).ok ()?;
+    Some ((redirector.name, target))
+}
+
+/// `host` is `domain` or one of its subdomains.
+fn is_domain (host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with (&format!(".{}", domain))
+}
+
+/// google.com, www.google.co.uk, google.de, ...
+fn is_google (host: &str) -> bool {
+    let host = host.trim_start_matches ("www.");
+    host.starts_with ("google.") && host.split ('.').count () <= 3
+}
+
+fn is_outlook_safelinks (host: &str) -> bool {
+    is_domain (host, "safelinks.protection.outlook.com")
+}
+
+fn is_facebook (host: &str) -> bool {
+    is_domain (host, "facebook.com")
+}
+
+fn is_instagram (host: &str) -> bool {
+    host == "l.instagram.com"
+}
+
+fn is_slack (host: &str) -> bool {
+    host == "slack-redir.net"
+}
+
+fn is_youtube (host: &str) -> bool {
+    is_domain (host, "youtube.com")
+}
+
+fn is_steam (host: &str) -> bool {
+    host == "steamcommunity.com"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_destination_of_a_google_result () {
+        let unwrapped = unwrap_redirector ("https://www.google.com/url?sa=t&q=https://example.com/page%3Fa%3D1&usg=x").unwrap ();
+
+        assert_eq!(unwrapped.url, "https://example.com/page?a=1");
+        assert_eq!(unwrapped.description (), "Google search");
+    }
+
+    #[test]
+    fn unwraps_nested_redirectors_outermost_first () {
+        let url = "https://eur01.safelinks.protection.outlook.com/?url=https%3A%2F%2Fl.facebook.com%2Fl.php%3Fu%3Dhttps%253A%252F%252Fexample.com%252F&data=1";
+        let unwrapped = unwrap_redirector (url).unwrap ();
+
+        assert_eq!(unwrapped.url, "https://example.com/");
+        assert_eq!(unwrapped.redirectors, vec!["Outlook Safe Links", "Facebook"]);
+        assert_eq!(unwrapped.description (), "Outlook Safe Links and Facebook");
+    }
+
+    #[test]
+    fn ignores_other_paths_and_hosts () {
+        assert_eq!(unwrap_redirector ("https://www.google.com/search?q=https://example.com/"), None);
+        assert_eq!(unwrap_redirector ("https://notgoogle.com/url?q=https://example.com/"), None);
+        assert_eq!(unwrap_redirector ("https://example.com/?url=https://other.test/"), None);
+    }
+
+    #[test]
+    fn ignores_search_terms () {
+        assert_eq!(unwrap_redirector ("https://www.youtube.com/redirect?q=cats"), None);
+    }
+}
@@ -0,0 +1,373 @@
+use std::sync::{Arc, RwLock};
+
+use super::{normalize_with, retired_reason, CancelFlag, Clipboard, HttpClient, LinkOptions, NormalizeOptions, Provider, ShortenError};
+use super::clean::{Change, Cleaner};
+use super::history::History;
+
+/// Id of the picker entry which walks the user's ordered list of providers
+/// instead of using a single one.
+pub const AUTOMATIC: &str = "automatic";
+
+/// What to shorten and with which provider.
+#[derive(Clone, Debug)]
+pub struct ShortenRequest {
+    /// URL as typed by the user, it gets normalized before being sent.
+    pub url: String,
+    /// How `url` is normalized.
+    pub normalize: NormalizeOptions,
+    /// Strips tracking parameters from the normalized URL.
+    pub cleaner: Option<Cleaner>,
+    /// Id of the provider, see `Provider::id`.
+    pub provider: String,
+    /// Providers tried in order when `provider` fails.
+    pub fallbacks: Vec<String>,
+    /// Custom ending of the short URL, only providers with
+    /// `Provider::supports_alias` accept one.
+    pub alias: Option<String>,
+    /// Title and the like, for the providers which take them.
+    pub options: LinkOptions,
+    /// Copy the short URL through the `Shortener`'s clipboard back-end.
+    pub copy_to_clipboard: bool,
+    /// Checked before each provider of the chain.
+    pub cancel: CancelFlag,
+}
+
+impl ShortenRequest {
+    pub fn new<U: Into<String>, P: Into<String>> (url: U, provider: P) -> ShortenRequest {
+        ShortenRequest {
+            url: url.into (),
+            normalize: NormalizeOptions::default (),
+            cleaner: None,
+            provider: provider.into (),
+            fallbacks: Vec::new (),
+            alias: None,
+            options: LinkOptions::default (),
+            copy_to_clipboard: false,
+            cancel: CancelFlag::new (),
+        }
+    }
+
+    /// Tries the providers of `chain` in order.
+    pub fn with_chain<U: Into<String>> (url: U, chain: &[String]) -> ShortenRequest {
+        let mut request = ShortenRequest::new (url, chain.first ().cloned ().unwrap_or_default ());
+        request.fallbacks = chain.iter ().skip (1).cloned ().collect ();
+        request
+    }
+}
+
+/// A successfully shortened URL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShortenOutcome {
+    /// The normalized and cleaned URL that was sent to the provider.
+    pub long_url: String,
+    /// What the cleaner removed from the URL.
+    pub removed: Vec<Change>,
+    pub short_url: String,
+    /// Id of the provider which produced `short_url`.
+    pub provider: String,
+    /// Providers that failed before `provider` succeeded.
+    pub failures: Vec<(String, ShortenError)>,
+    /// Whether `short_url` made it to the clipboard.
+    pub copied: bool,
+}
+
+/// The provider id and domain of a picked provider. The pickers offer the
+/// branded domains of a provider as `<id>@<domain>`.
+pub fn picked_provider (picked: &str) -> (String, Option<String>) {
+    match picked.find ('@') {
+        Some (at) => (picked[..at].to_owned (), Some (picked[at + 1..].to_owned ())),
+        None => (picked.to_owned (), None),
+    }
+}
+
+/// Shortens URLs with a set of providers, an HTTP and a clipboard back-end.
+pub struct Shortener {
+    /// Replaced as a whole by `set_providers` while the shortener is shared,
+    /// readers get the list of the moment.
+    providers: RwLock<Vec<Arc<dyn Provider>>>,
+    http: Box<dyn HttpClient>,
+    clipboard: Box<dyn Clipboard>,
+    history: Option<Arc<History>>,
+}
+
+impl Shortener {
+    pub fn new (http: Box<dyn HttpClient>, clipboard: Box<dyn Clipboard>) -> Shortener {
+        Shortener {
+            providers: RwLock::new (Vec::new ()),
+            http,
+            clipboard,
+            history: None,
+        }
+    }
+
+    /// Records every call to `shorten` from now on.
+    pub fn set_history (&mut self, history: Arc<History>) {
+        self.history = Some (history);
+    }
+
+    pub fn history (&self) -> Option<&Arc<History>> {
+        self.history.as_ref ()
+    }
+
+    pub fn add_provider (&mut self, provider: Box<dyn Provider>) {
+        self.providers.get_mut ().unwrap ().push (Arc::from (provider));
+    }
+
+    /// Swaps every provider for `providers`, e.g. once custom providers got
+    /// imported. Requests already running finish with the old ones.
+    pub fn set_providers (&self, providers: Vec<Box<dyn Provider>>) {
+        *self.providers.write ().unwrap () = providers.into_iter ().map (Arc::from).collect ();
+    }
+
+    pub fn providers (&self) -> Vec<Arc<dyn Provider>> {
+        self.providers.read ().unwrap ().clone ()
+    }
+
+    pub fn provider (&self, id: &str) -> Option<Arc<dyn Provider>> {
+        self.providers.read ().unwrap ().iter ()
+            .find (|provider| provider.id () == id)
+            .cloned ()
+    }
+
+    /// The ids of `priority` which can be used right now: known, still in
+    /// service and with their token set.
+    pub fn usable_chain (&self, priority: &[String]) -> Vec<String> {
+        priority.iter ()
+            .filter (|id| retired_reason (id).is_none ())
+            .filter (|id| self.provider (id).map (|provider| provider.is_configured ()).unwrap_or (false))
+            .cloned ()
+            .collect ()
+    }
+
+    /// A request for `url` to the provider `picked` in a front-end, or to
+    /// `last_used` without one, as long as it still exists. Falls back to
+    /// `AUTOMATIC`, which tries the usable providers of `priority`. A
+    /// branded domain picked as `<id>@<domain>` goes to the options. `None`
+    /// when the automatic chain is empty.
+    pub fn request_for<U: Into<String>> (&self, url: U, picked: Option<&str>, last_used: Option<&str>, priority: &[String]) -> Option<ShortenRequest> {
+        let picked = picked
+            .or_else (|| last_used.filter (|last_used| self.provider (&picked_provider (last_used).0).is_some ()))
+            .unwrap_or (AUTOMATIC);
+        let (provider, domain) = picked_provider (picked);
+
+        if provider == AUTOMATIC {
+            let chain = self.usable_chain (priority);
+            if chain.is_empty () {
+                return None;
+            }
+            Some (ShortenRequest::with_chain (url, &chain))
+        } else {
+            let mut request = ShortenRequest::new (url, provider);
+            request.options.domain = domain;
+            Some (request)
+        }
+    }
+
+    pub fn http (&self) -> &dyn HttpClient {
+        &*self.http
+    }
+
+    /// Shortens with `request.provider`, then with each of
+    /// `request.fallbacks` until one succeeds.
+    pub fn shorten (&self, request: &ShortenRequest) -> Result<ShortenOutcome, ShortenError> {
+        let result = self.try_providers (request);
+
+        // The history is a convenience, failing to write it must not fail
+        // the shortening. Cancelling is not a failure worth keeping.
+        if let Some (ref history) = self.history {
+            let _ = match result {
+                Ok (ref outcome) => Some (history.record (&outcome.long_url, &outcome.short_url, &outcome.provider, None)),
+                Err (ShortenError::Cancelled) => None,
+                Err (ref error) => Some (history.record (request.url.trim (), "", &request.provider, Some (error.to_string ()))),
+            };
+        }
+        result
+    }
+
+    fn try_providers (&self, request: &ShortenRequest) -> Result<ShortenOutcome, ShortenError> {
+        let long_url = normalize_with (&request.url, &request.normalize, self.http ())?;
+        let (long_url, removed) = match request.cleaner {
+            Some (ref cleaner) => {
+                let cleaned = cleaner.clean (&long_url);
+                (cleaned.url, cleaned.changes)
+            },
+            None => (long_url, Vec::new ()),
+        };
+
+        let mut failures = Vec::new ();
+        for id in Some (&request.provider).into_iter ().chain (&request.fallbacks) {
+            if request.cancel.is_cancelled () {
+                return Err (ShortenError::Cancelled);
+            }
+            let result = self.provider (id)
+                .ok_or_else (|| ShortenError::UnknownProvider (id.clone ()))
+                .and_then (|provider| {
+                    let alias = request.alias.as_ref ().map (|alias| alias.as_str ());
+                    provider.shorten_with_options (&long_url, alias, &request.options, self.http ())
+                });
+
+            match result {
+                Ok (short_url) => {
+                    let copied = request.copy_to_clipboard && self.clipboard.set_text (&short_url).is_ok ();
+                    return Ok (ShortenOutcome {
+                        long_url,
+                        removed,
+                        short_url,
+                        provider: id.clone (),
+                        failures,
+                        copied,
+                    });
+                },
+                Err (error) => failures.push ((id.clone (), error)),
+            }
+        }
+
+        if failures.len () == 1 {
+            Err (failures.remove (0).1)
+        } else {
+            Err (ShortenError::AllFailed (failures))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use super::super::{builtin_providers, NoClipboard};
+    use super::super::clean::builtin_rules;
+    use super::super::testing::{provider_config, FakeHttp, MemoryStore};
+
+    fn shortener (http: &Arc<FakeHttp>) -> Shortener {
+        let mut shortener = Shortener::new (Box::new (http.clone ()), Box::new (NoClipboard));
+        for provider in builtin_providers (MemoryStore::with (&[]), provider_config (&[])) {
+            shortener.add_provider (provider);
+        }
+        shortener
+    }
+
+    fn chain (ids: &[&str]) -> Vec<String> {
+        ids.iter ().map (|id| id.to_string ()).collect ()
+    }
+
+    #[test]
+    fn sends_the_normalized_and_cleaned_url () {
+        let http = Arc::new (FakeHttp::new ().answer ("https://is.gd/create.php*", 200, "https://is.gd/abc\n"));
+        let mut request = ShortenRequest::new (" example.com/p?utm_source=x&id=1 ", "is.gd");
+        request.cleaner = Some (Cleaner::new (builtin_rules ()));
+        let outcome = shortener (&http).try_providers (&request).unwrap ();
+
+        assert_eq!(outcome.short_url, "https://is.gd/abc");
+        assert_eq!(outcome.long_url, "https://example.com/p?id=1");
+        assert_eq!(outcome.removed.len (), 1);
+        assert_eq!(http.sent_one ().url, "https://is.gd/create.php?format=simple&url=https%3A%2F%2Fexample.com%2Fp%3Fid%3D1");
+    }
+
+    #[test]
+    fn falls_back_along_the_chain () {
+        let http = Arc::new (FakeHttp::new ()
+            .fail ("https://is.gd/*", "timed out")
+            .answer ("https://v.gd/create.php*", 200, "https://v.gd/abc"));
+        let outcome = shortener (&http).try_providers (&ShortenRequest::with_chain ("https://example.com/", &chain (&["is.gd", "v.gd"]))).unwrap ();
+
+        assert_eq!(outcome.provider, "v.gd");
+        assert_eq!(outcome.short_url, "https://v.gd/abc");
+        assert_eq!(outcome.failures, vec![("is.gd".to_owned (), ShortenError::Unavailable ("timed out".to_owned ()))]);
+    }
+
+    #[test]
+    fn reports_every_failure_of_the_chain () {
+        let http = Arc::new (FakeHttp::new ()
+            .answer ("https://is.gd/*", 503, "")
+            .answer ("https://v.gd/*", 200, ""));
+        let request = ShortenRequest::with_chain ("https://example.com/", &chain (&["is.gd", "nowhere", "v.gd"]));
+
+        match shortener (&http).try_providers (&request) {
+            Err (ShortenError::AllFailed (failures)) => {
+                let ids: Vec<&str> = failures.iter ().map (|&(ref id, _)| id.as_str ()).collect ();
+                assert_eq!(ids, vec!["is.gd", "nowhere", "v.gd"]);
+                assert_eq!(failures[0].1, ShortenError::Unavailable ("is.gd answered HTTP 503".to_owned ()));
+                assert_eq!(failures[1].1, ShortenError::UnknownProvider ("nowhere".to_owned ()));
+                assert_eq!(failures[2].1, ShortenError::Decode ("No short URL in the answer of v.gd".to_owned ()));
+            },
+            other => panic!("expected every provider to fail, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_single_provider_fails_with_its_own_error () {
+        let http = Arc::new (FakeHttp::new ());
+        let result = shortener (&http).try_providers (&ShortenRequest::new ("https://example.com/", "goo.gl"));
+        assert_eq!(result, Err (ShortenError::MissingCredentials ("goo.gl".to_owned ())));
+        assert!(http.sent ().is_empty ());
+    }
+
+    #[test]
+    fn invalid_urls_are_not_sent () {
+        let http = Arc::new (FakeHttp::new ());
+        let result = shortener (&http).try_providers (&ShortenRequest::new ("not a url", "is.gd"));
+        assert_eq!(result, Err (ShortenError::InvalidUrl ("not a url".to_owned ())));
+        assert!(http.sent ().is_empty ());
+    }
+
+    #[test]
+    fn stops_before_the_next_provider_once_cancelled () {
+        let http = Arc::new (FakeHttp::new ());
+        let request = ShortenRequest::with_chain ("https://example.com/", &chain (&["is.gd", "v.gd"]));
+        request.cancel.cancel ();
+        assert_eq!(shortener (&http).try_providers (&request), Err (ShortenError::Cancelled));
+        assert!(http.sent ().is_empty ());
+    }
+
+    #[test]
+    fn usable_chain_skips_unknown_retired_and_unconfigured_providers () {
+        let http = Arc::new (FakeHttp::new ());
+        let usable = shortener (&http).usable_chain (&chain (&["goo.gl", "bitly.com", "nowhere", "v.gd", "is.gd"]));
+        assert_eq!(usable, chain (&["v.gd", "is.gd"]));
+    }
+
+    #[test]
+    fn providers_can_be_swapped_while_shared () {
+        let http = Arc::new (FakeHttp::new ());
+        let shortener = Arc::new (shortener (&http));
+        let providers = builtin_providers (MemoryStore::with (&[]), provider_config (&[])).into_iter ()
+            .filter (|provider| provider.id () != "v.gd")
+            .collect ();
+        shortener.set_providers (providers);
+
+        assert!(shortener.provider ("v.gd").is_none ());
+        assert!(shortener.provider ("is.gd").is_some ());
+        assert_eq!(shortener.usable_chain (&chain (&["v.gd", "is.gd"])), chain (&["is.gd"]));
+    }
+
+    #[test]
+    fn requests_go_to_the_picked_then_the_last_used_provider () {
+        let http = Arc::new (FakeHttp::new ());
+        let shortener = shortener (&http);
+        let priority = chain (&["bitly.com", "v.gd", "is.gd"]);
+
+        let request = shortener.request_for ("u", Some ("bitly.com@go.test"), Some ("v.gd"), &priority).unwrap ();
+        assert_eq!((request.provider.as_str (), request.options.domain), ("bitly.com", Some ("go.test".to_owned ())));
+        let request = shortener.request_for ("u", None, Some ("v.gd"), &priority).unwrap ();
+        assert_eq!((request.provider.as_str (), request.fallbacks.len ()), ("v.gd", 0));
+    }
+
+    #[test]
+    fn a_vanished_last_provider_falls_back_to_the_usable_chain () {
+        let http = Arc::new (FakeHttp::new ());
+        let shortener = shortener (&http);
+        let priority = chain (&["bitly.com", "v.gd", "is.gd"]);
+
+        let request = shortener.request_for ("u", None, Some ("gone.example"), &priority).unwrap ();
+        assert_eq!(request.provider, "v.gd");
+        assert_eq!(request.fallbacks, chain (&["is.gd"]));
+        assert!(shortener.request_for ("u", Some (AUTOMATIC), None, &chain (&["bitly.com"])).is_none ());
+    }
+
+    #[test]
+    fn picked_branded_domains_are_split_off () {
+        assert_eq!(picked_provider ("shlink@s.test"), ("shlink".to_owned (), Some ("s.test".to_owned ())));
+        assert_eq!(picked_provider ("is.gd"), ("is.gd".to_owned (), None));
+    }
+}
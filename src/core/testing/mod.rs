@@ -0,0 +1,186 @@
+//! Stand-ins for the HTTP and credential back-ends, so the core is tested
+//! without a network or a keyring.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use reqwest::Url;
+use serde_json::{self, Value};
+
+use super::config::ProviderConfig;
+use super::credentials::{CredentialError, CredentialStore};
+use super::{HttpClient, HttpError, HttpRequest, HttpResponse, Method};
+
+/// Answers requests from a list of canned responses and keeps what was
+/// sent. A route matches its URL exactly, or every URL it is a prefix of
+/// when it ends with `*`. The first matching route answers.
+#[derive(Default)]
+pub struct FakeHttp {
+    routes: Vec<(Option<Method>, String, Result<HttpResponse, HttpError>)>,
+    sent: Mutex<Vec<HttpRequest>>,
+}
+
+impl FakeHttp {
+    pub fn new () -> FakeHttp {
+        FakeHttp::default ()
+    }
+
+    /// Answers every method on `url` with `status` and `body`.
+    pub fn answer (self, url: &str, status: u16, body: &str) -> FakeHttp {
+        self.route (None, url, status, body)
+    }
+
+    /// Answers `method` only on `url`.
+    pub fn answer_to (self, method: Method, url: &str, status: u16, body: &str) -> FakeHttp {
+        self.route (Some (method), url, status, body)
+    }
+
+    /// Adds a header to the answer of the last route.
+    pub fn header (mut self, name: &str, value: &str) -> FakeHttp {
+        if let Some (&mut (_, _, Ok (ref mut response))) = self.routes.last_mut () {
+            response.headers.push ((name.to_owned (), value.to_owned ()));
+        }
+        self
+    }
+
+    /// `url` cannot be reached.
+    pub fn fail (mut self, url: &str, message: &str) -> FakeHttp {
+        self.routes.push ((None, url.to_owned (), Err (HttpError (message.to_owned ()))));
+        self
+    }
+
+    /// Every request, in the order they were sent.
+    pub fn sent (&self) -> Vec<HttpRequest> {
+        self.sent.lock ().unwrap ().clone ()
+    }
+
+    /// The only request sent, panics when there were others.
+    pub fn sent_one (&self) -> HttpRequest {
+        let sent = self.sent ();
+        assert_eq!(sent.len (), 1, "expected one request, got {:?}", sent);
+        sent[0].clone ()
+    }
+
+    fn route (mut self, method: Option<Method>, url: &str, status: u16, body: &str) -> FakeHttp {
+        let route_url = url.trim_end_matches ('*');
+        let response = HttpResponse {
+            url: route_url.to_owned (),
+            status,
+            headers: Vec::new (),
+            body: body.to_owned (),
+        };
+        self.routes.push ((method, url.to_owned (), Ok (response)));
+        self
+    }
+}
+
+impl HttpClient for FakeHttp {
+    fn send (&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        self.sent.lock ().unwrap ().push (request.clone ());
+        let found = self.routes.iter ().find (|&&(method, ref url, _)| {
+            let url_matches = if url.ends_with ('*') {
+                request.url.starts_with (url.trim_end_matches ('*'))
+            } else {
+                request.url == *url
+            };
+            url_matches && method.map (|method| method == request.method).unwrap_or (true)
+        });
+        match found {
+            Some (&(_, _, Ok (ref response))) => {
+                let mut response = response.clone ();
+                response.url = request.url.clone ();
+                Ok (response)
+            },
+            Some (&(_, _, Err (ref error))) => Err (error.clone ()),
+            None => Err (HttpError (format!("No answer for {:?} {}", request.method, request.url))),
+        }
+    }
+}
+
+/// The fields of a form sent in the body of `request`.
+pub fn form (request: &HttpRequest) -> HashMap<String, String> {
+    let body = request.body.as_ref ().map (|body| body.as_str ()).unwrap_or ("");
+    Url::parse (&format!("http://localhost/?{}", body)).unwrap ()
+        .query_pairs ()
+        .into_owned ()
+        .collect ()
+}
+
+/// The JSON sent in the body of `request`.
+pub fn json_body (request: &HttpRequest) -> Value {
+    serde_json::from_str (request.body.as_ref ().expect ("no body")).unwrap ()
+}
+
+/// First value of the header `name` of `request`.
+pub fn header (request: &HttpRequest, name: &str) -> Option<String> {
+    request.headers.iter ()
+        .find (|&&(ref key, _)| key.eq_ignore_ascii_case (name))
+        .map (|&(_, ref value)| value.clone ())
+}
+
+/// Lets a test keep the client it hands to a `Shortener`, to look at what
+/// was sent.
+impl<T: HttpClient> HttpClient for Arc<T> {
+    fn send (&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        (**self).send (request)
+    }
+}
+
+/// Secrets kept in memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl MemoryStore {
+    pub fn with (secrets: &[(&str, &str)]) -> Arc<MemoryStore> {
+        let store = MemoryStore::default ();
+        for &(provider, secret) in secrets {
+            store.secrets.lock ().unwrap ().insert (provider.to_owned (), secret.to_owned ());
+        }
+        Arc::new (store)
+    }
+}
+
+impl CredentialStore for MemoryStore {
+    fn get (&self, provider: &str) -> Result<Option<String>, CredentialError> {
+        Ok (self.secrets.lock ().unwrap ().get (provider).cloned ())
+    }
+
+    fn set (&self, provider: &str, secret: &str) -> Result<(), CredentialError> {
+        self.secrets.lock ().unwrap ().insert (provider.to_owned (), secret.to_owned ());
+        Ok (())
+    }
+
+    fn delete (&self, provider: &str) -> Result<(), CredentialError> {
+        self.secrets.lock ().unwrap ().remove (provider);
+        Ok (())
+    }
+
+    fn description (&self) -> String {
+        "memory".to_owned ()
+    }
+}
+
+/// A path in a directory of its own under the temporary directory, for
+/// tests running at the same time not to share files.
+pub fn temporary_path (name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new (0);
+    let dir = env::temp_dir ()
+        .join (format!("srtnr-test-{}-{}", ::std::process::id (), COUNTER.fetch_add (1, Ordering::SeqCst)));
+    fs::create_dir_all (&dir).unwrap ();
+    dir.join (name)
+}
+
+/// Provider settings holding `settings`, as `(provider, key, value)`.
+pub fn provider_config (settings: &[(&str, &str, &str)]) -> Arc<ProviderConfig> {
+    let config = ProviderConfig::with_path (temporary_path ("providers.json"));
+    for &(provider, key, value) in settings {
+        config.set (provider, key, value).unwrap ();
+    }
+    Arc::new (config)
+}
@@ -0,0 +1,16 @@
+//! Shortening logic shared by every srtnr front-end.
+//!
+//! Nothing in this library depends on GTK or on a display, so the GUI, the
+//! command line and scripts all go through the same code.
+
+extern crate glib_sys;
+extern crate libc;
+extern crate openssl;
+extern crate regex;
+extern crate reqwest;
+#[macro_use]
+extern crate serde_json;
+extern crate urlshortener;
+extern crate validator;
+
+pub mod core;
@@ -1,23 +1,51 @@
 extern crate gdk;
 extern crate gio;
+extern crate gio_sys;
 extern crate gtk;
+extern crate gtk_sys;
 extern crate glib;
+extern crate glib_sys;
+extern crate srtnr;
+#[macro_use]
+extern crate serde_json;
 
 use gio::prelude::*;
 use gtk::Application;
 use gio::{ApplicationExt, ApplicationFlags};    
 
 mod buildui;
+mod cli;
 
 fn main () {
+    let args = std::env::args ().collect::<Vec<_>> ();
+
+    // The command line runs before anything GTK related is created, so it
+    // works without a display or a session bus.
+    if cli::handles (&args) {
+        // Settings of an older release apply to the command line as well.
+        if let Some (settings) = cli::settings () {
+            for notice in buildui::migration::migrate (&settings) {
+                eprintln!("{}", notice);
+            }
+        }
+        std::process::exit (cli::run (&args));
+    }
+
     let app = Application::new ("com.github.arshubham.srtnr", ApplicationFlags::empty ())
         .expect ("Failed to create Application");
     
+    // Started by D-Bus (--gapplication-service) there is only a startup,
+    // the window comes with the first activation.
     ApplicationExt::connect_startup (&app, |app| {
-        buildui::ui (&app);
+        let mut shared = buildui::Shared::new (app);
+        // Shown in the window like the other startup problems, the search
+        // providers and scripts simply find no service then.
+        if let Err (error) = buildui::dbus::export (app, &shared) {
+            shared.warnings.push (format!("Could not export the D-Bus service, searching and shortening from other apps is off: {}", error));
+        }
+        ApplicationExt::connect_activate (app, move |app| buildui::ui (app, &shared));
     });
-    ApplicationExt::connect_activate (&app, |_| { });
 
     // Error
-    app.run (&std::env::args ().collect::<Vec<_>> ());
+    app.run (&args);
 }